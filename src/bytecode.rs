@@ -0,0 +1,996 @@
+use std::{cell::RefCell, fmt, io::Write, rc::Rc};
+
+use crate::{
+    pratt::{E, S},
+    resolver::{self, RuntimeError, RuntimeErrorKind, MAX_CALL_DEPTH},
+    span::Span,
+    tokenizer::OP,
+};
+
+/// The instructions of the stack machine. Each pops its operands from the
+/// stack and pushes its result.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Op {
+    Int(i32),
+    Bool(bool),
+    Unit,
+    /// Pushes the local in slot `n` of the frame, counted from its first
+    /// parameter
+    Load(usize),
+    /// Pushes the captured variable `n` of the running closure
+    Capture(usize),
+    /// Pushes the running closure, which `let rec` names in its own body
+    This,
+    /// Puts the value on top in a new cell, for a `var`
+    Cell,
+    /// Replaces the cell on top with its value
+    Get,
+    /// Pops a cell and the value below it, which it stores in the cell
+    Set,
+    Pop,
+    /// Pops `n` values
+    PopN(usize),
+    /// Pops `n` values below the one on top, the locals of a scope ending
+    /// in a value
+    Slide(usize),
+    Neg,
+    Pos,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Eq,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    Jump(usize),
+    /// Pops a bool and jumps if it is `false`
+    JumpIfFalse(usize),
+    /// Pushes a closure of function `n`, capturing its variables from the
+    /// running frame
+    Closure(usize),
+    /// Names the function on top after the `let rec` binding it
+    Rec(String),
+    /// Checks that the value on top can be called with `n` arguments,
+    /// before they are evaluated
+    CheckCall(usize),
+    /// Calls the function below the `n` arguments on top
+    Call(usize),
+    Return,
+    Print,
+    /// Fails with an error found while compiling, when it is reached
+    Raise(RuntimeErrorKind),
+}
+
+impl Op {
+    /// The number of values the instruction adds to the stack, negative
+    /// for those it removes
+    fn effect(&self) -> isize {
+        match self {
+            Op::Int(_)
+            | Op::Bool(_)
+            | Op::Unit
+            | Op::Load(_)
+            | Op::Capture(_)
+            | Op::This
+            | Op::Closure(_)
+            // stands for the value of the expression that fails
+            | Op::Raise(_) => 1,
+            Op::Cell | Op::Get | Op::Neg | Op::Pos | Op::Jump(_) | Op::Rec(_) | Op::CheckCall(_) => 0,
+            Op::Set => -2,
+            Op::Pop | Op::JumpIfFalse(_) | Op::Return | Op::Print => -1,
+            Op::PopN(n) | Op::Slide(n) | Op::Call(n) => -(*n as isize),
+            Op::Add
+            | Op::Sub
+            | Op::Mul
+            | Op::Div
+            | Op::Pow
+            | Op::Eq
+            | Op::Gt
+            | Op::Lt
+            | Op::Ge
+            | Op::Le => -1,
+        }
+    }
+
+    /// The operator of a binary instruction
+    fn operator(&self) -> Option<OP> {
+        match self {
+            Op::Add => Some(OP::PLUS),
+            Op::Sub => Some(OP::MINUS),
+            Op::Mul => Some(OP::MULT),
+            Op::Div => Some(OP::DIV),
+            Op::Pow => Some(OP::POW),
+            Op::Eq => Some(OP::EQUALITY),
+            Op::Gt => Some(OP::GREATER),
+            Op::Lt => Some(OP::LESS),
+            Op::Ge => Some(OP::GEQ),
+            Op::Le => Some(OP::LEQ),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Int(v) => write!(f, "int {}", v),
+            Op::Bool(b) => write!(f, "bool {}", b),
+            Op::Unit => write!(f, "unit"),
+            Op::Load(n) => write!(f, "load {}", n),
+            Op::Capture(n) => write!(f, "capture {}", n),
+            Op::This => write!(f, "this"),
+            Op::Cell => write!(f, "cell"),
+            Op::Get => write!(f, "get"),
+            Op::Set => write!(f, "set"),
+            Op::Pop => write!(f, "pop"),
+            Op::PopN(n) => write!(f, "pop {}", n),
+            Op::Slide(n) => write!(f, "slide {}", n),
+            Op::Neg => write!(f, "neg"),
+            Op::Pos => write!(f, "pos"),
+            Op::Add => write!(f, "add"),
+            Op::Sub => write!(f, "sub"),
+            Op::Mul => write!(f, "mul"),
+            Op::Div => write!(f, "div"),
+            Op::Pow => write!(f, "pow"),
+            Op::Eq => write!(f, "eq"),
+            Op::Gt => write!(f, "gt"),
+            Op::Lt => write!(f, "lt"),
+            Op::Ge => write!(f, "ge"),
+            Op::Le => write!(f, "le"),
+            Op::Jump(target) => write!(f, "jump {}", target),
+            Op::JumpIfFalse(target) => write!(f, "jump-if-false {}", target),
+            Op::Closure(n) => write!(f, "closure #{}", n),
+            Op::Rec(name) => write!(f, "rec {}", name),
+            Op::CheckCall(n) => write!(f, "check-call {}", n),
+            Op::Call(n) => write!(f, "call {}", n),
+            Op::Return => write!(f, "return"),
+            Op::Print => write!(f, "print"),
+            Op::Raise(kind) => {
+                let e = RuntimeError::new(kind.clone(), Span::default());
+                write!(f, "raise \"{}\"", e)
+            }
+        }
+    }
+}
+
+/// Where an instruction was compiled from, for its errors: the span of the
+/// node and of the operands that can be blamed
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+struct Site {
+    span: Span,
+    left: Span,
+    right: Span,
+}
+
+impl Site {
+    fn new(span: Span) -> Site {
+        Site {
+            span,
+            left: span,
+            right: span,
+        }
+    }
+}
+
+/// How a closure gets a variable it captures, from the frame creating it
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Capture {
+    Local(usize),
+    /// A variable the creating closure captured itself
+    Outer(usize),
+    /// The creating closure, named by `let rec`
+    Itself,
+}
+
+#[derive(Debug)]
+struct Function {
+    /// The name given by `let rec`
+    name: Option<String>,
+    params: Vec<String>,
+    captures: Vec<(String, Capture)>,
+    code: Vec<Op>,
+    sites: Vec<Site>,
+    /// Where the function was defined
+    span: Span,
+}
+
+/// A compiled program, function `#0` is the top level
+#[derive(Debug)]
+pub struct Program {
+    functions: Vec<Function>,
+}
+
+/// Lists the instructions of every function with the position they were
+/// compiled from
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, function) in self.functions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "fn #{}", i)?;
+            if let Some(name) = &function.name {
+                write!(f, " {}", name)?;
+            }
+            write!(f, "({})", function.params.join(", "))?;
+            if !function.captures.is_empty() {
+                let names: Vec<&str> = function.captures.iter().map(|(n, _)| n.as_str()).collect();
+                write!(f, " captures {}", names.join(", "))?;
+            }
+            writeln!(f)?;
+            for (ip, (op, site)) in function.code.iter().zip(&function.sites).enumerate() {
+                let position = format!("{}:{}", site.span.line, site.span.col);
+                writeln!(f, "{:>4}  {:<6}{}", ip, position, op)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Local {
+    name: String,
+    slot: usize,
+    /// A `var`, whose slot holds a cell
+    mutable: bool,
+}
+
+/// A loop being compiled
+struct Loop {
+    start: usize,
+    /// The height of the stack around the loop
+    height: usize,
+    /// `break` jumps to patch with the end of the loop
+    breaks: Vec<usize>,
+}
+
+/// A function being compiled
+struct Scope {
+    function: Function,
+    locals: Vec<Local>,
+    /// The number of values on the stack of the frame
+    height: usize,
+    loops: Vec<Loop>,
+}
+
+/// How a name is reached from the function using it
+#[derive(Clone, Copy)]
+enum Access {
+    Local(usize),
+    Capture(usize),
+    This,
+}
+
+struct Compiler {
+    functions: Vec<Option<Function>>,
+    /// The functions being compiled, innermost last
+    scopes: Vec<Scope>,
+}
+
+/// Compiles `program` to bytecode. Names are resolved while compiling, a
+/// name that is not in scope compiles to an error raised when it is used.
+pub fn compile(program: &[S]) -> Program {
+    let mut compiler = Compiler {
+        functions: vec![None],
+        scopes: vec![],
+    };
+    let span = program.first().map_or(Span::default(), |s| s.span());
+    let main = compiler.function(None, &[], span, |c| c.statements(program));
+    compiler.functions[0] = Some(main);
+    Program {
+        functions: compiler.functions.into_iter().map(Option::unwrap).collect(),
+    }
+}
+
+impl Compiler {
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, op: Op, site: Site) -> usize {
+        let scope = self.scope();
+        scope.height = (scope.height as isize + op.effect()) as usize;
+        scope.function.code.push(op);
+        scope.function.sites.push(site);
+        scope.function.code.len() - 1
+    }
+
+    fn here(&mut self) -> usize {
+        self.scope().function.code.len()
+    }
+
+    /// Points the jump at `at` to the next instruction
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        match &mut self.scope().function.code[at] {
+            Op::Jump(t) | Op::JumpIfFalse(t) => *t = target,
+            op => unreachable!("patching {}", op),
+        }
+    }
+
+    /// Compiles a function whose parameters are its first locals, `body`
+    /// leaves its result on the stack
+    fn function(
+        &mut self,
+        name: Option<&str>,
+        params: &[String],
+        span: Span,
+        body: impl FnOnce(&mut Compiler),
+    ) -> Function {
+        let locals = params
+            .iter()
+            .enumerate()
+            .map(|(slot, name)| Local {
+                name: name.clone(),
+                slot,
+                mutable: false,
+            })
+            .collect();
+        self.scopes.push(Scope {
+            function: Function {
+                name: name.map(str::to_string),
+                params: params.to_vec(),
+                captures: vec![],
+                code: vec![],
+                sites: vec![],
+                span,
+            },
+            locals,
+            height: params.len(),
+            loops: vec![],
+        });
+        body(self);
+        self.emit(Op::Return, Site::new(span));
+        self.scopes.pop().unwrap().function
+    }
+
+    /// Finds `name` from the function at `depth`, capturing it from the
+    /// functions around it as needed. Returns whether it is a `var`.
+    fn resolve(&mut self, depth: usize, name: &str) -> Option<(Access, bool)> {
+        let scope = &self.scopes[depth];
+        if let Some(local) = scope.locals.iter().rev().find(|l| l.name == name) {
+            return Some((Access::Local(local.slot), local.mutable));
+        }
+        if scope.function.name.as_deref() == Some(name) && depth > 0 {
+            return Some((Access::This, false));
+        }
+        let captures = &scope.function.captures;
+        if let Some(i) = captures.iter().position(|(n, _)| n == name) {
+            let mutable = self.resolve(depth - 1, name).unwrap().1;
+            return Some((Access::Capture(i), mutable));
+        }
+        if depth == 0 {
+            return None;
+        }
+        let (access, mutable) = self.resolve(depth - 1, name)?;
+        let capture = match access {
+            Access::Local(slot) => Capture::Local(slot),
+            Access::Capture(i) => Capture::Outer(i),
+            Access::This => Capture::Itself,
+        };
+        let captures = &mut self.scopes[depth].function.captures;
+        captures.push((name.to_string(), capture));
+        Some((Access::Capture(captures.len() - 1), mutable))
+    }
+
+    fn declare(&mut self, name: &str, mutable: bool) {
+        let scope = self.scope();
+        let slot = scope.height - 1;
+        scope.locals.push(Local {
+            name: name.to_string(),
+            slot,
+            mutable,
+        });
+    }
+
+    /// Statements in order, leaving the value of the sequence on the stack
+    /// and removing their locals
+    fn statements(&mut self, statements: &[S]) {
+        let scope = self.scope().locals.len();
+        let mut value = None;
+        for statement in statements {
+            if let Some(span) = value {
+                self.emit(Op::Pop, Site::new(span));
+            }
+            value = self.statement(statement);
+        }
+        let span = statements.last().map_or(Span::default(), |s| s.span());
+        if value.is_none() {
+            self.emit(Op::Unit, Site::new(span));
+        }
+        let locals = self.scope().locals.len() - scope;
+        if locals > 0 {
+            self.emit(Op::Slide(locals), Site::new(span));
+            self.scope().locals.truncate(scope);
+        }
+    }
+
+    /// Compiles a statement, returns the span of its value if it leaves
+    /// one on the stack
+    fn statement(&mut self, statement: &S) -> Option<Span> {
+        match statement {
+            S::EXPR(e) => {
+                self.expression(e);
+                return Some(e.span());
+            }
+            S::LET(name, value, _) => {
+                self.expression(value);
+                self.declare(name, false);
+            }
+            S::LETREC(name, value, span) => {
+                self.recursive(name, value, *span);
+                self.declare(name, false);
+            }
+            S::PRINT(e, span) => {
+                self.expression(e);
+                self.emit(Op::Print, Site::new(*span));
+            }
+            S::VAR(name, value, span) => {
+                self.expression(value);
+                self.emit(Op::Cell, Site::new(*span));
+                self.declare(name, true);
+            }
+            S::ASSIGN(name, value, span) => {
+                let site = Site::new(*span);
+                let depth = self.scopes.len() - 1;
+                match self.resolve(depth, name) {
+                    Some((access, true)) => {
+                        self.expression(value);
+                        self.access(access, site);
+                        self.emit(Op::Set, site);
+                    }
+                    Some((_, false)) => {
+                        let kind = RuntimeErrorKind::AssignToImmutable(name.clone());
+                        self.emit(Op::Raise(kind), site);
+                        self.emit(Op::Pop, site);
+                    }
+                    None => {
+                        let kind = RuntimeErrorKind::UnboundVariable(name.clone());
+                        self.emit(Op::Raise(kind), site);
+                        self.emit(Op::Pop, site);
+                    }
+                }
+            }
+            S::WHILE(cond, body, span) => {
+                let start = self.here();
+                let height = self.scope().height;
+                self.scope().loops.push(Loop {
+                    start,
+                    height,
+                    breaks: vec![],
+                });
+                self.expression(cond);
+                let site = Site {
+                    left: cond.span(),
+                    ..Site::new(*span)
+                };
+                let exit = self.emit(Op::JumpIfFalse(0), site);
+                self.statements(body);
+                self.emit(Op::Pop, site);
+                self.emit(Op::Jump(start), site);
+                self.patch(exit);
+                for at in self.scope().loops.pop().unwrap().breaks {
+                    self.patch(at);
+                }
+            }
+            S::BREAK(span) | S::CONTINUE(span) => {
+                let site = Site::new(*span);
+                let scope = self.scope();
+                let height = scope.height;
+                match scope.loops.last().map(|l| (l.start, l.height)) {
+                    Some((start, inner)) => {
+                        // leave the scopes inside the loop, the stack stays as
+                        // it is for the code after the jump
+                        self.emit(Op::PopN(height - inner), site);
+                        if let S::BREAK(_) = statement {
+                            let at = self.emit(Op::Jump(0), site);
+                            self.scope().loops.last_mut().unwrap().breaks.push(at);
+                        } else {
+                            self.emit(Op::Jump(start), site);
+                        }
+                        self.scope().height = height;
+                    }
+                    None => {
+                        let keyword = match statement {
+                            S::BREAK(_) => "break",
+                            _ => "continue",
+                        };
+                        self.emit(Op::Raise(RuntimeErrorKind::OutsideLoop(keyword)), site);
+                        self.emit(Op::Pop, site);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Pushes the variable reached by `access`, a cell for a `var`
+    fn access(&mut self, access: Access, site: Site) {
+        match access {
+            Access::Local(slot) => self.emit(Op::Load(slot), site),
+            Access::Capture(i) => self.emit(Op::Capture(i), site),
+            Access::This => self.emit(Op::This, site),
+        };
+    }
+
+    /// The value of `let rec name = value`. A function written as the value
+    /// sees itself through `name`.
+    fn recursive(&mut self, name: &str, value: &E, span: Span) {
+        let mut e = value;
+        while let E::PAREN(inner, _) = e {
+            e = inner;
+        }
+        match e {
+            E::FN(params, body, span) => self.closure(Some(name), params, body, *span),
+            _ => self.expression(value),
+        }
+        self.emit(Op::Rec(name.to_string()), Site::new(span));
+    }
+
+    fn closure(&mut self, name: Option<&str>, params: &[String], body: &E, span: Span) {
+        let index = self.functions.len();
+        self.functions.push(None);
+        let function = self.function(name, params, span, |c| c.expression(body));
+        self.functions[index] = Some(function);
+        self.emit(Op::Closure(index), Site::new(span));
+    }
+
+    fn expression(&mut self, e: &E) {
+        match e {
+            E::LITERAL(v, span) => {
+                let op = match i32::try_from(*v) {
+                    Ok(v) => Op::Int(v),
+                    Err(_) => Op::Raise(RuntimeErrorKind::LiteralOutOfRange(*v)),
+                };
+                self.emit(op, Site::new(*span));
+            }
+            E::BOOL(b, span) => {
+                self.emit(Op::Bool(*b), Site::new(*span));
+            }
+            E::PAREN(e, _) => self.expression(e),
+            // `-2147483648` is the negation of a literal that is out of range on its own
+            E::UNARY(OP::MINUS, v, span) if matches!(**v, E::LITERAL(2147483648, _)) => {
+                self.emit(Op::Int(i32::MIN), Site::new(*span));
+            }
+            E::UNARY(op, v, span) => {
+                self.expression(v);
+                let site = Site {
+                    left: v.span(),
+                    ..Site::new(*span)
+                };
+                let op = match op {
+                    OP::MINUS => Op::Neg,
+                    _ => Op::Pos,
+                };
+                self.emit(op, site);
+            }
+            E::BINARY(left, op, right, span) => {
+                self.expression(left);
+                self.expression(right);
+                let site = Site {
+                    span: *span,
+                    left: left.span(),
+                    right: right.span(),
+                };
+                let op = match op {
+                    OP::PLUS => Op::Add,
+                    OP::MINUS => Op::Sub,
+                    OP::MULT => Op::Mul,
+                    OP::DIV => Op::Div,
+                    OP::POW => Op::Pow,
+                    OP::EQUALITY => Op::Eq,
+                    OP::GREATER => Op::Gt,
+                    OP::LESS => Op::Lt,
+                    OP::GEQ => Op::Ge,
+                    OP::LEQ => Op::Le,
+                };
+                self.emit(op, site);
+            }
+            E::IF(cond, then, elze, span) => {
+                self.expression(cond);
+                let site = Site {
+                    left: cond.span(),
+                    ..Site::new(*span)
+                };
+                let to_else = self.emit(Op::JumpIfFalse(0), site);
+                self.expression(then);
+                let to_end = self.emit(Op::Jump(0), site);
+                // only one of the branches pushes its value
+                self.scope().height -= 1;
+                self.patch(to_else);
+                self.expression(elze);
+                self.patch(to_end);
+            }
+            E::LET(name, value, body, span) | E::LETREC(name, value, body, span) => {
+                match e {
+                    E::LETREC(..) => self.recursive(name, value, *span),
+                    _ => self.expression(value),
+                }
+                self.declare(name, false);
+                self.expression(body);
+                self.scope().locals.pop();
+                self.emit(Op::Slide(1), Site::new(*span));
+            }
+            E::VAR(name, span) => {
+                let site = Site::new(*span);
+                let depth = self.scopes.len() - 1;
+                match self.resolve(depth, name) {
+                    Some((access, mutable)) => {
+                        self.access(access, site);
+                        if mutable {
+                            self.emit(Op::Get, site);
+                        }
+                    }
+                    None => {
+                        let kind = RuntimeErrorKind::UnboundVariable(name.clone());
+                        self.emit(Op::Raise(kind), site);
+                    }
+                }
+            }
+            E::FN(params, body, span) => self.closure(None, params, body, *span),
+            E::CALL(callee, args, span) => {
+                self.expression(callee);
+                let site = Site {
+                    left: callee.span(),
+                    ..Site::new(*span)
+                };
+                self.emit(Op::CheckCall(args.len()), site);
+                for arg in args {
+                    self.expression(arg);
+                }
+                self.emit(Op::Call(args.len()), site);
+            }
+            E::BLOCK(statements, _) => self.statements(statements),
+        }
+    }
+}
+
+/// A function together with the variables it captured
+#[derive(Debug)]
+pub struct Closure {
+    function: usize,
+    captures: Vec<Value>,
+    name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i32),
+    Bool(bool),
+    Function(Rc<Closure>),
+    Unit,
+    /// The cell holding the value of a `var`, only found in its slot and in
+    /// the closures capturing it
+    Cell(Rc<RefCell<Value>>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::Bool(_) => "bool",
+            Value::Function(_) => "function",
+            Value::Unit => "unit",
+            Value::Cell(cell) => cell.borrow().type_name(),
+        }
+    }
+}
+
+/// Prints values the way the resolver does
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Function(closure) => match &closure.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => write!(f, "<fn>"),
+            },
+            Value::Unit => write!(f, "()"),
+            Value::Cell(cell) => write!(f, "{}", cell.borrow()),
+        }
+    }
+}
+
+fn mismatch(expected: &'static str, found: &Value, span: Span, operand: Span) -> RuntimeError {
+    let kind = RuntimeErrorKind::TypeMismatch {
+        expected,
+        found: found.type_name(),
+    };
+    RuntimeError::new(kind, span).operand(operand)
+}
+
+/// `==` on values of the same type, functions are only equal to themselves
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => a == b,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+        _ => true,
+    }
+}
+
+/// The result of a binary instruction, with the errors the resolver gives
+/// for the same operands
+fn binary(op: OP, a: Value, b: Value, site: Site) -> Result<Value, RuntimeError> {
+    match (a, b) {
+        (a, b) if op == OP::EQUALITY && a.type_name() == b.type_name() => {
+            Ok(Value::Bool(equal(&a, &b)))
+        }
+        (Value::Int(a), Value::Int(b)) => match resolver::binary(&op, a, b) {
+            Ok(resolver::Value::Int(v)) => Ok(Value::Int(v)),
+            Ok(resolver::Value::Bool(b)) => Ok(Value::Bool(b)),
+            Ok(v) => unreachable!("{} of two integers", v),
+            Err(
+                kind @ (RuntimeErrorKind::DivisionByZero | RuntimeErrorKind::NegativeExponent(_)),
+            ) => Err(RuntimeError::new(kind, site.span).operand(site.right)),
+            Err(kind) => Err(RuntimeError::new(kind, site.span)),
+        },
+        (a, b) if op == OP::EQUALITY => Err(mismatch(a.type_name(), &b, site.span, site.right)),
+        (Value::Int(_), b) => Err(mismatch("int", &b, site.span, site.right)),
+        (a, _) => Err(mismatch("int", &a, site.span, site.left)),
+    }
+}
+
+struct Frame {
+    function: usize,
+    /// `None` for the top level
+    closure: Option<Rc<Closure>>,
+    ip: usize,
+    /// Where the locals of the frame start on the stack
+    base: usize,
+}
+
+/// Runs `program`, `print` writes to `out`. Returns the value of the
+/// program, which is the same as the resolver's.
+pub fn run(program: &Program, out: &mut dyn Write) -> Result<Value, RuntimeError> {
+    let mut stack: Vec<Value> = vec![];
+    let mut frames = vec![Frame {
+        function: 0,
+        closure: None,
+        ip: 0,
+        base: 0,
+    }];
+    loop {
+        let frame = frames.last_mut().unwrap();
+        let function = &program.functions[frame.function];
+        let (op, site) = (&function.code[frame.ip], function.sites[frame.ip]);
+        frame.ip += 1;
+        let pop = |stack: &mut Vec<Value>| stack.pop().unwrap();
+        match op {
+            Op::Int(v) => stack.push(Value::Int(*v)),
+            Op::Bool(b) => stack.push(Value::Bool(*b)),
+            Op::Unit => stack.push(Value::Unit),
+            Op::Load(slot) => stack.push(stack[frame.base + slot].clone()),
+            Op::Capture(i) => {
+                let closure = frame.closure.as_ref().unwrap();
+                stack.push(closure.captures[*i].clone());
+            }
+            Op::This => stack.push(Value::Function(frame.closure.clone().unwrap())),
+            Op::Cell => {
+                let value = pop(&mut stack);
+                stack.push(Value::Cell(Rc::new(RefCell::new(value))));
+            }
+            Op::Get => match pop(&mut stack) {
+                Value::Cell(cell) => stack.push(cell.borrow().clone()),
+                v => unreachable!("get from {}", v),
+            },
+            Op::Set => match (pop(&mut stack), pop(&mut stack)) {
+                (Value::Cell(cell), value) => *cell.borrow_mut() = value,
+                (v, _) => unreachable!("set of {}", v),
+            },
+            Op::Pop => {
+                stack.pop();
+            }
+            Op::PopN(n) => stack.truncate(stack.len() - n),
+            Op::Slide(n) => {
+                let top = pop(&mut stack);
+                stack.truncate(stack.len() - n);
+                stack.push(top);
+            }
+            Op::Neg | Op::Pos => match (op, pop(&mut stack)) {
+                (Op::Neg, Value::Int(v)) => match v.checked_neg() {
+                    Some(v) => stack.push(Value::Int(v)),
+                    None => {
+                        let kind = RuntimeErrorKind::Overflow(OP::MINUS);
+                        return Err(RuntimeError::new(kind, site.span));
+                    }
+                },
+                (_, Value::Int(v)) => stack.push(Value::Int(v)),
+                (_, v) => return Err(mismatch("int", &v, site.span, site.left)),
+            },
+            Op::Jump(target) => frame.ip = *target,
+            Op::JumpIfFalse(target) => match pop(&mut stack) {
+                Value::Bool(true) => (),
+                Value::Bool(false) => frame.ip = *target,
+                v => return Err(mismatch("bool", &v, site.span, site.left)),
+            },
+            Op::Closure(n) => {
+                let captures = program.functions[*n]
+                    .captures
+                    .iter()
+                    .map(|(_, capture)| match capture {
+                        Capture::Local(slot) => stack[frame.base + slot].clone(),
+                        Capture::Outer(i) => frame.closure.as_ref().unwrap().captures[*i].clone(),
+                        Capture::Itself => Value::Function(frame.closure.clone().unwrap()),
+                    })
+                    .collect();
+                stack.push(Value::Function(Rc::new(Closure {
+                    function: *n,
+                    captures,
+                    name: None,
+                })));
+            }
+            Op::Rec(name) => {
+                if let Some(Value::Function(closure)) = stack.last_mut() {
+                    *closure = Rc::new(Closure {
+                        function: closure.function,
+                        captures: closure.captures.clone(),
+                        name: Some(name.clone()),
+                    });
+                }
+            }
+            Op::CheckCall(n) => {
+                let closure = match stack.last().unwrap() {
+                    Value::Function(closure) => closure,
+                    v => return Err(mismatch("function", v, site.span, site.left)),
+                };
+                let callee = &program.functions[closure.function];
+                if callee.params.len() != *n {
+                    let kind = RuntimeErrorKind::ArityMismatch {
+                        expected: callee.params.len(),
+                        found: *n,
+                    };
+                    return Err(RuntimeError::new(kind, site.span).operand(callee.span));
+                }
+                if frames.len() - 1 == MAX_CALL_DEPTH {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::RecursionLimit,
+                        site.span,
+                    ));
+                }
+            }
+            Op::Call(n) => {
+                let base = stack.len() - n;
+                let closure = match &stack[base - 1] {
+                    Value::Function(closure) => closure.clone(),
+                    v => unreachable!("call of {}", v),
+                };
+                frames.push(Frame {
+                    function: closure.function,
+                    closure: Some(closure),
+                    ip: 0,
+                    base,
+                });
+            }
+            Op::Return => {
+                let result = pop(&mut stack);
+                let frame = frames.pop().unwrap();
+                if frames.is_empty() {
+                    return Ok(result);
+                }
+                // the callee sits below the arguments
+                stack.truncate(frame.base - 1);
+                stack.push(result);
+            }
+            Op::Print => {
+                let value = pop(&mut stack);
+                writeln!(out, "{}", value).expect("failed to write output");
+            }
+            Op::Raise(kind) => return Err(RuntimeError::new(kind.clone(), site.span)),
+            op => {
+                let b = pop(&mut stack);
+                let a = pop(&mut stack);
+                stack.push(binary(op.operator().unwrap(), a, b, site)?);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{pratt::program, tokenizer::Tokenizer};
+
+    use super::*;
+
+    fn compile_source(input: &str) -> Program {
+        let tokens = Tokenizer::new(input).run().unwrap();
+        compile(&program(&tokens).unwrap())
+    }
+
+    fn run_source(input: &str) -> (Result<Value, RuntimeError>, String) {
+        let mut out = vec![];
+        let result = run(&compile_source(input), &mut out);
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn disassembly() {
+        let program = compile_source("let x = 2; if x > 1 | x * 3 else -x");
+        assert_eq!(
+            "\
+fn #0()
+   0  1:9   int 2
+   1  1:15  load 0
+   2  1:19  int 1
+   3  1:15  gt
+   4  1:12  jump-if-false 9
+   5  1:23  load 0
+   6  1:27  int 3
+   7  1:23  mul
+   8  1:12  jump 11
+   9  1:35  load 0
+  10  1:34  neg
+  11  1:12  slide 1
+  12  1:1   return
+",
+            program.to_string()
+        );
+    }
+
+    #[test]
+    fn functions_are_listed_with_their_captures() {
+        let program = compile_source("let rec f = fn n => fn => f(n)");
+        let listing = program.to_string();
+        assert!(listing.contains("fn #1 f(n)\n"), "{}", listing);
+        assert!(listing.contains("fn #2() captures f, n\n"), "{}", listing);
+    }
+
+    #[test]
+    fn unbound_names_fail_when_reached() {
+        let (result, out) = run_source("print 1; if false | y else 2");
+        assert_eq!("2", result.unwrap().to_string());
+        assert_eq!("1\n", out);
+        let program = compile_source("y");
+        assert!(program
+            .to_string()
+            .contains("raise \"cannot find variable `y` in this scope\""));
+    }
+
+    #[test]
+    fn program_runs_more_than_once() {
+        let program = compile_source("var n = 0; while n < 3 { print n; n = n + 1; }; n");
+        for _ in 0..2 {
+            let mut out = vec![];
+            assert_eq!("3", run(&program, &mut out).unwrap().to_string());
+            assert_eq!("0\n1\n2\n", String::from_utf8(out).unwrap());
+        }
+    }
+
+    #[test]
+    fn break_leaves_nested_scopes() {
+        let (result, _) = run_source(
+            "var i = 0; while true { let a = 1; { let b = 2; i = i + a + b; if i > 10 | { break; } else {} } }; i",
+        );
+        assert_eq!("12", result.unwrap().to_string());
+    }
+
+    #[test]
+    fn closures_capture_cells() {
+        let (result, _) = run_source(
+            "let counter = fn => { var c = 0; fn => { c = c + 1; c } }; let a = counter(); let b = counter(); a(); a(); b(); a() * 10 + b()",
+        );
+        assert_eq!("32", result.unwrap().to_string());
+    }
+
+    #[test]
+    fn deep_recursion() {
+        let (result, _) = run_source(&format!(
+            "let rec f = fn n => if n == 0 | 0 else 1 + f(n - 1); f({})",
+            MAX_CALL_DEPTH - 1
+        ));
+        assert_eq!(
+            (MAX_CALL_DEPTH - 1).to_string(),
+            result.unwrap().to_string()
+        );
+        let e = run(
+            &compile_source("let rec f = fn n => 1 + f(n); f(0)"),
+            &mut io::sink(),
+        );
+        assert_eq!(RuntimeErrorKind::RecursionLimit, e.unwrap_err().kind);
+    }
+}
@@ -0,0 +1,833 @@
+use std::fmt::Write;
+
+use crate::{
+    pratt::{E, S},
+    resolver::{RuntimeError, RuntimeErrorKind, MAX_CALL_DEPTH},
+    span::Span,
+    tokenizer::OP,
+};
+
+/// The exit status of a compiled program that fails, the same as the
+/// interpreter's
+const EXIT_RUNTIME: i32 = 5;
+
+/// The message of a runtime error, without where it happened
+fn message(kind: RuntimeErrorKind) -> String {
+    RuntimeError::new(kind, Span::default()).to_string()
+}
+
+/// A C string literal, escaping what is not printable ASCII
+fn string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for b in s.bytes() {
+        match b {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20..=0x7e => out.push(b as char),
+            b => write!(out, "\\{:03o}", b).unwrap(),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// The values and the checked arithmetic of the generated code. Values are
+/// tagged so that `print` and `==` work on them whatever their type.
+fn runtime() -> String {
+    let mut out = String::from(
+        r#"#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef struct goof_closure goof_closure;
+
+/* booleans are 0 or 1 */
+typedef struct {
+    enum { GOOF_INT, GOOF_BOOL, GOOF_FN, GOOF_UNIT } tag;
+    union {
+        int32_t i;
+        goof_closure *fn;
+    } as;
+} goof_value;
+
+/* a function with the cells of the variables it captures, which it shares
+   with the scopes they come from */
+struct goof_closure {
+    void (*code)(void);
+    /* the name given by `let rec`, or NULL */
+    const char *name;
+    int captures;
+    goof_value *env[];
+};
+
+/* the number of calls being evaluated */
+static int goof_depth;
+
+static goof_value goof_int(int32_t i) {
+    goof_value v;
+    v.tag = GOOF_INT;
+    v.as.i = i;
+    return v;
+}
+
+static goof_value goof_bool(int b) {
+    goof_value v;
+    v.tag = GOOF_BOOL;
+    v.as.i = b;
+    return v;
+}
+
+static goof_value goof_unit(void) {
+    goof_value v;
+    v.tag = GOOF_UNIT;
+    v.as.i = 0;
+    return v;
+}
+
+static goof_value goof_fn(goof_closure *fn) {
+    goof_value v;
+    v.tag = GOOF_FN;
+    v.as.fn = fn;
+    return v;
+}
+
+static goof_value *goof_cell(goof_value v) {
+    goof_value *cell = malloc(sizeof *cell);
+    *cell = v;
+    return cell;
+}
+
+static goof_closure *goof_closure_new(void (*code)(void), const char *name, int captures) {
+    goof_closure *fn = malloc(sizeof *fn + captures * sizeof fn->env[0]);
+    fn->code = code;
+    fn->name = name;
+    fn->captures = captures;
+    return fn;
+}
+
+/* a function bound by `let rec` is a copy named after the binding, any
+   other value is unchanged */
+static goof_value goof_rec(goof_value v, const char *name) {
+    goof_closure *fn;
+    int i;
+    if (v.tag != GOOF_FN)
+        return v;
+    fn = goof_closure_new(v.as.fn->code, name, v.as.fn->captures);
+    for (i = 0; i < fn->captures; i++)
+        fn->env[i] = v.as.fn->env[i];
+    return goof_fn(fn);
+}
+
+static void goof_print(goof_value v) {
+    switch (v.tag) {
+    case GOOF_INT:
+        printf("%ld\n", (long)v.as.i);
+        break;
+    case GOOF_BOOL:
+        puts(v.as.i ? "true" : "false");
+        break;
+    case GOOF_FN:
+        if (v.as.fn->name)
+            printf("<fn %s>\n", v.as.fn->name);
+        else
+            puts("<fn>");
+        break;
+    case GOOF_UNIT:
+        puts("()");
+        break;
+    }
+}
+
+/* the type checker only lets values of the same type be compared,
+   functions are only equal to themselves */
+static goof_value goof_equal(goof_value a, goof_value b) {
+    switch (a.tag) {
+    case GOOF_FN:
+        return goof_bool(a.as.fn == b.as.fn);
+    case GOOF_UNIT:
+        return goof_bool(1);
+    default:
+        return goof_bool(a.as.i == b.as.i);
+    }
+}
+
+static void goof_fail(const char *message, int line, int col) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s\n --> %d:%d\n", message, line, col);
+"#,
+    );
+    writeln!(out, "    exit({});", EXIT_RUNTIME).unwrap();
+    out += "}\n\n";
+
+    let overflow = |op: OP| string(&message(RuntimeErrorKind::Overflow(op)));
+    out += "static int32_t goof_checked(int64_t v, const char *message, int line, int col) {\n";
+    out += "    if (v < INT32_MIN || v > INT32_MAX)\n";
+    out += "        goof_fail(message, line, col);\n";
+    out += "    return (int32_t)v;\n";
+    out += "}\n";
+    for (name, op, operator) in [
+        ("add", OP::PLUS, '+'),
+        ("sub", OP::MINUS, '-'),
+        ("mul", OP::MULT, '*'),
+    ] {
+        write!(
+            out,
+            "
+static goof_value goof_{}(goof_value a, goof_value b, int line, int col) {{
+    return goof_int(goof_checked((int64_t)a.as.i {} b.as.i, {}, line, col));
+}}
+",
+            name,
+            operator,
+            overflow(op)
+        )
+        .unwrap();
+    }
+    write!(
+        out,
+        "
+static goof_value goof_neg(goof_value a, int line, int col) {{
+    return goof_int(goof_checked(-(int64_t)a.as.i, {}, line, col));
+}}
+
+/* i32::MIN / -1 is the only overflowing division */
+static goof_value goof_div(goof_value a, goof_value b, int line, int col) {{
+    if (b.as.i == 0)
+        goof_fail({}, line, col);
+    if (b.as.i == -1)
+        return goof_int(goof_checked(-(int64_t)a.as.i, {}, line, col));
+    return goof_int(a.as.i / b.as.i);
+}}
+",
+        overflow(OP::MINUS),
+        string(&message(RuntimeErrorKind::DivisionByZero)),
+        overflow(OP::DIV),
+    )
+    .unwrap();
+
+    // the exponent ends the message
+    let negative = message(RuntimeErrorKind::NegativeExponent(0));
+    let negative = negative.strip_suffix('0').unwrap();
+    write!(
+        out,
+        "
+/* exponentiation by squaring, which like `i32::checked_pow` only overflows
+   if the result does */
+static goof_value goof_pow(goof_value a, goof_value b, int line, int col) {{
+    int64_t base = a.as.i, result = 1;
+    int32_t exp = b.as.i;
+    if (exp < 0) {{
+        char message[64];
+        sprintf(message, \"%s%ld\", {}, (long)exp);
+        goof_fail(message, line, col);
+    }}
+    if (exp == 0)
+        return goof_int(1);
+    while (exp > 1) {{
+        if (exp & 1)
+            result = goof_checked(result * base, {overflow}, line, col);
+        exp /= 2;
+        base = goof_checked(base * base, {overflow}, line, col);
+    }}
+    return goof_int(goof_checked(result * base, {overflow}, line, col));
+}}
+",
+        string(negative),
+        overflow = overflow(OP::POW),
+    )
+    .unwrap();
+    out
+}
+
+/// A variable of the function being compiled
+struct Local {
+    name: String,
+    /// The name of the C variable, a cell for a `var`
+    c: String,
+    mutable: bool,
+}
+
+/// Where a variable is found from a function
+enum Access {
+    Local(String),
+    /// The cell at an index of the environment of the running closure
+    Capture(usize),
+    /// The running closure, named by `let rec`
+    This,
+}
+
+/// How a closure gets the cell of a variable it captures, from the
+/// function creating it
+enum Capture {
+    Local(String),
+    /// A variable the creating closure captured itself
+    Outer(usize),
+    /// The creating closure
+    Itself,
+}
+
+/// A function being compiled
+struct Scope {
+    /// The name given by `let rec`
+    name: Option<String>,
+    /// The captured variables with whether they are `var`s
+    captures: Vec<(String, Capture, bool)>,
+    locals: Vec<Local>,
+    code: String,
+    indent: usize,
+}
+
+struct Compiler {
+    scopes: Vec<Scope>,
+    /// The C functions of the closures, each one after those it creates
+    functions: Vec<String>,
+    /// Numbers the C variables
+    names: usize,
+}
+
+/// Compiles a program that passed the type checker to a C translation
+/// unit. Its `main` prints the value of the program like the interpreter
+/// does, and exits with status 5 after printing the message of a runtime
+/// error. Functions become C functions taking their closure and their
+/// arguments, variables become C locals.
+pub fn compile(program: &[S]) -> String {
+    let mut c = Compiler {
+        scopes: vec![],
+        functions: vec![],
+        names: 0,
+    };
+    c.scopes.push(Scope {
+        name: None,
+        captures: vec![],
+        locals: vec![],
+        code: String::new(),
+        indent: 1,
+    });
+    let value = c.statements(program);
+    // like the interpreter, unit values are not printed
+    c.line(format!("if ({}.tag != GOOF_UNIT)", value));
+    c.line(format!("    goof_print({});", value));
+    c.line("return 0;");
+    let main = c.scopes.pop().unwrap();
+
+    let mut out = runtime();
+    for function in &c.functions {
+        out += "\n";
+        out += function;
+    }
+    write!(out, "\nint main(void) {{\n{}}}\n", main.code).unwrap();
+    out
+}
+
+impl Compiler {
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn line(&mut self, line: impl AsRef<str>) {
+        let scope = self.scope();
+        for _ in 0..scope.indent {
+            scope.code += "    ";
+        }
+        scope.code += line.as_ref();
+        scope.code.push('\n');
+    }
+
+    /// Opens a C block after `line`, closed with `close`
+    fn open(&mut self, line: impl AsRef<str>) {
+        self.line(format!("{} {{", line.as_ref()));
+        self.scope().indent += 1;
+    }
+
+    fn close(&mut self, line: &str) {
+        self.scope().indent -= 1;
+        self.line(line);
+    }
+
+    /// A new C variable, named after the goof variable if it is ASCII
+    fn fresh(&mut self, name: Option<&str>) -> String {
+        self.names += 1;
+        match name {
+            Some(name) if name.is_ascii() => format!("{}_{}", name, self.names),
+            Some(_) => format!("v_{}", self.names),
+            None => format!("t{}", self.names),
+        }
+    }
+
+    /// Stores the value of `expression` in a new C variable
+    fn temporary(&mut self, expression: String) -> String {
+        let t = self.fresh(None);
+        self.line(format!("goof_value {} = {};", t, expression));
+        t
+    }
+
+    /// Finds `name` from the function at `depth`, capturing it from the
+    /// functions around it as needed. Returns whether it is a `var`.
+    fn resolve(&mut self, depth: usize, name: &str) -> (Access, bool) {
+        let scope = &self.scopes[depth];
+        if let Some(local) = scope.locals.iter().rev().find(|l| l.name == name) {
+            return (Access::Local(local.c.clone()), local.mutable);
+        }
+        if scope.name.as_deref() == Some(name) && depth > 0 {
+            return (Access::This, false);
+        }
+        if let Some(i) = scope.captures.iter().position(|(n, ..)| n == name) {
+            return (Access::Capture(i), scope.captures[i].2);
+        }
+        // the type checker found the variable in scope
+        let (access, mutable) = self.resolve(depth - 1, name);
+        let capture = match access {
+            Access::Local(c) => Capture::Local(c),
+            Access::Capture(i) => Capture::Outer(i),
+            Access::This => Capture::Itself,
+        };
+        let captures = &mut self.scopes[depth].captures;
+        captures.push((name.to_string(), capture, mutable));
+        (Access::Capture(captures.len() - 1), mutable)
+    }
+
+    fn declare(&mut self, name: &str, value: String, mutable: bool) {
+        let c = self.fresh(Some(name));
+        if mutable {
+            self.line(format!("goof_value *{} = goof_cell({});", c, value));
+        } else {
+            self.line(format!("goof_value {} = {};", c, value));
+        }
+        self.scope().locals.push(Local {
+            name: name.to_string(),
+            c,
+            mutable,
+        });
+    }
+
+    /// Statements in order, returns the value of the sequence
+    fn statements(&mut self, statements: &[S]) -> String {
+        let scope = self.scope().locals.len();
+        let mut value = None;
+        for statement in statements {
+            value = self.statement(statement);
+        }
+        self.scope().locals.truncate(scope);
+        value.unwrap_or_else(|| "goof_unit()".to_string())
+    }
+
+    /// Compiles a statement, returns its value if it is an expression
+    fn statement(&mut self, statement: &S) -> Option<String> {
+        match statement {
+            S::EXPR(e) => return Some(self.expression(e)),
+            S::LET(name, value, _) => {
+                let value = self.expression(value);
+                self.declare(name, value, false);
+            }
+            S::LETREC(name, value, _) => {
+                let value = self.recursive(name, value);
+                self.declare(name, value, false);
+            }
+            S::VAR(name, value, _) => {
+                let value = self.expression(value);
+                self.declare(name, value, true);
+            }
+            S::PRINT(e, _) => {
+                let value = self.expression(e);
+                self.line(format!("goof_print({});", value));
+            }
+            S::ASSIGN(name, value, _) => {
+                let value = self.expression(value);
+                let depth = self.scopes.len() - 1;
+                match self.resolve(depth, name) {
+                    (Access::Local(c), true) => self.line(format!("*{} = {};", c, value)),
+                    (Access::Capture(i), true) => {
+                        self.line(format!("*self->env[{}] = {};", i, value))
+                    }
+                    _ => unreachable!("the type checker only allows assigning to a `var`"),
+                }
+            }
+            S::WHILE(cond, body, _) => {
+                self.open("while (1)");
+                let cond = self.expression(cond);
+                self.line(format!("if (!{}.as.i)", cond));
+                self.line("    break;");
+                self.statements(body);
+                self.close("}");
+            }
+            // the type checker only accepts them inside loops, which are C
+            // loops of the same function
+            S::BREAK(_) => self.line("break;"),
+            S::CONTINUE(_) => self.line("continue;"),
+        }
+        None
+    }
+
+    /// The value of `let rec name = value`. A function written as the value
+    /// sees itself through `name`.
+    fn recursive(&mut self, name: &str, value: &E) -> String {
+        let mut e = value;
+        while let E::PAREN(inner, _) = e {
+            e = inner;
+        }
+        match e {
+            E::FN(params, body, span) => self.closure(Some(name), params, body, *span),
+            _ => {
+                let value = self.expression(value);
+                self.temporary(format!("goof_rec({}, {})", value, string(name)))
+            }
+        }
+    }
+
+    /// Compiles a function to a C function, returns the closure created
+    /// from it
+    fn closure(&mut self, name: Option<&str>, params: &[String], body: &E, span: Span) -> String {
+        let locals: Vec<Local> = params
+            .iter()
+            .map(|param| Local {
+                name: param.clone(),
+                c: self.fresh(Some(param)),
+                mutable: false,
+            })
+            .collect();
+        let mut signature = String::from("goof_closure *self");
+        for local in &locals {
+            write!(signature, ", goof_value {}", local.c).unwrap();
+        }
+        self.scopes.push(Scope {
+            name: name.map(str::to_string),
+            captures: vec![],
+            locals,
+            code: String::new(),
+            indent: 1,
+        });
+        self.line("(void)self;");
+        let result = self.expression(body);
+        self.line(format!("return {};", result));
+        let scope = self.scopes.pop().unwrap();
+
+        let function = format!("goof_fn{}", self.functions.len() + 1);
+        self.functions.push(format!(
+            "/* fn{}({}) at {}:{} */\nstatic goof_value {}({}) {{\n{}}}\n",
+            name.map_or(String::new(), |name| format!(" {}", name)),
+            params.join(", "),
+            span.line,
+            span.col,
+            function,
+            signature,
+            scope.code
+        ));
+        let name = name.map_or("NULL".to_string(), string);
+        let closure = self.temporary(format!(
+            "goof_fn(goof_closure_new((void (*)(void)){}, {}, {}))",
+            function,
+            name,
+            scope.captures.len()
+        ));
+        for (i, (_, capture, mutable)) in scope.captures.iter().enumerate() {
+            let cell = match (capture, mutable) {
+                (Capture::Local(c), true) => c.clone(),
+                (Capture::Local(c), false) => format!("goof_cell({})", c),
+                (Capture::Outer(i), _) => format!("self->env[{}]", i),
+                (Capture::Itself, _) => "goof_cell(goof_fn(self))".to_string(),
+            };
+            self.line(format!("{}.as.fn->env[{}] = {};", closure, i, cell));
+        }
+        closure
+    }
+
+    /// Compiles `e`, returns a C expression for its value that later code
+    /// cannot change: a constant, a C variable bound by `let` or a new
+    /// temporary
+    fn expression(&mut self, e: &E) -> String {
+        match e {
+            E::LITERAL(v, span) => match i32::try_from(*v) {
+                Ok(v) => format!("goof_int({})", v),
+                Err(_) => {
+                    let kind = RuntimeErrorKind::LiteralOutOfRange(*v);
+                    self.fail(kind, *span);
+                    "goof_unit()".to_string()
+                }
+            },
+            E::BOOL(b, _) => format!("goof_bool({})", *b as i32),
+            E::PAREN(e, _) => self.expression(e),
+            // `-2147483648` is the negation of a literal that is out of range on its own
+            E::UNARY(OP::MINUS, v, _) if matches!(**v, E::LITERAL(2147483648, _)) => {
+                "goof_int(INT32_MIN)".to_string()
+            }
+            E::UNARY(op, v, span) => {
+                let v = self.expression(v);
+                match op {
+                    OP::MINUS => self.temporary(format!("goof_neg({}, {})", v, Self::site(*span))),
+                    _ => v,
+                }
+            }
+            E::BINARY(left, op, right, span) => {
+                let (a, b) = (self.expression(left), self.expression(right));
+                let value = match op {
+                    OP::PLUS => format!("goof_add({}, {}, {})", a, b, Self::site(*span)),
+                    OP::MINUS => format!("goof_sub({}, {}, {})", a, b, Self::site(*span)),
+                    OP::MULT => format!("goof_mul({}, {}, {})", a, b, Self::site(*span)),
+                    OP::DIV => format!("goof_div({}, {}, {})", a, b, Self::site(*span)),
+                    OP::POW => format!("goof_pow({}, {}, {})", a, b, Self::site(*span)),
+                    OP::EQUALITY => format!("goof_equal({}, {})", a, b),
+                    OP::GREATER => format!("goof_bool({}.as.i > {}.as.i)", a, b),
+                    OP::LESS => format!("goof_bool({}.as.i < {}.as.i)", a, b),
+                    OP::GEQ => format!("goof_bool({}.as.i >= {}.as.i)", a, b),
+                    OP::LEQ => format!("goof_bool({}.as.i <= {}.as.i)", a, b),
+                };
+                self.temporary(value)
+            }
+            E::IF(cond, then, elze, _) => {
+                let cond = self.expression(cond);
+                let t = self.fresh(None);
+                self.line(format!("goof_value {};", t));
+                self.open(format!("if ({}.as.i)", cond));
+                let then = self.expression(then);
+                self.line(format!("{} = {};", t, then));
+                self.close("} else {");
+                self.scope().indent += 1;
+                let elze = self.expression(elze);
+                self.line(format!("{} = {};", t, elze));
+                self.close("}");
+                t
+            }
+            E::LET(name, value, body, _) | E::LETREC(name, value, body, _) => {
+                let value = match e {
+                    E::LETREC(..) => self.recursive(name, value),
+                    _ => self.expression(value),
+                };
+                self.declare(name, value, false);
+                let body = self.expression(body);
+                self.scope().locals.pop();
+                body
+            }
+            E::VAR(name, _) => {
+                let depth = self.scopes.len() - 1;
+                match self.resolve(depth, name) {
+                    (Access::Local(c), false) => c,
+                    (Access::Local(c), true) => self.temporary(format!("*{}", c)),
+                    (Access::Capture(i), _) => self.temporary(format!("*self->env[{}]", i)),
+                    (Access::This, _) => "goof_fn(self)".to_string(),
+                }
+            }
+            E::FN(params, body, span) => self.closure(None, params, body, *span),
+            E::CALL(callee, args, span) => {
+                let callee = self.expression(callee);
+                let limit = message(RuntimeErrorKind::RecursionLimit);
+                self.line(format!("if (goof_depth == {})", MAX_CALL_DEPTH));
+                self.line(format!(
+                    "    goof_fail({}, {});",
+                    string(&limit),
+                    Self::site(*span)
+                ));
+                let args: Vec<String> = args.iter().map(|arg| self.expression(arg)).collect();
+                let mut types = String::from("goof_closure *");
+                let mut values = format!("{}.as.fn", callee);
+                for arg in &args {
+                    types += ", goof_value";
+                    write!(values, ", {}", arg).unwrap();
+                }
+                self.line("goof_depth++;");
+                let result = self.temporary(format!(
+                    "((goof_value (*)({})){}.as.fn->code)({})",
+                    types, callee, values
+                ));
+                self.line("goof_depth--;");
+                result
+            }
+            E::BLOCK(statements, _) => self.statements(statements),
+        }
+    }
+
+    /// The line and column arguments of the runtime routines
+    fn site(span: Span) -> String {
+        format!("{}, {}", span.line, span.col)
+    }
+
+    fn fail(&mut self, kind: RuntimeErrorKind, span: Span) {
+        let message = string(&message(kind));
+        self.line(format!("goof_fail({}, {});", message, Self::site(span)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        process::Command,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{pratt::program, resolver, tokenizer::Tokenizer, typeck};
+
+    use super::*;
+
+    fn compile_source(input: &str) -> String {
+        let tokens = Tokenizer::new(input).run().unwrap();
+        let program = program(&tokens).unwrap();
+        typeck::check(&program).unwrap();
+        compile(&program)
+    }
+
+    /// Compiles `input` to C, builds it with `cc` and runs it. Returns the
+    /// exit status, stdout and stderr.
+    fn native(input: &str) -> (i32, String, String) {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let run = RUNS.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("goofpiler-c-{}-{}", std::process::id(), run));
+        fs::create_dir_all(&dir).unwrap();
+        let (source, executable) = (dir.join("a.c"), dir.join("a"));
+        fs::write(&source, compile_source(input)).unwrap();
+        let output = Command::new("cc")
+            .args(["-std=c99", "-pedantic", "-o"])
+            .arg(&executable)
+            .arg(&source)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "cc failed on {}:\n{}",
+            input,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let output = Command::new(&executable).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        (
+            output.status.code().unwrap(),
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    }
+
+    /// Checks that the compiled program prints what the interpreter prints
+    /// and fails with the same message
+    fn test(input: &str) {
+        let tokens = Tokenizer::new(input).run().unwrap();
+        let mut out = vec![];
+        let result = resolver::execute(program(&tokens).unwrap(), &mut out);
+        let mut out = String::from_utf8(out).unwrap();
+        let (status, stdout, stderr) = native(input);
+        match result {
+            Ok(value) => {
+                if value != resolver::Value::Unit {
+                    out += &format!("{}\n", value);
+                }
+                assert_eq!(
+                    (0, out, String::new()),
+                    (status, stdout, stderr),
+                    "{}",
+                    input
+                );
+            }
+            Err(e) => {
+                let message = format!("error: {}\n --> {}:{}\n", e, e.span.line, e.span.col);
+                assert_eq!(
+                    (EXIT_RUNTIME, out, message),
+                    (status, stdout, stderr),
+                    "{}",
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        test("1 + 2 * 3");
+        test("7 / -2");
+        test("-7 / 2");
+        test("2 ^ 30");
+        test("(-2) ^ 31");
+        test("(-3) ^ 3");
+        test("3 ^ 0");
+        test("-1 ^ 2147483647");
+        test("-2147483648");
+        test("+5 - -5");
+    }
+
+    #[test]
+    fn comparisons() {
+        test("1 < 2");
+        test("2 <= 1");
+        test("(1 == 1) == (2 > 3)");
+        test("{} == {}");
+        test("let f = fn => 1; let g = fn => 1; f == f");
+        test("let f = fn => 1; let g = fn => 1; f == g");
+    }
+
+    #[test]
+    fn runtime_errors() {
+        test("2147483647 + 1");
+        test("-2147483648 - 1");
+        test("65536 * 32768");
+        test("1 / 0");
+        test("-2147483648 / -1");
+        test("-(-2147483648)");
+        test("2 ^ 31");
+        test("2 ^ -3");
+        test("4294967295");
+        test("print 1; 1 / (1 - 1)");
+    }
+
+    #[test]
+    fn statements() {
+        test("print 1; print true; print {}; {}");
+        test("var x = 1; x + { x = 10; x }");
+        test("let x = 5; let f = fn => x; let x = 6; f() + x");
+        test(
+            "var i = 0; var s = 0; while i < 10 { i = i + 1; \
+             if i == 5 | { continue; } else {}; if i == 8 | { break; } else {}; s = s + i }; s",
+        );
+    }
+
+    #[test]
+    fn functions() {
+        test("let rec fact = fn n => if n == 0 | 1 else (n * fact(n - 1)); fact(12)");
+        test("let rec fact = fn n => if n == 0 | 1 else (n * fact(n - 1)); fact(13)");
+        test("let rec f = fn a, b => if a == 0 | b else f(a - 1, b * 2); f(10, 1)");
+        test("let make = fn x => fn y => fn z => x + y + z; make(1)(2)(3)");
+        test("let rec f = fn n => if n == 0 | 0 else ((fn => f(n - 1))() + 1); f(5)");
+        test("let show = fn x => { print x; x }; show(1); show(true); show(fn => 1); show({})");
+        test("let g = fn x => x; let rec h = g; print h; h == g");
+        test(&format!(
+            "let rec f = fn n => if n == 0 | 0 else 1 + f(n - 1); f({})",
+            MAX_CALL_DEPTH - 1
+        ));
+        test("let rec f = fn n => 1 + f(n); f(0)");
+    }
+
+    #[test]
+    fn closures() {
+        test("var x = 0; let inc = fn => { x = x + 1; x }; inc(); inc(); print x; inc");
+        test("var c = 0; let counter = fn => fn => { c = c + 1; c }; let a = counter(); a(); a(); counter()()");
+        test("let adder = fn n => { var total = n; fn k => { total = total + k; total } }; let a = adder(10); a(1); a(2)");
+    }
+
+    #[test]
+    fn listing() {
+        let source = compile_source("let y = 2; let rec f = fn x => x * y; f(3)");
+        assert!(
+            source.contains(
+                "/* fn f(x) at 1:24 */
+static goof_value goof_fn1(goof_closure *self, goof_value x_2) {
+    (void)self;
+    goof_value t3 = *self->env[0];
+    goof_value t4 = goof_mul(x_2, t3, 1, 32);
+    return t4;
+}
+"
+            ),
+            "{}",
+            source
+        );
+        assert!(
+            source.contains(
+                "    goof_value y_1 = goof_int(2);
+    goof_value t5 = goof_fn(goof_closure_new((void (*)(void))goof_fn1, \"f\", 1));
+    t5.as.fn->env[0] = goof_cell(y_1);
+    goof_value f_6 = t5;
+"
+            ),
+            "{}",
+            source
+        );
+    }
+}
@@ -0,0 +1,425 @@
+use std::{
+    env, fmt,
+    io::{self, IsTerminal},
+    path::Path,
+};
+
+pub const USAGE: &str = "\
+usage: goofpiler [OPTIONS] <FILE>
+       goofpiler [OPTIONS] -e <PROGRAM>
+       goofpiler build [OPTIONS] <FILE> [-o <OUTPUT>]
+
+`build` compiles the program to a static x86-64 Linux executable, to a
+WebAssembly module exporting `main` with `--target wasm`, or to C source
+whose `main` prints the value with `--target c`. `--target` on its own
+compiles the same way, without `build`.
+
+options:
+  -e <PROGRAM>             run PROGRAM instead of reading a file
+  -o <OUTPUT>              where `build` writes the executable (default: the
+                           input file without its extension, a.out for -e,
+                           with a .wasm or .c extension for wasm and c)
+  --target <x86-64|wasm|c> what `build` and `--emit asm` compile to, asm is
+                           the text format for wasm and the source for c
+                           (default: x86-64)
+  --emit <tokens|ast|types|bytecode|asm|value>
+                           stop after the given stage and print its output,
+                           types are those of the top-level bindings and of
+                           the program, asm is x86-64 assembly for Linux
+                           (default: value)
+  --parser <pratt|lr|lalr> parser used to build the syntax tree, lr and lalr
+                           only parse single expressions (default: pratt)
+  --engine <resolver|vm>   run the syntax tree directly or compile it to
+                           bytecode for the stack machine (default: resolver)
+  --color <auto|always|never>
+                           color error messages (default: auto)
+  -h, --help               print this message";
+
+// Exit codes, one per stage that can fail
+pub const EXIT_IO: u8 = 1;
+pub const EXIT_USAGE: u8 = 2;
+pub const EXIT_LEX: u8 = 3;
+pub const EXIT_PARSE: u8 = 4;
+pub const EXIT_RUNTIME: u8 = 5;
+pub const EXIT_TYPE: u8 = 6;
+pub const EXIT_CODEGEN: u8 = 7;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Input {
+    File(String),
+    Expr(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Types,
+    Bytecode,
+    Asm,
+    Value,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ParserKind {
+    Pratt,
+    LR,
+    LALR,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Engine {
+    Resolver,
+    VM,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Target {
+    X86_64,
+    Wasm,
+    C,
+}
+
+impl Target {
+    /// The extension of what `build` writes
+    fn extension(self) -> &'static str {
+        match self {
+            Target::X86_64 => "",
+            Target::Wasm => "wasm",
+            Target::C => "c",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    /// Auto enables color when stderr is a terminal and `NO_COLOR` is unset
+    pub fn enabled(self) -> bool {
+        match self {
+            Color::Always => true,
+            Color::Never => false,
+            Color::Auto => io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Run(Options),
+    /// Compile to an executable written to the path
+    Build(Options, String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Options {
+    pub input: Input,
+    pub emit: Emit,
+    pub parser: ParserKind,
+    pub engine: Engine,
+    pub target: Target,
+    pub color: Color,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn usage_error<T>(message: impl Into<String>) -> Result<T, UsageError> {
+    Err(UsageError(message.into()))
+}
+
+/// Parses the command line arguments, not including the program name.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, UsageError> {
+    let mut args = args.into_iter().peekable();
+    let build = args.next_if(|arg| arg == "build").is_some();
+    let mut output = None;
+    let mut input = None;
+    let mut emit = Emit::Value;
+    let mut parser = ParserKind::Pratt;
+    let mut engine = Engine::Resolver;
+    let mut target = None;
+    let mut color = Color::Auto;
+
+    while let Some(arg) = args.next() {
+        // allow both `--emit ast` and `--emit=ast`
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| match inline.clone().or_else(|| args.next()) {
+            Some(v) => Ok(v),
+            None => usage_error(format!("`{}` expects a value", name)),
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-e" => {
+                let expr = value("-e")?;
+                set_input(&mut input, Input::Expr(expr))?;
+            }
+            "-o" => output = Some(value("-o")?),
+            "--emit" => {
+                emit = match value("--emit")?.as_str() {
+                    "tokens" => Emit::Tokens,
+                    "ast" => Emit::Ast,
+                    "types" => Emit::Types,
+                    "bytecode" => Emit::Bytecode,
+                    "asm" => Emit::Asm,
+                    "value" => Emit::Value,
+                    other => return usage_error(format!("unknown stage `{}` for --emit", other)),
+                }
+            }
+            "--parser" => {
+                parser = match value("--parser")?.as_str() {
+                    "pratt" => ParserKind::Pratt,
+                    "lr" => ParserKind::LR,
+                    "lalr" => ParserKind::LALR,
+                    other => return usage_error(format!("unknown parser `{}`", other)),
+                }
+            }
+            "--engine" => {
+                engine = match value("--engine")?.as_str() {
+                    "resolver" => Engine::Resolver,
+                    "vm" => Engine::VM,
+                    other => return usage_error(format!("unknown engine `{}`", other)),
+                }
+            }
+            "--target" => {
+                target = match value("--target")?.as_str() {
+                    "x86-64" => Some(Target::X86_64),
+                    "wasm" => Some(Target::Wasm),
+                    "c" => Some(Target::C),
+                    other => return usage_error(format!("unknown target `{}`", other)),
+                }
+            }
+            "--color" => {
+                color = match value("--color")?.as_str() {
+                    "auto" => Color::Auto,
+                    "always" => Color::Always,
+                    "never" => Color::Never,
+                    other => return usage_error(format!("unknown color choice `{}`", other)),
+                }
+            }
+            _ if flag.starts_with('-') && flag.len() > 1 => {
+                return usage_error(format!("unknown option `{}`", flag))
+            }
+            _ => set_input(&mut input, Input::File(arg))?,
+        }
+    }
+
+    let Some(input) = input else {
+        return usage_error("no input given");
+    };
+    // `--target` on its own compiles like `build`
+    let build = build || (target.is_some() && emit == Emit::Value && engine == Engine::Resolver);
+    if !build && output.is_some() {
+        return usage_error("`-o` is only used by `build`");
+    }
+    if build && (emit != Emit::Value || engine != Engine::Resolver) {
+        return usage_error("`--emit` and `--engine` cannot be used with `build`");
+    }
+    if !build && emit != Emit::Asm && target.is_some() {
+        return usage_error("`--target` is only used by `build` and `--emit asm`");
+    }
+    let target = target.unwrap_or(Target::X86_64);
+    let output = match (build, &input, output) {
+        (false, ..) => None,
+        (true, _, Some(output)) => Some(output),
+        (true, Input::Expr(_), None) if target == Target::X86_64 => Some("a.out".to_string()),
+        (true, Input::Expr(_), None) => Some(format!("a.{}", target.extension())),
+        (true, Input::File(path), None) => {
+            // the file name of the input with the extension of the target
+            let name = Path::new(path).file_name().map(Path::new);
+            match name.map(|name| name.with_extension(target.extension())) {
+                Some(output) if Some(output.as_path()) != name => {
+                    Some(output.to_string_lossy().into_owned())
+                }
+                _ => return usage_error("give the executable a name with `-o`"),
+            }
+        }
+    };
+    let options = Options {
+        input,
+        emit,
+        parser,
+        engine,
+        target,
+        color,
+    };
+    match output {
+        Some(output) => Ok(Command::Build(options, output)),
+        None => Ok(Command::Run(options)),
+    }
+}
+
+fn set_input(slot: &mut Option<Input>, input: Input) -> Result<(), UsageError> {
+    if slot.is_some() {
+        return usage_error("only one input file or `-e` expression can be given");
+    }
+    *slot = Some(input);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test(args: &[&str]) -> Result<Command, UsageError> {
+        parse(args.iter().map(|a| a.to_string()))
+    }
+
+    fn run(args: &[&str]) -> Options {
+        match test(args) {
+            Ok(Command::Run(options)) => options,
+            other => panic!("expected options, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn file_input() {
+        let options = run(&["prog.goof"]);
+        assert_eq!(Input::File("prog.goof".into()), options.input);
+        assert_eq!(Emit::Value, options.emit);
+        assert_eq!(ParserKind::Pratt, options.parser);
+    }
+
+    #[test]
+    fn expr_input() {
+        let options = run(&["-e", "1 + 2"]);
+        assert_eq!(Input::Expr("1 + 2".into()), options.input);
+    }
+
+    #[test]
+    fn emit() {
+        assert_eq!(Emit::Tokens, run(&["--emit", "tokens", "-e", "1"]).emit);
+        assert_eq!(Emit::Ast, run(&["-e", "1", "--emit=ast"]).emit);
+        assert_eq!(Emit::Types, run(&["-e", "1", "--emit=types"]).emit);
+        assert_eq!(Emit::Bytecode, run(&["-e", "1", "--emit", "bytecode"]).emit);
+        assert_eq!(Emit::Asm, run(&["--emit=asm", "a.goof"]).emit);
+    }
+
+    #[test]
+    fn lr_parser() {
+        assert_eq!(ParserKind::LR, run(&["--parser", "lr", "a.goof"]).parser);
+    }
+
+    #[test]
+    fn lalr_parser() {
+        assert_eq!(ParserKind::LALR, run(&["--parser=lalr", "a.goof"]).parser);
+    }
+
+    #[test]
+    fn engine() {
+        assert_eq!(Engine::Resolver, run(&["a.goof"]).engine);
+        assert_eq!(Engine::VM, run(&["--engine", "vm", "a.goof"]).engine);
+        assert!(test(&["--engine=jit", "a.goof"]).is_err());
+    }
+
+    #[test]
+    fn build() {
+        let build = |args: &[&str]| match test(args) {
+            Ok(Command::Build(options, output)) => (options.input, output),
+            other => panic!("expected a build, got {:?}", other),
+        };
+        let file = Input::File("dir/prog.goof".into());
+        assert_eq!(
+            (file.clone(), "prog".into()),
+            build(&["build", "dir/prog.goof"])
+        );
+        assert_eq!(
+            (file, "out".into()),
+            build(&["build", "dir/prog.goof", "-o", "out"])
+        );
+        assert_eq!("a.out", build(&["build", "-e", "1"]).1);
+        assert!(test(&["build", "prog"]).is_err());
+        assert!(test(&["-o", "out", "prog.goof"]).is_err());
+        assert!(test(&["build", "--emit=ast", "prog.goof"]).is_err());
+    }
+
+    #[test]
+    fn target() {
+        assert_eq!(Target::X86_64, run(&["--emit=asm", "a.goof"]).target);
+        assert_eq!(
+            Target::Wasm,
+            run(&["--emit=asm", "--target", "wasm", "a.goof"]).target
+        );
+        match test(&["build", "--target=wasm", "dir/prog.goof"]) {
+            Ok(Command::Build(options, output)) => {
+                assert_eq!(Target::Wasm, options.target);
+                assert_eq!("prog.wasm", output);
+            }
+            other => panic!("expected a build, got {:?}", other),
+        }
+        match test(&["build", "--target", "c", "-e", "1"]) {
+            Ok(Command::Build(options, output)) => {
+                assert_eq!(Target::C, options.target);
+                assert_eq!("a.c", output);
+            }
+            other => panic!("expected a build, got {:?}", other),
+        }
+        // without `build`, `--target` compiles too
+        match test(&["--target", "wasm", "a.goof"]) {
+            Ok(Command::Build(options, output)) => {
+                assert_eq!(Target::Wasm, options.target);
+                assert_eq!("a.wasm", output);
+            }
+            other => panic!("expected a build, got {:?}", other),
+        }
+        match test(&["--target=c", "-e", "1", "-o", "out.c"]) {
+            Ok(Command::Build(options, output)) => {
+                assert_eq!(Target::C, options.target);
+                assert_eq!("out.c", output);
+            }
+            other => panic!("expected a build, got {:?}", other),
+        }
+        assert!(test(&["--target", "wasm", "--emit", "ast", "a.goof"]).is_err());
+        assert!(test(&["--target", "wasm", "--engine", "vm", "a.goof"]).is_err());
+        assert!(test(&["build", "--target", "arm", "a.goof"]).is_err());
+    }
+
+    #[test]
+    fn color() {
+        assert_eq!(Color::Auto, run(&["a.goof"]).color);
+        assert_eq!(Color::Never, run(&["--color", "never", "a.goof"]).color);
+        assert_eq!(Color::Always, run(&["--color=always", "a.goof"]).color);
+    }
+
+    #[test]
+    fn help() {
+        assert_eq!(Ok(Command::Help), test(&["-e", "1", "--help"]));
+    }
+
+    #[test]
+    fn missing_input() {
+        assert!(test(&["--emit", "ast"]).is_err());
+    }
+
+    #[test]
+    fn two_inputs() {
+        assert!(test(&["a.goof", "-e", "1"]).is_err());
+    }
+
+    #[test]
+    fn missing_value() {
+        assert!(test(&["-e"]).is_err());
+    }
+
+    #[test]
+    fn unknown_stage() {
+        assert!(test(&["--emit", "bytes", "-e", "1"]).is_err());
+    }
+}
@@ -0,0 +1,979 @@
+use std::fmt;
+
+use crate::{
+    diagnostic::Diagnostic,
+    pratt::{E, S},
+    resolver::{RuntimeError, RuntimeErrorKind},
+    span::Span,
+    tokenizer::OP,
+    typeck::{Type, Types},
+};
+
+/// The exit status of a compiled program that fails, the same as the
+/// interpreter's
+const EXIT_RUNTIME: i32 = 5;
+
+// Linux system call numbers
+const SYS_WRITE: i32 = 1;
+const SYS_EXIT: i32 = 60;
+
+const STDOUT: i32 = 1;
+const STDERR: i32 = 2;
+
+/// The registers the generated code uses, in the order of their encoding
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Reg {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+}
+
+impl Reg {
+    fn name(self, width: Width) -> &'static str {
+        let names = match self {
+            Reg::Rax => ["al", "eax", "rax"],
+            Reg::Rcx => ["cl", "ecx", "rcx"],
+            Reg::Rdx => ["dl", "edx", "rdx"],
+            Reg::Rbx => ["bl", "ebx", "rbx"],
+            Reg::Rsp => ["spl", "esp", "rsp"],
+            Reg::Rbp => ["bpl", "ebp", "rbp"],
+            Reg::Rsi => ["sil", "esi", "rsi"],
+            Reg::Rdi => ["dil", "edi", "rdi"],
+        };
+        names[width as usize]
+    }
+}
+
+/// The size of an operation, its suffix in AT&T syntax
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Width {
+    B,
+    L,
+    Q,
+}
+
+impl fmt::Display for Width {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Width::B => write!(f, "b"),
+            Width::L => write!(f, "l"),
+            Width::Q => write!(f, "q"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Operand {
+    Imm(i32),
+    Reg(Reg),
+    /// The memory at an offset from a register
+    Mem(Reg, i32),
+    /// The address of a label in the data section, relative to `%rip`
+    Data(String),
+}
+
+impl Operand {
+    fn show(&self, width: Width) -> String {
+        match self {
+            Operand::Imm(v) => format!("${}", v),
+            Operand::Reg(r) => format!("%{}", r.name(width)),
+            Operand::Mem(r, 0) => format!("(%{})", r.name(Width::Q)),
+            Operand::Mem(r, offset) => format!("{}(%{})", offset, r.name(Width::Q)),
+            Operand::Data(label) => format!("{}(%rip)", label),
+        }
+    }
+}
+
+/// The conditions of jumps and `set` instructions
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Cond {
+    E,
+    NE,
+    L,
+    LE,
+    G,
+    GE,
+    /// Overflow
+    O,
+    /// Negative
+    S,
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Cond::E => "e",
+            Cond::NE => "ne",
+            Cond::L => "l",
+            Cond::LE => "le",
+            Cond::G => "g",
+            Cond::GE => "ge",
+            Cond::O => "o",
+            Cond::S => "s",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The x86-64 instructions the code generator emits. Operands are in AT&T
+/// order, source first.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Inst {
+    Label(String),
+    Mov(Width, Operand, Operand),
+    /// Sign-extends a 32-bit register to 64 bits
+    Movslq(Reg, Reg),
+    /// Zero-extends the low byte of a register to 32 bits
+    Movzbl(Reg, Reg),
+    Lea(Operand, Reg),
+    Add(Width, Operand, Operand),
+    Sub(Width, Operand, Operand),
+    Imul(Width, Operand, Reg),
+    Neg(Width, Operand),
+    Cmp(Width, Operand, Operand),
+    Test(Width, Operand, Operand),
+    /// Shifts right by a constant, filling with zeros
+    Shr(Width, u8, Operand),
+    /// Sign-extends `%eax` into `%edx` before a division
+    Cltd,
+    /// Signed division of `%edx:%eax`
+    Idiv(Width, Operand),
+    /// Unsigned division of `%rdx:%rax`
+    Div(Width, Operand),
+    Set(Cond, Reg),
+    Jmp(String),
+    J(Cond, String),
+    Call(String),
+    Ret,
+    Push(Reg),
+    Pop(Reg),
+    Syscall,
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let two = |f: &mut fmt::Formatter<'_>, name: &str, w: &Width, a: &Operand, b: &Operand| {
+            write!(f, "    {}{} {}, {}", name, w, a.show(*w), b.show(*w))
+        };
+        match self {
+            Inst::Label(label) => write!(f, "{}:", label),
+            Inst::Mov(w, a, b) => two(f, "mov", w, a, b),
+            Inst::Movslq(a, b) => {
+                write!(f, "    movslq %{}, %{}", a.name(Width::L), b.name(Width::Q))
+            }
+            Inst::Movzbl(a, b) => {
+                write!(f, "    movzbl %{}, %{}", a.name(Width::B), b.name(Width::L))
+            }
+            Inst::Lea(a, b) => write!(f, "    leaq {}, %{}", a.show(Width::Q), b.name(Width::Q)),
+            Inst::Add(w, a, b) => two(f, "add", w, a, b),
+            Inst::Sub(w, a, b) => two(f, "sub", w, a, b),
+            Inst::Imul(w, a, b) => two(f, "imul", w, a, &Operand::Reg(*b)),
+            Inst::Neg(w, a) => write!(f, "    neg{} {}", w, a.show(*w)),
+            Inst::Cmp(w, a, b) => two(f, "cmp", w, a, b),
+            Inst::Test(w, a, b) => two(f, "test", w, a, b),
+            Inst::Shr(w, n, a) => write!(f, "    shr{} ${}, {}", w, n, a.show(*w)),
+            Inst::Cltd => write!(f, "    cltd"),
+            Inst::Idiv(w, a) => write!(f, "    idiv{} {}", w, a.show(*w)),
+            Inst::Div(w, a) => write!(f, "    div{} {}", w, a.show(*w)),
+            Inst::Set(c, r) => write!(f, "    set{} %{}", c, r.name(Width::B)),
+            Inst::Jmp(label) => write!(f, "    jmp {}", label),
+            Inst::J(c, label) => write!(f, "    j{} {}", c, label),
+            Inst::Call(label) => write!(f, "    call {}", label),
+            Inst::Ret => write!(f, "    ret"),
+            Inst::Push(r) => write!(f, "    pushq %{}", r.name(Width::Q)),
+            Inst::Pop(r) => write!(f, "    popq %{}", r.name(Width::Q)),
+            Inst::Syscall => write!(f, "    syscall"),
+        }
+    }
+}
+
+/// A compiled program: its code, which starts at `_start`, and the
+/// read-only data it refers to
+#[derive(Debug)]
+pub struct Assembly {
+    pub text: Vec<Inst>,
+    pub data: Vec<(String, Vec<u8>)>,
+}
+
+/// The program as a GNU `as` source file
+impl fmt::Display for Assembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "    .text")?;
+        writeln!(f, "    .globl _start")?;
+        for inst in &self.text {
+            writeln!(f, "{}", inst)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "    .section .rodata")?;
+        for (label, bytes) in &self.data {
+            writeln!(f, "{}:", label)?;
+            write!(f, "    .ascii \"")?;
+            for &b in bytes {
+                match b {
+                    b'"' | b'\\' => write!(f, "\\{}", b as char)?,
+                    b' '..=b'~' => write!(f, "{}", b as char)?,
+                    _ => write!(f, "\\{:03o}", b)?,
+                }
+            }
+            writeln!(f, "\"")?;
+        }
+        Ok(())
+    }
+}
+
+/// A construct a backend cannot compile
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CodegenError {
+    /// What is not supported, e.g. "functions"
+    pub construct: &'static str,
+    /// The backend, e.g. "native"
+    pub backend: &'static str,
+    pub span: Span,
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} are not supported by the {} backend",
+            self.construct, self.backend
+        )
+    }
+}
+
+impl From<&CodegenError> for Diagnostic {
+    fn from(e: &CodegenError) -> Diagnostic {
+        Diagnostic::error(e.to_string())
+            .primary(e.span, "not supported")
+            .help("run the program with the resolver or the bytecode VM instead")
+    }
+}
+
+/// The code that reports a runtime error and exits, jumped to from where
+/// it happens
+struct Failure {
+    label: String,
+    /// The message up to the number that is only known at runtime, which
+    /// is in `%ecx` when jumping here
+    message: String,
+    dynamic: bool,
+    span: Span,
+}
+
+struct Local {
+    name: String,
+    slot: usize,
+}
+
+struct Loop {
+    start: String,
+    end: String,
+}
+
+struct Compiler<'a> {
+    types: &'a Types,
+    text: Vec<Inst>,
+    data: Vec<(String, Vec<u8>)>,
+    failures: Vec<Failure>,
+    labels: usize,
+    locals: Vec<Local>,
+    /// Slots of the stack frame in use, by locals and by the left operands
+    /// of binary operators
+    slots: usize,
+    /// The most slots used at once, which sizes the frame
+    frame: usize,
+    loops: Vec<Loop>,
+}
+
+/// Compiles a program that passed the type checker to x86-64 code for
+/// Linux. The executable prints the value of the program like the
+/// interpreter does, and exits with status 5 after printing the message of
+/// a runtime error. Values are 32-bit integers in `%eax`, with booleans as
+/// 0 or 1 and unit as 0.
+pub fn compile(program: &[S], types: &Types) -> Result<Assembly, CodegenError> {
+    let mut c = Compiler {
+        types,
+        text: vec![],
+        data: vec![],
+        failures: vec![],
+        labels: 0,
+        locals: vec![],
+        slots: 0,
+        frame: 0,
+        loops: vec![],
+    };
+    c.emit(Inst::Label("_start".into()));
+    c.emit(Inst::Push(Reg::Rbp));
+    c.emit(Inst::Mov(
+        Width::Q,
+        Operand::Reg(Reg::Rsp),
+        Operand::Reg(Reg::Rbp),
+    ));
+    // the size of the frame is known at the end
+    let reserve = c.text.len();
+    c.emit(Inst::Sub(Width::Q, Operand::Imm(0), Operand::Reg(Reg::Rsp)));
+    c.statements(program)?;
+    // like the interpreter, unit values are not printed
+    if types.program != Type::Unit {
+        c.print(&types.program, STDOUT);
+    }
+    c.exit(0);
+    let size = (c.frame * 8).next_multiple_of(16) as i32;
+    c.text[reserve] = Inst::Sub(Width::Q, Operand::Imm(size), Operand::Reg(Reg::Rsp));
+
+    for failure in std::mem::take(&mut c.failures) {
+        c.fail(failure);
+    }
+    c.runtime();
+    Ok(Assembly {
+        text: c.text,
+        data: c.data,
+    })
+}
+
+impl Compiler<'_> {
+    fn emit(&mut self, inst: Inst) {
+        self.text.push(inst);
+    }
+
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    /// Adds `bytes` to the data section, returns its label
+    fn string(&mut self, bytes: &[u8]) -> String {
+        if let Some((label, _)) = self.data.iter().find(|(_, b)| b == bytes) {
+            return label.clone();
+        }
+        let label = format!(".Lstr{}", self.data.len());
+        self.data.push((label.clone(), bytes.to_vec()));
+        label
+    }
+
+    fn slot(slot: usize) -> Operand {
+        Operand::Mem(Reg::Rbp, -8 * (slot as i32 + 1))
+    }
+
+    /// Takes a slot of the frame, freed with `self.slots -= 1`
+    fn push_slot(&mut self) -> usize {
+        self.slots += 1;
+        self.frame = self.frame.max(self.slots);
+        self.slots - 1
+    }
+
+    /// Jumps to a runtime error if `cond` holds
+    fn fail_if(&mut self, cond: Cond, kind: RuntimeErrorKind, span: Span) {
+        let label = self.failure(kind, span);
+        self.emit(Inst::J(cond, label));
+    }
+
+    /// Adds the code reporting a runtime error, returns its label
+    fn failure(&mut self, kind: RuntimeErrorKind, span: Span) -> String {
+        let label = self.label();
+        let (message, dynamic) = match kind {
+            // the exponent is printed at runtime
+            RuntimeErrorKind::NegativeExponent(_) => {
+                let message = RuntimeError::new(RuntimeErrorKind::NegativeExponent(0), span);
+                let message = message.to_string();
+                (message.strip_suffix('0').unwrap().to_string(), true)
+            }
+            kind => (RuntimeError::new(kind, span).to_string(), false),
+        };
+        self.failures.push(Failure {
+            label: label.clone(),
+            message,
+            dynamic,
+            span,
+        });
+        label
+    }
+
+    /// Statements in order, leaving the value of the last one in `%eax`
+    fn statements(&mut self, statements: &[S]) -> Result<(), CodegenError> {
+        let (locals, slots) = (self.locals.len(), self.slots);
+        let mut value = false;
+        for statement in statements {
+            value = matches!(statement, S::EXPR(_));
+            self.statement(statement)?;
+        }
+        if !value {
+            self.emit(Inst::Mov(Width::L, Operand::Imm(0), Operand::Reg(Reg::Rax)));
+        }
+        self.locals.truncate(locals);
+        self.slots = slots;
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &S) -> Result<(), CodegenError> {
+        match statement {
+            S::EXPR(e) => self.expression(e)?,
+            S::LET(name, value, _) | S::VAR(name, value, _) => {
+                self.expression(value)?;
+                self.declare(name);
+            }
+            S::LETREC(_, _, span) => return unsupported("recursive bindings", *span),
+            S::PRINT(e, _) => {
+                self.expression(e)?;
+                self.print(
+                    self.types.get(e).expect("every expression has a type"),
+                    STDOUT,
+                );
+            }
+            S::ASSIGN(name, value, _) => {
+                self.expression(value)?;
+                let slot = self.lookup(name);
+                self.emit(Inst::Mov(
+                    Width::L,
+                    Operand::Reg(Reg::Rax),
+                    Self::slot(slot),
+                ));
+            }
+            S::WHILE(cond, body, _) => {
+                let (start, end) = (self.label(), self.label());
+                self.emit(Inst::Label(start.clone()));
+                self.expression(cond)?;
+                self.test(end.clone());
+                self.loops.push(Loop {
+                    start: start.clone(),
+                    end: end.clone(),
+                });
+                self.statements(body)?;
+                self.loops.pop();
+                self.emit(Inst::Jmp(start));
+                self.emit(Inst::Label(end));
+            }
+            // the type checker only accepts them inside loops
+            S::BREAK(_) => {
+                let end = self.loops.last().unwrap().end.clone();
+                self.emit(Inst::Jmp(end));
+            }
+            S::CONTINUE(_) => {
+                let start = self.loops.last().unwrap().start.clone();
+                self.emit(Inst::Jmp(start));
+            }
+        }
+        Ok(())
+    }
+
+    /// Binds `name` to the value in `%eax`
+    fn declare(&mut self, name: &str) {
+        let slot = self.push_slot();
+        self.emit(Inst::Mov(
+            Width::L,
+            Operand::Reg(Reg::Rax),
+            Self::slot(slot),
+        ));
+        self.locals.push(Local {
+            name: name.to_string(),
+            slot,
+        });
+    }
+
+    /// The slot of a variable, which the type checker found in scope
+    fn lookup(&self, name: &str) -> usize {
+        self.locals
+            .iter()
+            .rev()
+            .find(|l| l.name == name)
+            .unwrap()
+            .slot
+    }
+
+    /// Jumps to `label` if `%eax` is false
+    fn test(&mut self, label: String) {
+        let eax = Operand::Reg(Reg::Rax);
+        self.emit(Inst::Test(Width::L, eax.clone(), eax));
+        self.emit(Inst::J(Cond::E, label));
+    }
+
+    fn expression(&mut self, e: &E) -> Result<(), CodegenError> {
+        let eax = Operand::Reg(Reg::Rax);
+        let ecx = Operand::Reg(Reg::Rcx);
+        match e {
+            E::LITERAL(v, span) => match i32::try_from(*v) {
+                Ok(v) => self.emit(Inst::Mov(Width::L, Operand::Imm(v), eax)),
+                Err(_) => {
+                    let label = self.failure(RuntimeErrorKind::LiteralOutOfRange(*v), *span);
+                    self.emit(Inst::Jmp(label));
+                }
+            },
+            E::BOOL(b, _) => self.emit(Inst::Mov(Width::L, Operand::Imm(*b as i32), eax)),
+            E::PAREN(e, _) => self.expression(e)?,
+            // `-2147483648` is the negation of a literal that is out of range on its own
+            E::UNARY(OP::MINUS, v, _) if matches!(**v, E::LITERAL(2147483648, _)) => {
+                self.emit(Inst::Mov(Width::L, Operand::Imm(i32::MIN), eax))
+            }
+            E::UNARY(op, v, span) => {
+                self.expression(v)?;
+                if *op == OP::MINUS {
+                    self.emit(Inst::Neg(Width::L, eax));
+                    self.fail_if(Cond::O, RuntimeErrorKind::Overflow(OP::MINUS), *span);
+                }
+            }
+            E::BINARY(left, op, right, span) => {
+                self.expression(left)?;
+                let slot = self.push_slot();
+                self.emit(Inst::Mov(Width::L, eax.clone(), Self::slot(slot)));
+                self.expression(right)?;
+                self.slots -= 1;
+                self.emit(Inst::Mov(Width::L, eax.clone(), ecx.clone()));
+                self.emit(Inst::Mov(Width::L, Self::slot(slot), eax.clone()));
+                self.binary(op, *span);
+            }
+            E::IF(cond, then, elze, _) => {
+                let (other, end) = (self.label(), self.label());
+                self.expression(cond)?;
+                self.test(other.clone());
+                self.expression(then)?;
+                self.emit(Inst::Jmp(end.clone()));
+                self.emit(Inst::Label(other));
+                self.expression(elze)?;
+                self.emit(Inst::Label(end));
+            }
+            E::LET(name, value, body, _) => {
+                self.expression(value)?;
+                self.declare(name);
+                self.expression(body)?;
+                self.locals.pop();
+                self.slots -= 1;
+            }
+            E::VAR(name, _) => {
+                let slot = self.lookup(name);
+                self.emit(Inst::Mov(Width::L, Self::slot(slot), eax));
+            }
+            E::BLOCK(statements, _) => self.statements(statements)?,
+            E::LETREC(_, _, _, span) => return unsupported("recursive bindings", *span),
+            E::FN(_, _, span) => return unsupported("functions", *span),
+            E::CALL(_, _, span) => return unsupported("function calls", *span),
+        }
+        Ok(())
+    }
+
+    /// `%eax op %ecx`, into `%eax`
+    fn binary(&mut self, op: &OP, span: Span) {
+        let eax = Operand::Reg(Reg::Rax);
+        let ecx = Operand::Reg(Reg::Rcx);
+        let edx = Operand::Reg(Reg::Rdx);
+        let overflow = RuntimeErrorKind::Overflow(op.clone());
+        let compare = |c: &mut Compiler, cond| {
+            c.emit(Inst::Cmp(Width::L, ecx.clone(), eax.clone()));
+            c.emit(Inst::Set(cond, Reg::Rax));
+            c.emit(Inst::Movzbl(Reg::Rax, Reg::Rax));
+        };
+        match op {
+            OP::PLUS => {
+                self.emit(Inst::Add(Width::L, ecx, eax));
+                self.fail_if(Cond::O, overflow, span);
+            }
+            OP::MINUS => {
+                self.emit(Inst::Sub(Width::L, ecx, eax));
+                self.fail_if(Cond::O, overflow, span);
+            }
+            OP::MULT => {
+                self.emit(Inst::Imul(Width::L, ecx, Reg::Rax));
+                self.fail_if(Cond::O, overflow, span);
+            }
+            OP::DIV => {
+                self.emit(Inst::Test(Width::L, ecx.clone(), ecx.clone()));
+                self.fail_if(Cond::E, RuntimeErrorKind::DivisionByZero, span);
+                // i32::MIN / -1 is the only overflowing division
+                let divide = self.label();
+                self.emit(Inst::Cmp(Width::L, Operand::Imm(-1), ecx.clone()));
+                self.emit(Inst::J(Cond::NE, divide.clone()));
+                self.emit(Inst::Cmp(Width::L, Operand::Imm(i32::MIN), eax));
+                self.fail_if(Cond::E, overflow, span);
+                self.emit(Inst::Label(divide));
+                self.emit(Inst::Cltd);
+                self.emit(Inst::Idiv(Width::L, ecx));
+            }
+            OP::POW => {
+                // exponentiation by squaring, which like `i32::checked_pow`
+                // only overflows if the result does
+                let (step, skip, last, end) =
+                    (self.label(), self.label(), self.label(), self.label());
+                self.emit(Inst::Test(Width::L, ecx.clone(), ecx.clone()));
+                self.fail_if(Cond::S, RuntimeErrorKind::NegativeExponent(0), span);
+                self.emit(Inst::Mov(Width::L, Operand::Imm(1), edx.clone()));
+                self.emit(Inst::J(Cond::NE, step.clone()));
+                self.emit(Inst::Mov(Width::L, edx, eax.clone()));
+                self.emit(Inst::Jmp(end.clone()));
+                self.emit(Inst::Label(step.clone()));
+                self.emit(Inst::Cmp(Width::L, Operand::Imm(1), ecx.clone()));
+                self.emit(Inst::J(Cond::LE, last.clone()));
+                self.emit(Inst::Test(Width::L, Operand::Imm(1), ecx.clone()));
+                self.emit(Inst::J(Cond::E, skip.clone()));
+                self.emit(Inst::Imul(Width::L, eax.clone(), Reg::Rdx));
+                self.fail_if(Cond::O, overflow.clone(), span);
+                self.emit(Inst::Label(skip));
+                self.emit(Inst::Shr(Width::L, 1, ecx));
+                self.emit(Inst::Imul(Width::L, eax, Reg::Rax));
+                self.fail_if(Cond::O, overflow.clone(), span);
+                self.emit(Inst::Jmp(step));
+                self.emit(Inst::Label(last));
+                self.emit(Inst::Imul(Width::L, Operand::Reg(Reg::Rdx), Reg::Rax));
+                self.fail_if(Cond::O, overflow, span);
+                self.emit(Inst::Label(end));
+            }
+            OP::EQUALITY => compare(self, Cond::E),
+            OP::GREATER => compare(self, Cond::G),
+            OP::LESS => compare(self, Cond::L),
+            OP::GEQ => compare(self, Cond::GE),
+            OP::LEQ => compare(self, Cond::LE),
+        }
+    }
+
+    /// Writes the value in `%eax` of type `t` and a newline to `fd`
+    fn print(&mut self, t: &Type, fd: i32) {
+        match t {
+            Type::Int => {
+                self.emit(Inst::Mov(
+                    Width::L,
+                    Operand::Imm(fd),
+                    Operand::Reg(Reg::Rdi),
+                ));
+                self.emit(Inst::Call("goof_print_int".into()));
+                self.write(fd, b"\n");
+            }
+            Type::Bool => {
+                let (other, end) = (self.label(), self.label());
+                self.test(other.clone());
+                self.write(fd, b"true\n");
+                self.emit(Inst::Jmp(end.clone()));
+                self.emit(Inst::Label(other));
+                self.write(fd, b"false\n");
+                self.emit(Inst::Label(end));
+            }
+            Type::Unit => self.write(fd, b"()\n"),
+            t => unreachable!("values of type `{}` cannot be compiled", t),
+        }
+    }
+
+    /// Writes `bytes` to `fd`
+    fn write(&mut self, fd: i32, bytes: &[u8]) {
+        let label = self.string(bytes);
+        self.emit(Inst::Lea(Operand::Data(label), Reg::Rsi));
+        self.emit(Inst::Mov(
+            Width::L,
+            Operand::Imm(bytes.len() as i32),
+            Operand::Reg(Reg::Rdx),
+        ));
+        self.emit(Inst::Mov(
+            Width::L,
+            Operand::Imm(fd),
+            Operand::Reg(Reg::Rdi),
+        ));
+        self.emit(Inst::Call("goof_write".into()));
+    }
+
+    fn exit(&mut self, status: i32) {
+        self.emit(Inst::Mov(
+            Width::L,
+            Operand::Imm(status),
+            Operand::Reg(Reg::Rdi),
+        ));
+        self.emit(Inst::Jmp("goof_exit".into()));
+    }
+
+    /// Reports a runtime error on stderr the way the interpreter's message
+    /// starts, and exits
+    fn fail(&mut self, failure: Failure) {
+        self.emit(Inst::Label(failure.label));
+        if failure.dynamic {
+            // `%rbx` survives the system calls
+            self.emit(Inst::Mov(
+                Width::L,
+                Operand::Reg(Reg::Rcx),
+                Operand::Reg(Reg::Rbx),
+            ));
+        }
+        self.write(STDERR, format!("error: {}", failure.message).as_bytes());
+        if failure.dynamic {
+            self.emit(Inst::Mov(
+                Width::L,
+                Operand::Reg(Reg::Rbx),
+                Operand::Reg(Reg::Rax),
+            ));
+            self.emit(Inst::Mov(
+                Width::L,
+                Operand::Imm(STDERR),
+                Operand::Reg(Reg::Rdi),
+            ));
+            self.emit(Inst::Call("goof_print_int".into()));
+        }
+        let position = format!("\n --> {}:{}\n", failure.span.line, failure.span.col);
+        self.write(STDERR, position.as_bytes());
+        self.exit(EXIT_RUNTIME);
+    }
+
+    /// The routines the generated code calls
+    fn runtime(&mut self) {
+        use Operand::{Imm, Mem, Reg as R};
+        let (rax, rcx, rdx, rbx, rsi) = (
+            R(Reg::Rax),
+            R(Reg::Rcx),
+            R(Reg::Rdx),
+            R(Reg::Rbx),
+            R(Reg::Rsi),
+        );
+
+        // write(%edi, %rsi, %rdx)
+        self.emit(Inst::Label("goof_write".into()));
+        self.emit(Inst::Mov(Width::L, Imm(SYS_WRITE), rax.clone()));
+        self.emit(Inst::Syscall);
+        self.emit(Inst::Ret);
+
+        // exit(%edi)
+        self.emit(Inst::Label("goof_exit".into()));
+        self.emit(Inst::Mov(Width::L, Imm(SYS_EXIT), rax.clone()));
+        self.emit(Inst::Syscall);
+
+        // writes %eax in decimal to %edi, the digits are written backwards
+        // below the frame pointer
+        let (digit, positive) = (self.label(), self.label());
+        self.emit(Inst::Label("goof_print_int".into()));
+        self.emit(Inst::Push(Reg::Rbp));
+        self.emit(Inst::Mov(Width::Q, R(Reg::Rsp), R(Reg::Rbp)));
+        self.emit(Inst::Sub(Width::Q, Imm(16), R(Reg::Rsp)));
+        self.emit(Inst::Movslq(Reg::Rax, Reg::Rax));
+        self.emit(Inst::Mov(Width::Q, rax.clone(), rbx.clone()));
+        self.emit(Inst::Mov(Width::Q, R(Reg::Rbp), rsi.clone()));
+        self.emit(Inst::Test(Width::Q, rax.clone(), rax.clone()));
+        self.emit(Inst::J(Cond::GE, digit.clone()));
+        self.emit(Inst::Neg(Width::Q, rax.clone()));
+        self.emit(Inst::Label(digit.clone()));
+        self.emit(Inst::Mov(Width::L, Imm(10), rcx.clone()));
+        self.emit(Inst::Mov(Width::L, Imm(0), rdx.clone()));
+        self.emit(Inst::Div(Width::Q, rcx));
+        self.emit(Inst::Add(Width::L, Imm(b'0' as i32), rdx.clone()));
+        self.emit(Inst::Sub(Width::Q, Imm(1), rsi.clone()));
+        self.emit(Inst::Mov(Width::B, rdx.clone(), Mem(Reg::Rsi, 0)));
+        self.emit(Inst::Test(Width::Q, rax.clone(), rax));
+        self.emit(Inst::J(Cond::NE, digit));
+        self.emit(Inst::Test(Width::Q, rbx.clone(), rbx));
+        self.emit(Inst::J(Cond::GE, positive.clone()));
+        self.emit(Inst::Sub(Width::Q, Imm(1), rsi.clone()));
+        self.emit(Inst::Mov(Width::B, Imm(b'-' as i32), Mem(Reg::Rsi, 0)));
+        self.emit(Inst::Label(positive));
+        self.emit(Inst::Mov(Width::Q, R(Reg::Rbp), rdx.clone()));
+        self.emit(Inst::Sub(Width::Q, rsi, rdx));
+        self.emit(Inst::Call("goof_write".into()));
+        self.emit(Inst::Mov(Width::Q, R(Reg::Rbp), R(Reg::Rsp)));
+        self.emit(Inst::Pop(Reg::Rbp));
+        self.emit(Inst::Ret);
+    }
+}
+
+fn unsupported<T>(construct: &'static str, span: Span) -> Result<T, CodegenError> {
+    Err(CodegenError {
+        construct,
+        backend: "native",
+        span,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::Write,
+        os::unix::fs::OpenOptionsExt,
+        process::Command,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{elf, encoder, pratt::program, resolver, tokenizer::Tokenizer, typeck};
+
+    use super::*;
+
+    fn compile_source(input: &str) -> Result<Assembly, CodegenError> {
+        let tokens = Tokenizer::new(input).run().unwrap();
+        let program = program(&tokens).unwrap();
+        compile(&program, &typeck::check(&program).unwrap())
+    }
+
+    /// Assembles and links `input` with `as` and `ld`, and with the
+    /// built-in encoder and ELF writer. Runs both executables, which must
+    /// behave the same, and returns the exit status, stdout and stderr.
+    fn native(input: &str) -> (i32, String, String) {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let run = RUNS.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("goofpiler-{}-{}", std::process::id(), run));
+        fs::create_dir_all(&dir).unwrap();
+        let (source, object, executable) = (dir.join("a.s"), dir.join("a.o"), dir.join("a"));
+        let assembly = compile_source(input).unwrap();
+        fs::write(&source, assembly.to_string()).unwrap();
+        let status = Command::new("as")
+            .arg("-o")
+            .arg(&object)
+            .arg(&source)
+            .status()
+            .unwrap();
+        assert!(status.success(), "as failed on {}", input);
+        let status = Command::new("ld")
+            .arg("-o")
+            .arg(&executable)
+            .arg(&object)
+            .status()
+            .unwrap();
+        assert!(status.success(), "ld failed on {}", input);
+        let built = dir.join("built");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).mode(0o755);
+        let mut file = options.open(&built).unwrap();
+        file.write_all(&elf::executable(&encoder::encode(&assembly)))
+            .unwrap();
+        drop(file);
+
+        let run = |path| {
+            let output = Command::new(path).output().unwrap();
+            (
+                output.status.code().unwrap(),
+                String::from_utf8(output.stdout).unwrap(),
+                String::from_utf8(output.stderr).unwrap(),
+            )
+        };
+        let result = run(&executable);
+        assert_eq!(result, run(&built), "built-in encoder on {}", input);
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    /// Checks that the compiled program prints what the interpreter prints
+    /// and fails with the same message
+    fn test(input: &str) {
+        let tokens = Tokenizer::new(input).run().unwrap();
+        let mut out = vec![];
+        let result = resolver::execute(program(&tokens).unwrap(), &mut out);
+        let mut out = String::from_utf8(out).unwrap();
+        let (status, stdout, stderr) = native(input);
+        match result {
+            Ok(value) => {
+                if value != resolver::Value::Unit {
+                    out += &format!("{}\n", value);
+                }
+                assert_eq!(
+                    (0, out, String::new()),
+                    (status, stdout, stderr),
+                    "{}",
+                    input
+                );
+            }
+            Err(e) => {
+                let message = format!("error: {}\n --> {}:{}\n", e, e.span.line, e.span.col);
+                assert_eq!(
+                    (EXIT_RUNTIME, out, message),
+                    (status, stdout, stderr),
+                    "{}",
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        test("1 + 2 * 3");
+        test("10 - 2 - 3");
+        test("-7 / 2");
+        test("+5 - -5");
+        test("2 ^ 3 ^ 2");
+        test("(-2) ^ 31");
+        test("0 ^ 0");
+        test("1 ^ 2000000000");
+        test("-2147483648");
+    }
+
+    #[test]
+    fn comparisons() {
+        test("1 < 2");
+        test("2 <= 1");
+        test("3 > -3");
+        test("3 >= 3");
+        test("1 + 1 == 2");
+        test("(1 == 2) == false");
+    }
+
+    #[test]
+    fn conditionals() {
+        test("if 1 < 2 | 10 else 20");
+        test("if false | true else (1 == 2)");
+        test("if 1 > 2 | 1 else if 2 > 3 | 2 else 3");
+    }
+
+    #[test]
+    fn bindings() {
+        test("let x = 5 in let y = x * 2 in x + y");
+        test("let x = 1 in (let x = 2 in x) + x");
+        test("let a = 2; let b = a ^ 10; { let a = 1; b - a }");
+    }
+
+    #[test]
+    fn statements() {
+        test("print 1; print -2147483648; print 1 == 1; print {}; 0");
+        test("print 42");
+        test("");
+    }
+
+    #[test]
+    fn loops() {
+        test("var i = 0; var s = 0; while i < 100 { i = i + 1; s = s + i; }; s");
+        test("var n = 48; var m = 18; while m > 0 { let t = m; m = n - n / m * m; n = t; }; n");
+        test(
+            "var i = 0; var s = 0; while true { i = i + 1; if i > 10 | { break; } else {}; if i == 5 | { continue; } else {}; s = s + i; }; s",
+        );
+    }
+
+    #[test]
+    fn runtime_errors() {
+        test("2147483647 + 1");
+        test("-2147483647 - 2");
+        test("65536 * 65536");
+        test("-(-2147483648)");
+        test("7 / (3 - 3)");
+        test("-2147483648 / -1");
+        test("2 ^ 31");
+        test("2 ^ (0 - 3)");
+        test("3000000000");
+        test("print 1; print 2 / 0; print 3");
+    }
+
+    #[test]
+    fn unsupported() {
+        let e = compile_source("let f = fn x => x;\nf(1)").unwrap_err();
+        assert_eq!("functions", e.construct);
+        assert_eq!(Span::new(8, 17, 1, 9), e.span);
+        assert!(compile_source("let rec x = 1; x").is_err());
+    }
+
+    #[test]
+    fn listing() {
+        let assembly = compile_source("1 + 2").unwrap().to_string();
+        assert!(
+            assembly.starts_with("    .text\n    .globl _start\n_start:\n"),
+            "{}",
+            assembly
+        );
+        assert!(
+            assembly.contains("    addl %ecx, %eax\n    jo .L1\n"),
+            "{}",
+            assembly
+        );
+        assert!(assembly.contains("    movb %dl, (%rsi)\n"), "{}", assembly);
+        assert!(
+            assembly.contains("    leaq .Lstr0(%rip), %rsi\n"),
+            "{}",
+            assembly
+        );
+        assert!(
+            assembly.contains(".Lstr0:\n    .ascii \"\\012\"\n"),
+            "{}",
+            assembly
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use std::fmt::Write;
+
+use crate::span::Span;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    /// The primary label points at the error itself and is underlined with
+    /// `^`, secondary labels give context and are underlined with `-`
+    pub primary: bool,
+}
+
+/// An error message with labelled source locations, rendered in the style
+/// of rustc:
+///
+/// ```text
+/// error: expected `|` after if-condition, found `else`
+///  --> <expr>:1:6
+///   |
+/// 1 | if 1 else 2
+///   | --   ^^^^ expected `|`
+///   | |
+///   | `if` starts here
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub help: Vec<String>,
+}
+
+/// A label resolved to its position on a source line
+struct Mark<'a> {
+    line: usize,
+    col: usize,
+    width: usize,
+    label: &'a Label,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            message: message.into(),
+            labels: vec![],
+            help: vec![],
+        }
+    }
+
+    pub fn primary(self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.label(span, message, true)
+    }
+
+    pub fn secondary(self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.label(span, message, false)
+    }
+
+    pub fn help(mut self, message: impl Into<String>) -> Diagnostic {
+        self.help.push(message.into());
+        self
+    }
+
+    fn label(mut self, span: Span, message: impl Into<String>, primary: bool) -> Diagnostic {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary,
+        });
+        self
+    }
+
+    /// Renders the diagnostic against `source`, whose name is `path`.
+    /// With `color` the output contains ANSI escape codes.
+    pub fn render(&self, source: &str, path: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            "{}{}",
+            paint(RED, "error"),
+            paint(BOLD, &format!(": {}", self.message))
+        )
+        .unwrap();
+
+        let lines: Vec<&str> = source.split('\n').collect();
+        let mut marks: Vec<Mark> = self
+            .labels
+            .iter()
+            .map(|label| mark(&lines, label))
+            .collect();
+        marks.sort_by_key(|m| (m.line, m.col));
+
+        let width = marks
+            .iter()
+            .map(|m| (m.line + 1).to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = |number: &str| paint(BLUE, &format!("{:>width$} |", number, width = width));
+
+        if let Some(primary) = marks.iter().find(|m| m.label.primary).or(marks.first()) {
+            writeln!(
+                out,
+                "{}{} {}:{}:{}",
+                " ".repeat(width),
+                paint(BLUE, "-->"),
+                path,
+                primary.line + 1,
+                primary.col + 1
+            )
+            .unwrap();
+            writeln!(out, "{}", gutter("")).unwrap();
+        }
+
+        let mut previous: Option<usize> = None;
+        for group in marks.chunk_by(|a, b| a.line == b.line) {
+            let line = group[0].line;
+            if previous.is_some_and(|p| line > p + 1) {
+                writeln!(out, "{}", paint(BLUE, "...")).unwrap();
+            }
+            previous = Some(line);
+
+            writeln!(
+                out,
+                "{} {}",
+                gutter(&(line + 1).to_string()),
+                expand_tabs(lines[line])
+            )
+            .unwrap();
+            for row in rows(group, &paint) {
+                writeln!(out, "{} {}", gutter(""), row.trim_end()).unwrap();
+            }
+        }
+
+        for help in &self.help {
+            if !marks.is_empty() {
+                writeln!(out, "{}", gutter("")).unwrap();
+            }
+            writeln!(
+                out,
+                "{}{} {}",
+                " ".repeat(width + 1),
+                paint(BOLD, "= help:"),
+                help
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', "    ")
+}
+
+/// Finds the line and column of a label, a span crossing lines is cut off at
+/// the end of its first line
+fn mark<'a>(lines: &[&str], label: &'a Label) -> Mark<'a> {
+    let mut line_start = 0;
+    for (line, text) in lines.iter().enumerate() {
+        let line_end = line_start + text.len();
+        let start = label.span.start as usize;
+        if start <= line_end || line == lines.len() - 1 {
+            let start = start.clamp(line_start, line_end);
+            let end = (label.span.end as usize).clamp(start, line_end);
+            let prefix = expand_tabs(&text[..start - line_start]);
+            let marked = expand_tabs(&text[start - line_start..end - line_start]);
+            return Mark {
+                line,
+                col: prefix.chars().count(),
+                width: marked.chars().count().max(1),
+                label,
+            };
+        }
+        line_start = line_end + 1;
+    }
+    unreachable!("source has at least one line")
+}
+
+/// The rows under a source line: first the underlines of every label with
+/// the message of the rightmost one, then the other messages hanging below
+/// their underline, right to left
+fn rows(marks: &[Mark], paint: &impl Fn(&str, &str) -> String) -> Vec<String> {
+    let style = |m: &Mark| if m.label.primary { RED } else { BLUE };
+    let mut rows = vec![];
+
+    // nested labels are drawn on top of the label around them, secondary
+    // labels on top of primary ones
+    let end = marks.iter().map(|m| m.col + m.width).max().unwrap_or(0);
+    let mut cells: Vec<Option<&Mark>> = vec![None; end];
+    let mut layers: Vec<&Mark> = marks.iter().collect();
+    layers.sort_by_key(|m| (!m.label.primary, std::cmp::Reverse(m.width)));
+    for m in layers {
+        cells[m.col..m.col + m.width].fill(Some(m));
+    }
+    let mut underline = String::new();
+    let same = |a: &Option<&Mark>, b: &Option<&Mark>| match (a, b) {
+        (Some(a), Some(b)) => std::ptr::eq(*a, *b),
+        (a, b) => a.is_none() && b.is_none(),
+    };
+    for run in cells.chunk_by(same) {
+        match run[0] {
+            Some(m) => {
+                let marker = if m.label.primary { "^" } else { "-" };
+                underline += &paint(style(m), &marker.repeat(run.len()));
+            }
+            None => underline += &" ".repeat(run.len()),
+        }
+    }
+    let last = marks.last().unwrap();
+    if !last.label.message.is_empty() {
+        underline += &format!(" {}", paint(style(last), &last.label.message));
+    }
+    rows.push(underline);
+
+    let hanging: Vec<&Mark> = marks[..marks.len() - 1]
+        .iter()
+        .filter(|m| !m.label.message.is_empty())
+        .collect();
+    for k in (0..hanging.len()).rev() {
+        let connectors = |upto: usize| {
+            let mut row = String::new();
+            let mut col = 0;
+            for m in &hanging[..upto] {
+                row += &" ".repeat(m.col.saturating_sub(col));
+                row += &paint(style(m), "|");
+                col = m.col.max(col) + 1;
+            }
+            (row, col)
+        };
+        rows.push(connectors(k + 1).0);
+        let (mut row, col) = connectors(k);
+        row += &" ".repeat(hanging[k].col.saturating_sub(col));
+        row += &paint(style(hanging[k]), &hanging[k].label.message);
+        rows.push(row);
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: u32, end: u32) -> Span {
+        // the renderer works from byte offsets only
+        Span::new(start, end, 0, 0)
+    }
+
+    fn test(diagnostic: Diagnostic, source: &str, expected: &str) {
+        assert_eq!(expected, diagnostic.render(source, "test.goof", false));
+    }
+
+    #[test]
+    fn message_only() {
+        test(
+            Diagnostic::error("something broke"),
+            "1",
+            "error: something broke\n",
+        );
+    }
+
+    #[test]
+    fn primary_label() {
+        let diagnostic =
+            Diagnostic::error("unexpected character `$`").primary(span(2, 3), "not valid here");
+        let expected = "\
+error: unexpected character `$`
+ --> test.goof:1:3
+  |
+1 | 1 $ 2
+  |   ^ not valid here
+";
+        test(diagnostic, "1 $ 2", expected);
+    }
+
+    #[test]
+    fn secondary_label_hangs_below() {
+        let diagnostic = Diagnostic::error("expected `|` after if-condition, found `else`")
+            .primary(span(5, 9), "expected `|`")
+            .secondary(span(0, 2), "`if` starts here");
+        let expected = "\
+error: expected `|` after if-condition, found `else`
+ --> test.goof:1:6
+  |
+1 | if 1 else 2
+  | --   ^^^^ expected `|`
+  | |
+  | `if` starts here
+";
+        test(diagnostic, "if 1 else 2", expected);
+    }
+
+    #[test]
+    fn three_labels() {
+        let diagnostic = Diagnostic::error("e")
+            .secondary(span(0, 1), "a")
+            .secondary(span(2, 3), "b")
+            .primary(span(4, 5), "c");
+        let expected = "\
+error: e
+ --> test.goof:1:5
+  |
+1 | 1 2 3
+  | - - ^ c
+  | | |
+  | | b
+  | |
+  | a
+";
+        test(diagnostic, "1 2 3", expected);
+    }
+
+    #[test]
+    fn labels_on_different_lines() {
+        let source = "(1 +\n2\n\n\n* 3";
+        let diagnostic = Diagnostic::error("expected `)`")
+            .primary(span(12, 12), "")
+            .secondary(span(0, 1), "unclosed `(`");
+        let expected = "\
+error: expected `)`
+ --> test.goof:5:4
+  |
+1 | (1 +
+  | - unclosed `(`
+...
+5 | * 3
+  |    ^
+";
+        test(diagnostic, source, expected);
+    }
+
+    #[test]
+    fn nested_labels() {
+        let diagnostic = Diagnostic::error("e")
+            .primary(span(0, 11), "whole")
+            .secondary(span(4, 11), "part");
+        let expected = "\
+error: e
+ --> test.goof:1:1
+  |
+1 | 2 ^ (1 - 4)
+  | ^^^^------- part
+  | |
+  | whole
+";
+        test(diagnostic, "2 ^ (1 - 4)", expected);
+    }
+
+    #[test]
+    fn multiline_span_is_cut_at_line_end() {
+        let diagnostic = Diagnostic::error("e").primary(span(2, 7), "here");
+        let expected = "\
+error: e
+ --> test.goof:1:3
+  |
+1 | 1 + 2
+  |   ^^^ here
+";
+        test(diagnostic, "1 + 2\n+ 3", expected);
+    }
+
+    #[test]
+    fn wide_gutter() {
+        let source = "\n".repeat(9) + "1 $";
+        let diagnostic = Diagnostic::error("e").primary(span(11, 12), "");
+        let expected = "\
+error: e
+  --> test.goof:10:3
+   |
+10 | 1 $
+   |   ^
+";
+        test(diagnostic, &source, expected);
+    }
+
+    #[test]
+    fn unicode_columns() {
+        // both letters are two bytes wide
+        let diagnostic = Diagnostic::error("e").primary(span(5, 6), "");
+        let expected = "\
+error: e
+ --> test.goof:1:4
+  |
+1 | \u{e6}\u{f8} $
+  |    ^
+";
+        test(diagnostic, "\u{e6}\u{f8} $", expected);
+    }
+
+    #[test]
+    fn help() {
+        let diagnostic = Diagnostic::error("e")
+            .primary(span(0, 1), "")
+            .help("try this");
+        let expected = "\
+error: e
+ --> test.goof:1:1
+  |
+1 | =
+  | ^
+  |
+  = help: try this
+";
+        test(diagnostic, "=", expected);
+    }
+
+    #[test]
+    fn color() {
+        let diagnostic = Diagnostic::error("e").primary(span(0, 1), "here");
+        let rendered = diagnostic.render("1", "test.goof", true);
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m\x1b[1m: e\x1b[0m\n"));
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m \x1b[1;31mhere\x1b[0m"));
+    }
+}
@@ -0,0 +1,350 @@
+//! Differential tests: random expressions from the grammar are parsed by
+//! the Pratt, LR and LALR(1) parsers, which must agree.
+
+use crate::{
+    grammar,
+    parser::Parser,
+    pratt::{self, ParseError, E, OPERATORS},
+    span::Span,
+    tokenizer::{Token, TokenKind, Tokenizer, OP},
+};
+
+/// splitmix64, seeded per case so a failure can be replayed
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+/// A random derivation of the grammar. It is only used to write out
+/// tokens, so it has parentheses where the generator chose them, not where
+/// the precedence of its operators needs them.
+fn generate(rng: &mut Rng, depth: u32) -> E {
+    let span = Span::default();
+    let sub = |rng: &mut Rng| Box::new(generate(rng, depth - 1));
+    let name = |rng: &mut Rng| ["x", "y"][rng.below(2) as usize].to_string();
+    match if depth == 0 {
+        rng.below(3)
+    } else {
+        rng.below(13)
+    } {
+        0 => E::LITERAL(rng.below(10) as u32, span),
+        1 => E::BOOL(rng.below(2) == 0, span),
+        2 => E::VAR(name(rng), span),
+        3 => {
+            let op = if rng.below(2) == 0 {
+                OP::PLUS
+            } else {
+                OP::MINUS
+            };
+            E::UNARY(op, sub(rng), span)
+        }
+        4..=6 => {
+            let op = OPERATORS[rng.below(OPERATORS.len() as u64) as usize].clone();
+            E::BINARY(sub(rng), op, sub(rng), span)
+        }
+        7 => E::PAREN(sub(rng), span),
+        8 => E::LET(name(rng), sub(rng), sub(rng), span),
+        9 => E::LETREC(name(rng), sub(rng), sub(rng), span),
+        10 => {
+            let params = (0..rng.below(3)).map(|_| name(rng)).collect();
+            E::FN(params, sub(rng), span)
+        }
+        11 => {
+            let callee = sub(rng);
+            let args = (0..rng.below(3)).map(|_| *sub(rng)).collect();
+            E::CALL(callee, args, span)
+        }
+        _ => E::IF(sub(rng), sub(rng), sub(rng), span),
+    }
+}
+
+fn write_tokens(e: &E, out: &mut Vec<TokenKind>) {
+    match e {
+        E::LITERAL(v, _) => out.push(TokenKind::LITERAL(*v)),
+        E::BOOL(b, _) => out.push(TokenKind::BOOL(*b)),
+        E::UNARY(op, e, _) => {
+            out.push(TokenKind::OPERATOR(op.clone()));
+            write_tokens(e, out);
+        }
+        E::BINARY(left, op, right, _) => {
+            write_tokens(left, out);
+            out.push(TokenKind::OPERATOR(op.clone()));
+            write_tokens(right, out);
+        }
+        E::PAREN(e, _) => {
+            out.push(TokenKind::LPAREN);
+            write_tokens(e, out);
+            out.push(TokenKind::RPAREN);
+        }
+        E::IF(cond, then, elze, _) => {
+            out.push(TokenKind::IF);
+            write_tokens(cond, out);
+            out.push(TokenKind::THEN);
+            write_tokens(then, out);
+            out.push(TokenKind::ELSE);
+            write_tokens(elze, out);
+        }
+        E::LET(name, value, body, _) | E::LETREC(name, value, body, _) => {
+            out.push(TokenKind::LET);
+            if let E::LETREC(..) = e {
+                out.push(TokenKind::REC);
+            }
+            out.push(TokenKind::IDENT(name.clone()));
+            out.push(TokenKind::ASSIGN);
+            write_tokens(value, out);
+            out.push(TokenKind::IN);
+            write_tokens(body, out);
+        }
+        E::VAR(name, _) => out.push(TokenKind::IDENT(name.clone())),
+        E::FN(params, body, _) => {
+            out.push(TokenKind::FN);
+            for (i, param) in params.iter().enumerate() {
+                if i > 0 {
+                    out.push(TokenKind::COMMA);
+                }
+                out.push(TokenKind::IDENT(param.clone()));
+            }
+            out.push(TokenKind::ARROW);
+            write_tokens(body, out);
+        }
+        E::CALL(callee, args, _) => {
+            write_tokens(callee, out);
+            out.push(TokenKind::LPAREN);
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    out.push(TokenKind::COMMA);
+                }
+                write_tokens(arg, out);
+            }
+            out.push(TokenKind::RPAREN);
+        }
+        E::BLOCK(..) => unreachable!("blocks are only parsed by the Pratt parser"),
+    }
+}
+
+/// The source text of the token stream of `e`
+fn source(e: &E) -> String {
+    let mut tokens = vec![];
+    write_tokens(e, &mut tokens);
+    let tokens: Vec<String> = tokens.iter().map(|t| t.to_string()).collect();
+    tokens.join(" ")
+}
+
+/// Removes `PAREN` nodes, the parsers only have to agree on the structure
+fn normalize(e: E) -> E {
+    let boxed = |e: Box<E>| Box::new(normalize(*e));
+    match e {
+        E::PAREN(e, _) => normalize(*e),
+        E::UNARY(op, e, span) => E::UNARY(op, boxed(e), span),
+        E::BINARY(left, op, right, span) => E::BINARY(boxed(left), op, boxed(right), span),
+        E::IF(cond, then, elze, span) => E::IF(boxed(cond), boxed(then), boxed(elze), span),
+        E::LET(name, value, body, span) => E::LET(name, boxed(value), boxed(body), span),
+        E::LETREC(name, value, body, span) => E::LETREC(name, boxed(value), boxed(body), span),
+        E::FN(params, body, span) => E::FN(params, boxed(body), span),
+        E::CALL(callee, args, span) => E::CALL(
+            boxed(callee),
+            args.into_iter().map(normalize).collect(),
+            span,
+        ),
+        e => e,
+    }
+}
+
+type Parse = fn(&[Token]) -> Result<E, Box<ParseError>>;
+
+/// Describes how the other parsers disagree with the Pratt parser on the
+/// tokens of `e`, if they do
+fn mismatch(e: &E) -> Option<String> {
+    let source = source(e);
+    let tokens = Tokenizer::new(&source).run().unwrap();
+    let parsers: [(&str, Parse); 3] = [
+        ("pratt", pratt::parse),
+        ("lr", |tokens| Parser::new(tokens).run()),
+        ("lalr", grammar::parse),
+    ];
+    let results: Vec<(&str, Result<E, Box<ParseError>>)> = parsers
+        .iter()
+        .map(|(name, parse)| (*name, parse(&tokens).map(normalize)))
+        .collect();
+
+    if results.iter().all(|(_, r)| *r == results[0].1) {
+        return None;
+    }
+    let lines: Vec<String> = results
+        .iter()
+        .map(|(name, result)| match result {
+            Ok(e) => format!("  {}: {:?}", name, e),
+            Err(e) => format!("  {}: error: {}", name, e),
+        })
+        .collect();
+    Some(lines.join("\n"))
+}
+
+fn children(e: &E) -> Vec<&E> {
+    match e {
+        E::LITERAL(..) | E::BOOL(..) | E::VAR(..) => vec![],
+        E::UNARY(_, e, _) | E::PAREN(e, _) | E::FN(_, e, _) => vec![e],
+        E::BINARY(left, _, right, _) | E::LET(_, left, right, _) | E::LETREC(_, left, right, _) => {
+            vec![left, right]
+        }
+        E::CALL(callee, args, _) => std::iter::once(&**callee).chain(args).collect(),
+        E::IF(cond, then, elze, _) => vec![cond, then, elze],
+        E::BLOCK(..) => unreachable!("blocks are only parsed by the Pratt parser"),
+    }
+}
+
+/// `e` with its children replaced, in the order of `children`
+fn rebuild(e: &E, children: Vec<E>) -> E {
+    let mut children = children.into_iter().map(Box::new);
+    let mut next = || children.next().unwrap();
+    match e {
+        E::LITERAL(..) | E::BOOL(..) | E::VAR(..) => e.clone(),
+        E::UNARY(op, _, span) => E::UNARY(op.clone(), next(), *span),
+        E::PAREN(_, span) => E::PAREN(next(), *span),
+        E::BINARY(_, op, _, span) => {
+            let left = next();
+            E::BINARY(left, op.clone(), next(), *span)
+        }
+        E::IF(.., span) => {
+            let (cond, then) = (next(), next());
+            E::IF(cond, then, next(), *span)
+        }
+        E::LET(name, _, _, span) => {
+            let value = next();
+            E::LET(name.clone(), value, next(), *span)
+        }
+        E::LETREC(name, _, _, span) => {
+            let value = next();
+            E::LETREC(name.clone(), value, next(), *span)
+        }
+        E::FN(params, _, span) => E::FN(params.clone(), next(), *span),
+        E::CALL(_, args, span) => {
+            let callee = next();
+            let args = (0..args.len()).map(|_| *next()).collect();
+            E::CALL(callee, args, *span)
+        }
+        E::BLOCK(..) => unreachable!("blocks are only parsed by the Pratt parser"),
+    }
+}
+
+/// Every way to make `e` one step simpler: replace it by a child or by `0`,
+/// or simplify one of its children
+fn smaller(e: &E) -> Vec<E> {
+    let children = children(e);
+    let mut out: Vec<E> = children.iter().map(|c| (*c).clone()).collect();
+    match e {
+        E::LITERAL(0, _) => {}
+        E::LITERAL(_, span) | E::BOOL(_, span) | E::VAR(_, span) => out.push(E::LITERAL(0, *span)),
+        _ => {}
+    }
+    for (i, child) in children.iter().enumerate() {
+        for simpler in smaller(child) {
+            let mut replaced: Vec<E> = children.iter().map(|c| (*c).clone()).collect();
+            replaced[i] = simpler;
+            out.push(rebuild(e, replaced));
+        }
+    }
+    out
+}
+
+/// Simplifies `e` step by step for as long as it still `fails`
+fn shrink(mut e: E, fails: impl Fn(&E) -> bool) -> E {
+    'outer: loop {
+        for candidate in smaller(&e) {
+            if fails(&candidate) {
+                e = candidate;
+                continue 'outer;
+            }
+        }
+        return e;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsers_agree_on_random_expressions() {
+        for seed in 0..3000 {
+            let e = generate(&mut Rng(seed), 5);
+            if mismatch(&e).is_some() {
+                let minimal = shrink(e, |e| mismatch(e).is_some());
+                panic!(
+                    "parsers disagree on `{}` (seed {}, shrunk)\n{}",
+                    source(&minimal),
+                    seed,
+                    mismatch(&minimal).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn generator_covers_the_grammar() {
+        let mut rng = Rng(7);
+        let mut seen = [false; 11];
+        for _ in 0..100 {
+            let mut pending = vec![generate(&mut rng, 4)];
+            while let Some(e) = pending.pop() {
+                let kind = match &e {
+                    E::LITERAL(..) => 0,
+                    E::BOOL(..) => 1,
+                    E::UNARY(..) => 2,
+                    E::BINARY(..) => 3,
+                    E::PAREN(..) => 4,
+                    E::IF(..) => 5,
+                    E::LET(..) => 6,
+                    E::VAR(..) => 7,
+                    E::LETREC(..) => 8,
+                    E::FN(..) => 9,
+                    E::CALL(..) => 10,
+                    E::BLOCK(..) => unreachable!("blocks are only parsed by the Pratt parser"),
+                };
+                seen[kind] = true;
+                pending.extend(children(&e).into_iter().cloned());
+            }
+        }
+        assert_eq!([true; 11], seen);
+    }
+
+    #[test]
+    fn generated_sources_parse() {
+        // only chained comparisons can make a derivation of the grammar fail
+        for seed in 0..200 {
+            let e = generate(&mut Rng(seed), 4);
+            let tokens = Tokenizer::new(&source(&e)).run().unwrap();
+            if let Err(error) = pratt::parse(&tokens) {
+                assert_eq!(pratt::ParseErrorKind::ChainedComparison, error.kind);
+            }
+        }
+    }
+
+    #[test]
+    fn normalize_removes_parens() {
+        let tokens = Tokenizer::new("((1) + (2 * 3))").run().unwrap();
+        let e = normalize(pratt::parse(&tokens).unwrap());
+        assert_eq!("(PLUS 1 (MULT 2 3))", format!("{:?}", e));
+    }
+
+    #[test]
+    fn shrinks_to_minimal_counterexample() {
+        let has_pow = |e: &E| source(e).contains('^');
+        let e = (0..)
+            .map(|seed| generate(&mut Rng(seed), 5))
+            .find(|e| has_pow(e) && source(e).len() > 40)
+            .unwrap();
+        assert_eq!("0 ^ 0", source(&shrink(e, has_pow)));
+    }
+}
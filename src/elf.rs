@@ -0,0 +1,83 @@
+use crate::encoder::Code;
+
+/// Where the executable is loaded, the usual base of static executables
+const BASE: u64 = 0x400000;
+const PAGE: u64 = 0x1000;
+
+const ELF_HEADER_SIZE: u16 = 64;
+const PROGRAM_HEADER_SIZE: u16 = 56;
+/// The code starts right after the headers
+const CODE_OFFSET: u64 = (ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE) as u64;
+
+const ET_EXEC: u16 = 2;
+const EM_X86_64: u16 = 0x3e;
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_R: u32 = 4;
+
+/// A static ELF64 executable for Linux on x86-64. The whole file, headers
+/// included, is mapped as a single readable and executable segment, so
+/// the code needs no relocation and no section headers.
+pub fn executable(code: &Code) -> Vec<u8> {
+    let size = CODE_OFFSET + code.bytes.len() as u64;
+    let mut out = Vec::with_capacity(size as usize);
+
+    // ELF header: 64-bit, little-endian, version 1, System V ABI
+    out.extend(b"\x7fELF");
+    out.extend([2, 1, 1, 0]);
+    out.extend([0; 8]);
+    out.extend(ET_EXEC.to_le_bytes());
+    out.extend(EM_X86_64.to_le_bytes());
+    out.extend(1u32.to_le_bytes());
+    out.extend((BASE + CODE_OFFSET + code.entry as u64).to_le_bytes());
+    // program headers right after this one, no section headers
+    out.extend((ELF_HEADER_SIZE as u64).to_le_bytes());
+    out.extend(0u64.to_le_bytes());
+    out.extend(0u32.to_le_bytes());
+    out.extend(ELF_HEADER_SIZE.to_le_bytes());
+    out.extend(PROGRAM_HEADER_SIZE.to_le_bytes());
+    out.extend(1u16.to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+
+    // the one segment, the whole file
+    out.extend(PT_LOAD.to_le_bytes());
+    out.extend((PF_R | PF_X).to_le_bytes());
+    out.extend(0u64.to_le_bytes());
+    out.extend(BASE.to_le_bytes());
+    out.extend(BASE.to_le_bytes());
+    out.extend(size.to_le_bytes());
+    out.extend(size.to_le_bytes());
+    out.extend(PAGE.to_le_bytes());
+
+    out.extend(&code.bytes);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers() {
+        let code = Code {
+            bytes: vec![0x90, 0xc3],
+            entry: 1,
+        };
+        let elf = executable(&code);
+        assert_eq!(122, elf.len());
+        assert_eq!(b"\x7fELF\x02\x01\x01", &elf[..7]);
+        // e_type and e_machine
+        assert_eq!([2, 0, 0x3e, 0], elf[16..20]);
+        // e_entry, after the headers and the first byte of code
+        assert_eq!(0x400079u64.to_le_bytes(), elf[24..32]);
+        // e_phoff and e_phnum
+        assert_eq!(64u64.to_le_bytes(), elf[32..40]);
+        assert_eq!([1, 0], elf[56..58]);
+        // p_type, p_flags, p_filesz
+        assert_eq!([1, 0, 0, 0, 5, 0, 0, 0], elf[64..72]);
+        assert_eq!(122u64.to_le_bytes(), elf[96..104]);
+        assert_eq!([0x90, 0xc3], elf[120..]);
+    }
+}
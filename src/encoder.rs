@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+
+use crate::codegen::{Assembly, Cond, Inst, Operand, Reg, Width};
+
+/// Machine code with the data it refers to placed right after it, ready to
+/// be loaded at any address
+#[derive(Debug)]
+pub struct Code {
+    pub bytes: Vec<u8>,
+    /// The offset of `_start`
+    pub entry: usize,
+}
+
+/// A 32-bit displacement to fill in once every label is placed
+struct Fixup {
+    at: usize,
+    label: String,
+    /// Where the displacement is counted from, the end of the instruction
+    end: usize,
+}
+
+struct Encoder {
+    bytes: Vec<u8>,
+    labels: HashMap<String, usize>,
+    fixups: Vec<Fixup>,
+}
+
+/// Encodes the instructions of `assembly`. Jumps and calls always take a
+/// 32-bit displacement, so every instruction has its final size when it is
+/// encoded.
+pub fn encode(assembly: &Assembly) -> Code {
+    let mut e = Encoder {
+        bytes: vec![],
+        labels: HashMap::new(),
+        fixups: vec![],
+    };
+    for inst in &assembly.text {
+        e.inst(inst);
+    }
+    for (label, data) in &assembly.data {
+        e.labels.insert(label.clone(), e.bytes.len());
+        e.bytes.extend(data);
+    }
+    for fixup in &e.fixups {
+        let target = e.labels[&fixup.label] as i64;
+        let displacement = (target - fixup.end as i64) as i32;
+        e.bytes[fixup.at..fixup.at + 4].copy_from_slice(&displacement.to_le_bytes());
+    }
+    Code {
+        entry: e.labels["_start"],
+        bytes: e.bytes,
+    }
+}
+
+impl Cond {
+    /// The low nibble of the `jcc` and `setcc` opcodes
+    fn code(self) -> u8 {
+        match self {
+            Cond::O => 0x0,
+            Cond::E => 0x4,
+            Cond::NE => 0x5,
+            Cond::S => 0x8,
+            Cond::L => 0xc,
+            Cond::GE => 0xd,
+            Cond::LE => 0xe,
+            Cond::G => 0xf,
+        }
+    }
+}
+
+impl Encoder {
+    fn emit(&mut self, bytes: &[u8]) {
+        self.bytes.extend(bytes);
+    }
+
+    /// A REX prefix for 64-bit operands, and for the byte registers that
+    /// need one to not mean `%ah` to `%bh`
+    fn rex(&mut self, width: Width, byte_regs: &[&Operand]) {
+        let low_byte = |o: &&Operand| matches!(o, Operand::Reg(r) if (*r as u8) >= 4);
+        if width == Width::Q {
+            self.emit(&[0x48]);
+        } else if width == Width::B && byte_regs.iter().any(low_byte) {
+            self.emit(&[0x40]);
+        }
+    }
+
+    /// The ModRM byte and displacement addressing `rm`, with `reg` in the
+    /// reg field. `rest` is the number of bytes of the instruction after
+    /// them, for `%rip`-relative displacements.
+    fn modrm(&mut self, reg: u8, rm: &Operand, rest: usize) {
+        match rm {
+            Operand::Reg(r) => self.emit(&[0xc0 | reg << 3 | *r as u8]),
+            Operand::Mem(base, offset) => {
+                let base = *base as u8;
+                // `%rbp` without a displacement means `%rip`-relative
+                let mode = match offset {
+                    0 if base != Reg::Rbp as u8 => 0x00,
+                    -128..=127 => 0x40,
+                    _ => 0x80,
+                };
+                self.emit(&[mode | reg << 3 | base]);
+                if base == Reg::Rsp as u8 {
+                    // a SIB byte without an index
+                    self.emit(&[0x24]);
+                }
+                match mode {
+                    0x40 => self.emit(&[*offset as i8 as u8]),
+                    0x80 => self.emit(&offset.to_le_bytes()),
+                    _ => (),
+                }
+            }
+            Operand::Data(label) => {
+                self.emit(&[reg << 3 | 0x05]);
+                self.fixup(label, rest);
+            }
+            Operand::Imm(_) => unreachable!("an immediate is not addressable"),
+        }
+    }
+
+    /// A 32-bit displacement to `label`, followed by `rest` more bytes
+    fn fixup(&mut self, label: &str, rest: usize) {
+        let at = self.bytes.len();
+        self.emit(&[0; 4]);
+        self.fixups.push(Fixup {
+            at,
+            label: label.to_string(),
+            end: at + 4 + rest,
+        });
+    }
+
+    /// An instruction taking a register or memory operand, `reg` is a
+    /// register or the opcode extension, `imm` the immediate that ends it
+    fn rm(&mut self, width: Width, opcode: &[u8], reg: u8, rm: &Operand, imm: &[u8]) {
+        self.rex(width, &[rm]);
+        self.emit(opcode);
+        self.modrm(reg, rm, imm.len());
+        self.emit(imm);
+    }
+
+    /// The arithmetic instructions sharing their encodings: `base` is the
+    /// opcode of the register form, `extension` that of the immediate form.
+    /// The byte forms take an 8-bit immediate and come one opcode before.
+    fn arithmetic(&mut self, width: Width, base: u8, extension: u8, src: &Operand, dst: &Operand) {
+        match src {
+            Operand::Imm(v) if width == Width::B => {
+                self.rm(width, &[0x80], extension, dst, &[*v as u8])
+            }
+            Operand::Imm(v) if i8::try_from(*v).is_ok() => {
+                self.rm(width, &[0x83], extension, dst, &[*v as u8])
+            }
+            Operand::Imm(v) => self.rm(width, &[0x81], extension, dst, &v.to_le_bytes()),
+            Operand::Reg(r) => {
+                let opcode = if width == Width::B { base - 1 } else { base };
+                self.rex(width, &[src, dst]);
+                self.emit(&[opcode]);
+                self.modrm(*r as u8, dst, 0);
+            }
+            _ => unreachable!("cannot encode an arithmetic instruction from {:?}", src),
+        }
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        let reg = |o: &Operand| match o {
+            Operand::Reg(r) => *r as u8,
+            o => unreachable!("expected a register, found {:?}", o),
+        };
+        match inst {
+            Inst::Label(label) => {
+                self.labels.insert(label.clone(), self.bytes.len());
+            }
+            Inst::Mov(Width::B, Operand::Imm(v), dst) => {
+                self.rm(Width::B, &[0xc6], 0, dst, &[*v as u8])
+            }
+            Inst::Mov(Width::L, Operand::Imm(v), Operand::Reg(r)) => {
+                self.emit(&[0xb8 + *r as u8]);
+                self.emit(&v.to_le_bytes());
+            }
+            // sign-extended to 64 bits
+            Inst::Mov(width, Operand::Imm(v), dst) => {
+                self.rm(*width, &[0xc7], 0, dst, &v.to_le_bytes())
+            }
+            Inst::Mov(width, src @ Operand::Reg(r), dst) => {
+                let opcode = if *width == Width::B { 0x88 } else { 0x89 };
+                self.rex(*width, &[src, dst]);
+                self.emit(&[opcode]);
+                self.modrm(*r as u8, dst, 0);
+            }
+            Inst::Mov(width, src, Operand::Reg(r)) => {
+                let opcode = if *width == Width::B { 0x8a } else { 0x8b };
+                self.rm(*width, &[opcode], *r as u8, src, &[])
+            }
+            Inst::Movslq(src, dst) => {
+                self.rm(Width::Q, &[0x63], *dst as u8, &Operand::Reg(*src), &[])
+            }
+            Inst::Movzbl(src, dst) => {
+                let src = Operand::Reg(*src);
+                self.rex(Width::B, &[&src]);
+                self.emit(&[0x0f, 0xb6]);
+                self.modrm(*dst as u8, &src, 0);
+            }
+            Inst::Lea(src, dst) => self.rm(Width::Q, &[0x8d], *dst as u8, src, &[]),
+            Inst::Add(width, src, dst) => self.arithmetic(*width, 0x01, 0, src, dst),
+            Inst::Sub(width, src, dst) => self.arithmetic(*width, 0x29, 5, src, dst),
+            Inst::Cmp(width, src, dst) => self.arithmetic(*width, 0x39, 7, src, dst),
+            Inst::Imul(width, src, dst) => self.rm(*width, &[0x0f, 0xaf], *dst as u8, src, &[]),
+            Inst::Neg(width, operand) => {
+                let opcode = if *width == Width::B { 0xf6 } else { 0xf7 };
+                self.rm(*width, &[opcode], 3, operand, &[])
+            }
+            Inst::Test(Width::B, Operand::Imm(v), dst) => {
+                self.rm(Width::B, &[0xf6], 0, dst, &[*v as u8])
+            }
+            Inst::Test(width, Operand::Imm(v), dst) => {
+                self.rm(*width, &[0xf7], 0, dst, &v.to_le_bytes())
+            }
+            Inst::Test(width, src, dst) => {
+                let opcode = if *width == Width::B { 0x84 } else { 0x85 };
+                self.rex(*width, &[src, dst]);
+                self.emit(&[opcode]);
+                self.modrm(reg(src), dst, 0);
+            }
+            Inst::Shr(width, 1, operand) => self.rm(*width, &[0xd1], 5, operand, &[]),
+            Inst::Shr(width, n, operand) => self.rm(*width, &[0xc1], 5, operand, &[*n]),
+            Inst::Cltd => self.emit(&[0x99]),
+            Inst::Idiv(width, operand) => self.rm(*width, &[0xf7], 7, operand, &[]),
+            Inst::Div(width, operand) => self.rm(*width, &[0xf7], 6, operand, &[]),
+            Inst::Set(cond, r) => {
+                let r = Operand::Reg(*r);
+                self.rex(Width::B, &[&r]);
+                self.emit(&[0x0f, 0x90 | cond.code()]);
+                self.modrm(0, &r, 0);
+            }
+            Inst::Jmp(label) => {
+                self.emit(&[0xe9]);
+                self.fixup(label, 0);
+            }
+            Inst::J(cond, label) => {
+                self.emit(&[0x0f, 0x80 | cond.code()]);
+                self.fixup(label, 0);
+            }
+            Inst::Call(label) => {
+                self.emit(&[0xe8]);
+                self.fixup(label, 0);
+            }
+            Inst::Ret => self.emit(&[0xc3]),
+            Inst::Push(r) => self.emit(&[0x50 + *r as u8]),
+            Inst::Pop(r) => self.emit(&[0x58 + *r as u8]),
+            Inst::Syscall => self.emit(&[0x0f, 0x05]),
+            Inst::Mov(..) => unreachable!("cannot encode {}", inst),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_one(inst: Inst) -> Vec<u8> {
+        let assembly = Assembly {
+            text: vec![Inst::Label("_start".into()), inst],
+            data: vec![],
+        };
+        encode(&assembly).bytes
+    }
+
+    fn test(inst: Inst, bytes: &[u8]) {
+        let text = inst.to_string();
+        assert_eq!(bytes, encode_one(inst), "{}", text);
+    }
+
+    #[test]
+    fn moves() {
+        use Operand::{Imm, Mem, Reg as R};
+        test(
+            Inst::Mov(Width::L, Imm(5), R(Reg::Rax)),
+            &[0xb8, 5, 0, 0, 0],
+        );
+        test(
+            Inst::Mov(Width::L, Imm(-1), R(Reg::Rdi)),
+            &[0xbf, 0xff, 0xff, 0xff, 0xff],
+        );
+        test(Inst::Mov(Width::L, R(Reg::Rax), R(Reg::Rcx)), &[0x89, 0xc1]);
+        test(
+            Inst::Mov(Width::L, R(Reg::Rax), Mem(Reg::Rbp, -8)),
+            &[0x89, 0x45, 0xf8],
+        );
+        test(
+            Inst::Mov(Width::L, Mem(Reg::Rbp, -16), R(Reg::Rax)),
+            &[0x8b, 0x45, 0xf0],
+        );
+        test(
+            Inst::Mov(Width::L, R(Reg::Rax), Mem(Reg::Rbp, -200)),
+            &[0x89, 0x85, 0x38, 0xff, 0xff, 0xff],
+        );
+        test(
+            Inst::Mov(Width::Q, R(Reg::Rsp), R(Reg::Rbp)),
+            &[0x48, 0x89, 0xe5],
+        );
+        test(
+            Inst::Mov(Width::B, R(Reg::Rdx), Mem(Reg::Rsi, 0)),
+            &[0x88, 0x16],
+        );
+        test(
+            Inst::Mov(Width::B, Imm(45), Mem(Reg::Rsi, 0)),
+            &[0xc6, 0x06, 0x2d],
+        );
+        test(Inst::Movslq(Reg::Rax, Reg::Rax), &[0x48, 0x63, 0xc0]);
+        test(Inst::Movzbl(Reg::Rax, Reg::Rax), &[0x0f, 0xb6, 0xc0]);
+    }
+
+    #[test]
+    fn arithmetic() {
+        use Operand::{Imm, Reg as R};
+        test(Inst::Add(Width::L, R(Reg::Rcx), R(Reg::Rax)), &[0x01, 0xc8]);
+        test(
+            Inst::Add(Width::L, Imm(48), R(Reg::Rdx)),
+            &[0x83, 0xc2, 0x30],
+        );
+        test(
+            Inst::Add(Width::Q, Imm(1000), R(Reg::Rsp)),
+            &[0x48, 0x81, 0xc4, 0xe8, 0x03, 0, 0],
+        );
+        test(
+            Inst::Sub(Width::Q, Imm(16), R(Reg::Rsp)),
+            &[0x48, 0x83, 0xec, 0x10],
+        );
+        test(
+            Inst::Sub(Width::Q, R(Reg::Rsi), R(Reg::Rdx)),
+            &[0x48, 0x29, 0xf2],
+        );
+        test(
+            Inst::Imul(Width::L, R(Reg::Rcx), Reg::Rax),
+            &[0x0f, 0xaf, 0xc1],
+        );
+        test(
+            Inst::Imul(Width::L, R(Reg::Rax), Reg::Rdx),
+            &[0x0f, 0xaf, 0xd0],
+        );
+        test(Inst::Neg(Width::Q, R(Reg::Rax)), &[0x48, 0xf7, 0xd8]);
+        test(Inst::Cltd, &[0x99]);
+        test(Inst::Idiv(Width::L, R(Reg::Rcx)), &[0xf7, 0xf9]);
+        test(Inst::Div(Width::Q, R(Reg::Rcx)), &[0x48, 0xf7, 0xf1]);
+        test(Inst::Shr(Width::L, 1, R(Reg::Rcx)), &[0xd1, 0xe9]);
+    }
+
+    #[test]
+    fn byte_arithmetic() {
+        use Operand::{Imm, Mem, Reg as R};
+        test(Inst::Add(Width::B, R(Reg::Rcx), R(Reg::Rax)), &[0x00, 0xc8]);
+        test(
+            Inst::Add(Width::B, Imm(48), R(Reg::Rdx)),
+            &[0x80, 0xc2, 0x30],
+        );
+        test(
+            Inst::Sub(Width::B, Imm(-1), Mem(Reg::Rsi, 0)),
+            &[0x80, 0x2e, 0xff],
+        );
+        // `%sil` needs a REX prefix
+        test(
+            Inst::Sub(Width::B, R(Reg::Rsi), R(Reg::Rdx)),
+            &[0x40, 0x28, 0xf2],
+        );
+        test(Inst::Cmp(Width::B, R(Reg::Rcx), R(Reg::Rax)), &[0x38, 0xc8]);
+        test(
+            Inst::Cmp(Width::B, Imm(45), R(Reg::Rbx)),
+            &[0x80, 0xfb, 0x2d],
+        );
+        test(Inst::Neg(Width::B, R(Reg::Rax)), &[0xf6, 0xd8]);
+        test(
+            Inst::Test(Width::B, R(Reg::Rbx), R(Reg::Rbx)),
+            &[0x84, 0xdb],
+        );
+        test(Inst::Test(Width::B, Imm(1), R(Reg::Rcx)), &[0xf6, 0xc1, 1]);
+    }
+
+    #[test]
+    fn comparisons() {
+        use Operand::{Imm, Reg as R};
+        test(Inst::Cmp(Width::L, R(Reg::Rcx), R(Reg::Rax)), &[0x39, 0xc8]);
+        test(
+            Inst::Cmp(Width::L, Imm(-1), R(Reg::Rcx)),
+            &[0x83, 0xf9, 0xff],
+        );
+        test(
+            Inst::Cmp(Width::L, Imm(i32::MIN), R(Reg::Rax)),
+            &[0x81, 0xf8, 0, 0, 0, 0x80],
+        );
+        test(
+            Inst::Test(Width::Q, R(Reg::Rbx), R(Reg::Rbx)),
+            &[0x48, 0x85, 0xdb],
+        );
+        test(
+            Inst::Test(Width::L, Imm(1), R(Reg::Rcx)),
+            &[0xf7, 0xc1, 1, 0, 0, 0],
+        );
+        test(Inst::Set(Cond::G, Reg::Rax), &[0x0f, 0x9f, 0xc0]);
+        test(Inst::Set(Cond::E, Reg::Rax), &[0x0f, 0x94, 0xc0]);
+        // `%sil` needs a REX prefix
+        test(Inst::Set(Cond::L, Reg::Rsi), &[0x40, 0x0f, 0x9c, 0xc6]);
+    }
+
+    #[test]
+    fn control() {
+        test(Inst::Ret, &[0xc3]);
+        test(Inst::Push(Reg::Rbp), &[0x55]);
+        test(Inst::Pop(Reg::Rbp), &[0x5d]);
+        test(Inst::Syscall, &[0x0f, 0x05]);
+    }
+
+    #[test]
+    fn labels() {
+        let assembly = Assembly {
+            text: vec![
+                Inst::Label("_start".into()),
+                Inst::Label("top".into()),
+                Inst::J(Cond::O, "end".into()),
+                Inst::Jmp("top".into()),
+                Inst::Call("top".into()),
+                Inst::Lea(Operand::Data("message".into()), Reg::Rsi),
+                Inst::Label("end".into()),
+            ],
+            data: vec![("message".into(), b"hi".to_vec())],
+        };
+        let code = encode(&assembly);
+        assert_eq!(0, code.entry);
+        let expected: &[u8] = &[
+            0x0f, 0x80, 17, 0, 0, 0, // jo end
+            0xe9, 0xf5, 0xff, 0xff, 0xff, // jmp top
+            0xe8, 0xf0, 0xff, 0xff, 0xff, // call top
+            0x48, 0x8d, 0x35, 0, 0, 0, 0, // leaq message(%rip), %rsi
+            b'h', b'i',
+        ];
+        assert_eq!(expected, code.bytes);
+    }
+}
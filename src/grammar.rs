@@ -0,0 +1,645 @@
+use std::sync::OnceLock;
+
+use crate::{
+    lalr::{Grammar, Stuck, Symbol, Tables, EOF},
+    pratt::{
+        end_of_input, infix, Assoc, Expected, ParseError, E, FN_HELP, IF_HELP, LET_HELP, OPERATORS,
+    },
+    tokenizer::{Token, TokenKind, OP},
+};
+
+/// Values on the stack of the LALR(1) engine, tokens as they are shifted
+/// and the expressions reduced from them
+#[derive(Debug, Clone)]
+enum Node {
+    Token(Token),
+    Expr(E),
+    /// The parameters of a function
+    Names(Vec<String>),
+    /// The arguments of a call
+    Exprs(Vec<E>),
+}
+
+/// The expression language as a grammar for `lalr`, the precedence and
+/// associativity of its operators come from `pratt::infix`
+struct Expressions {
+    grammar: Grammar<Node>,
+    tables: Tables,
+    /// The terminal of each kind of token, literals stand for all literals
+    terminals: Vec<(TokenKind, Symbol)>,
+    /// Terminals that can start an expression
+    starts: Vec<Symbol>,
+    operators: Vec<Symbol>,
+}
+
+fn expressions() -> &'static Expressions {
+    static EXPRESSIONS: OnceLock<Expressions> = OnceLock::new();
+    EXPRESSIONS.get_or_init(Expressions::new)
+}
+
+impl Expressions {
+    fn new() -> Expressions {
+        let mut g = Grammar::new();
+        let e = g.nonterminal("E");
+        let params = g.nonterminal("PARAMS");
+        let names = g.nonterminal("NAMES");
+        let args = g.nonterminal("ARGS");
+        let exprs = g.nonterminal("EXPRS");
+
+        let mut kinds = vec![
+            TokenKind::LITERAL(0),
+            TokenKind::BOOL(false),
+            TokenKind::LPAREN,
+            TokenKind::COMMA,
+            TokenKind::RPAREN,
+            TokenKind::IF,
+            TokenKind::THEN,
+            TokenKind::ELSE,
+            TokenKind::IDENT(String::new()),
+            TokenKind::LET,
+            TokenKind::ASSIGN,
+            TokenKind::IN,
+            TokenKind::REC,
+            TokenKind::FN,
+            TokenKind::ARROW,
+            TokenKind::LBRACE,
+            TokenKind::RBRACE,
+            TokenKind::SEMICOLON,
+            TokenKind::PRINT,
+            TokenKind::VAR,
+            TokenKind::WHILE,
+            TokenKind::BREAK,
+            TokenKind::CONTINUE,
+        ];
+        kinds.extend(OPERATORS.map(TokenKind::OPERATOR));
+        let terminals: Vec<(TokenKind, Symbol)> = kinds
+            .into_iter()
+            .map(|kind| {
+                let symbol = match kind {
+                    TokenKind::LITERAL(_) => g.terminal("LITERAL"),
+                    TokenKind::BOOL(_) => g.terminal("BOOL"),
+                    TokenKind::IDENT(_) => g.terminal("IDENT"),
+                    _ => g.terminal(kind.to_string()),
+                };
+                (kind, symbol)
+            })
+            .collect();
+        let t = |kind: TokenKind| terminals.iter().find(|(k, _)| *k == kind).unwrap().1;
+        let op = |op: OP| t(TokenKind::OPERATOR(op));
+        // only used to give prefix operators their precedence
+        let prefix = g.terminal("PREFIX");
+
+        // the else branch of if and the bodies of let and fn take any
+        // operator that follows them
+        g.precedence(
+            Assoc::Right,
+            &[t(TokenKind::ELSE), t(TokenKind::IN), t(TokenKind::ARROW)],
+        );
+        // one level for each binding power of the infix operators, loosest first
+        let mut powers: Vec<u8> = OPERATORS.iter().map(|o| infix(o).0).collect();
+        powers.sort();
+        powers.dedup();
+        for power in powers {
+            let level: Vec<&OP> = OPERATORS.iter().filter(|o| infix(o).0 == power).collect();
+            let symbols: Vec<Symbol> = level.iter().map(|&o| op(o.clone())).collect();
+            g.precedence(infix(level[0]).1, &symbols);
+        }
+        g.precedence(Assoc::Right, &[prefix]);
+        // and the callee of a call is the tightest expression before `(`
+        g.precedence(Assoc::Left, &[t(TokenKind::LPAREN)]);
+
+        g.production(e, &[t(TokenKind::LITERAL(0))], literal);
+        g.production(e, &[t(TokenKind::BOOL(false))], boolean);
+        g.production(e, &[t(TokenKind::LPAREN), e, t(TokenKind::RPAREN)], paren);
+        g.production_prec(e, &[op(OP::PLUS), e], prefix, unary);
+        g.production_prec(e, &[op(OP::MINUS), e], prefix, unary);
+        for o in OPERATORS {
+            g.production(e, &[e, op(o), e], binary);
+        }
+        let if_then_else = [
+            t(TokenKind::IF),
+            e,
+            t(TokenKind::THEN),
+            e,
+            t(TokenKind::ELSE),
+            e,
+        ];
+        g.production(e, &if_then_else, if_);
+        g.production(e, &[t(TokenKind::IDENT(String::new()))], var);
+        let let_in = [
+            t(TokenKind::LET),
+            t(TokenKind::IDENT(String::new())),
+            t(TokenKind::ASSIGN),
+            e,
+            t(TokenKind::IN),
+            e,
+        ];
+        g.production(e, &let_in, let_);
+        let let_rec = [
+            t(TokenKind::LET),
+            t(TokenKind::REC),
+            t(TokenKind::IDENT(String::new())),
+            t(TokenKind::ASSIGN),
+            e,
+            t(TokenKind::IN),
+            e,
+        ];
+        g.production(e, &let_rec, let_rec_);
+
+        g.production(e, &[t(TokenKind::FN), params, t(TokenKind::ARROW), e], fn_);
+        g.production(params, &[], |_| Node::Names(vec![]));
+        g.production(params, &[names], |mut values| values.remove(0));
+        g.production(names, &[t(TokenKind::IDENT(String::new()))], first_name);
+        let more_names = [
+            names,
+            t(TokenKind::COMMA),
+            t(TokenKind::IDENT(String::new())),
+        ];
+        g.production(names, &more_names, next_name);
+
+        let call = [e, t(TokenKind::LPAREN), args, t(TokenKind::RPAREN)];
+        g.production(e, &call, call_);
+        g.production(args, &[], |_| Node::Exprs(vec![]));
+        g.production(args, &[exprs], |mut values| values.remove(0));
+        g.production(exprs, &[e], |values| {
+            let [e] = nodes(values);
+            Node::Exprs(vec![expr(e)])
+        });
+        g.production(exprs, &[exprs, t(TokenKind::COMMA), e], next_arg);
+
+        let starts = [
+            TokenKind::LITERAL(0),
+            TokenKind::BOOL(false),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::OPERATOR(OP::MINUS),
+            TokenKind::LPAREN,
+            TokenKind::IF,
+            TokenKind::IDENT(String::new()),
+            TokenKind::LET,
+            TokenKind::FN,
+        ]
+        .map(t)
+        .to_vec();
+        // a call continues an expression like an operator does
+        let mut operators = OPERATORS.map(op).to_vec();
+        operators.push(t(TokenKind::LPAREN));
+        Expressions {
+            tables: Tables::build(&g),
+            grammar: g,
+            terminals,
+            starts,
+            operators,
+        }
+    }
+
+    fn terminal(&self, kind: &TokenKind) -> Symbol {
+        let same = |k: &TokenKind| match (k, kind) {
+            (TokenKind::LITERAL(_), TokenKind::LITERAL(_)) => true,
+            (TokenKind::BOOL(_), TokenKind::BOOL(_)) => true,
+            (TokenKind::IDENT(_), TokenKind::IDENT(_)) => true,
+            (k, kind) => k == kind,
+        };
+        match self.terminals.iter().find(|(k, _)| same(k)) {
+            Some((_, symbol)) => *symbol,
+            None => unreachable!("every token kind is a terminal"),
+        }
+    }
+
+    fn kind(&self, symbol: Symbol) -> TokenKind {
+        match self.terminals.iter().find(|(_, s)| *s == symbol) {
+            Some((kind, _)) => kind.clone(),
+            None => unreachable!("only token terminals are expected"),
+        }
+    }
+
+    /// Turns the engine's expected terminals into what the other parsers
+    /// report, naming the construct that is still open
+    fn error(&self, tokens: &[Token], stuck: Stuck<Node>) -> Box<ParseError> {
+        let found = tokens.get(stuck.position);
+
+        if let (
+            Some(
+                token @ Token {
+                    kind: TokenKind::OPERATOR(op),
+                    ..
+                },
+            ),
+            [.., Node::Expr(left), Node::Token(Token {
+                kind: TokenKind::OPERATOR(prev),
+                ..
+            }), Node::Expr(right)],
+        ) = (found, stuck.stack.as_slice())
+        {
+            if infix(prev).1 == Assoc::Non && infix(op).1 == Assoc::Non {
+                return ParseError::chained_comparison(token, left.span().to(right.span()));
+            }
+        }
+
+        let mut rest = stuck.expected;
+        let mut expected = vec![];
+        for (group, summary) in [
+            (&self.starts, Expected::Expression),
+            (&self.operators, Expected::Operator),
+        ] {
+            if group.iter().all(|s| rest.contains(s)) {
+                rest.retain(|s| !group.contains(s));
+                expected.push(summary);
+            }
+        }
+        let end = rest.contains(&EOF);
+        // `rec` is optional, an identifier is what `let` needs
+        let rec = self.terminal(&TokenKind::REC);
+        rest.retain(|s| *s != EOF && *s != rec);
+        let kinds: Vec<TokenKind> = rest.into_iter().map(|s| self.kind(s)).collect();
+        expected.extend(kinds.iter().map(|kind| match kind {
+            TokenKind::IDENT(_) => Expected::Identifier,
+            kind => Expected::Token(kind.clone()),
+        }));
+        if end {
+            expected.push(Expected::EndOfInput);
+        }
+
+        // the innermost `(` or `if` on the stack is the one still open
+        let open = |kind: TokenKind| {
+            stuck.stack.iter().rev().find_map(|node| match node {
+                Node::Token(t) if t.kind == kind => Some(t.span),
+                _ => None,
+            })
+        };
+        let mut error = ParseError::boxed(expected, found);
+        if let (true, Some(span)) = (kinds.contains(&TokenKind::RPAREN), open(TokenKind::LPAREN)) {
+            error = error.related(span, "unclosed `(`");
+        }
+        if let (true, Some(span)) = (kinds.contains(&TokenKind::THEN), open(TokenKind::IF)) {
+            error = error
+                .context("after if-condition")
+                .related(span, "`if` starts here")
+                .help(IF_HELP);
+        }
+        if let (true, Some(span)) = (kinds.contains(&TokenKind::ELSE), open(TokenKind::IF)) {
+            error = error
+                .context("after then-branch")
+                .related(span, "this `if` has no `else`")
+                .help(IF_HELP);
+        }
+        if kinds.contains(&TokenKind::IDENT(String::new())) {
+            error = match stuck.stack.last() {
+                Some(Node::Token(t)) if t.kind == TokenKind::FN => {
+                    error.context("after `fn`").help(FN_HELP)
+                }
+                Some(Node::Token(t)) if t.kind == TokenKind::COMMA => {
+                    error.context("after `,`").help(FN_HELP)
+                }
+                _ => error.context("after `let`").help(LET_HELP),
+            };
+        }
+        if kinds.contains(&TokenKind::ARROW) && kinds.contains(&TokenKind::COMMA) {
+            error = error.context("after parameter").help(FN_HELP);
+        }
+        if let (true, Some(span)) = (kinds.contains(&TokenKind::ASSIGN), open(TokenKind::LET)) {
+            error = error
+                .context("after let-bound name")
+                .related(span, "`let` starts here")
+                .help(LET_HELP);
+        }
+        if let (true, Some(span)) = (kinds.contains(&TokenKind::IN), open(TokenKind::LET)) {
+            error = error
+                .context("after let-bound value")
+                .related(span, "`let` starts here")
+                .help(LET_HELP);
+        }
+        if found.is_none() {
+            error.span = end_of_input(tokens);
+        }
+        error
+    }
+}
+
+/// Parses `tokens` as a single expression with the LALR(1) tables of the
+/// expression grammar
+pub fn parse(tokens: &[Token]) -> Result<E, Box<ParseError>> {
+    let expressions = expressions();
+    let input = tokens
+        .iter()
+        .map(|t| (expressions.terminal(&t.kind), Node::Token(t.clone())));
+    match expressions.tables.parse(&expressions.grammar, input) {
+        Ok(node) => Ok(expr(node)),
+        Err(stuck) => Err(expressions.error(tokens, stuck)),
+    }
+}
+
+// Semantic actions, the engine only reduces a production when the stack
+// holds its right-hand side, so the shapes below always match
+
+fn nodes<const N: usize>(values: Vec<Node>) -> [Node; N] {
+    match values.try_into() {
+        Ok(nodes) => nodes,
+        Err(_) => unreachable!("production has {} symbols", N),
+    }
+}
+
+fn token(node: Node) -> Token {
+    match node {
+        Node::Token(token) => token,
+        _ => unreachable!("expected a token"),
+    }
+}
+
+fn expr(node: Node) -> E {
+    match node {
+        Node::Expr(e) => e,
+        _ => unreachable!("expected an expression"),
+    }
+}
+
+fn name(node: Node) -> String {
+    match token(node).kind {
+        TokenKind::IDENT(name) => name,
+        _ => unreachable!("expected an identifier"),
+    }
+}
+
+fn operator(token: &Token) -> OP {
+    match &token.kind {
+        TokenKind::OPERATOR(op) => op.clone(),
+        _ => unreachable!("expected an operator"),
+    }
+}
+
+fn literal(values: Vec<Node>) -> Node {
+    let [t] = nodes(values);
+    match token(t) {
+        Token {
+            kind: TokenKind::LITERAL(v),
+            span,
+        } => Node::Expr(E::LITERAL(v, span)),
+        _ => unreachable!("expected a literal"),
+    }
+}
+
+fn boolean(values: Vec<Node>) -> Node {
+    let [t] = nodes(values);
+    match token(t) {
+        Token {
+            kind: TokenKind::BOOL(b),
+            span,
+        } => Node::Expr(E::BOOL(b, span)),
+        _ => unreachable!("expected a boolean"),
+    }
+}
+
+fn paren(values: Vec<Node>) -> Node {
+    let [l, e, r] = nodes(values);
+    let span = token(l).span.to(token(r).span);
+    Node::Expr(E::PAREN(Box::new(expr(e)), span))
+}
+
+fn unary(values: Vec<Node>) -> Node {
+    let [op, e] = nodes(values);
+    let (op, e) = (token(op), expr(e));
+    let span = op.span.to(e.span());
+    Node::Expr(E::UNARY(operator(&op), Box::new(e), span))
+}
+
+fn binary(values: Vec<Node>) -> Node {
+    let [left, op, right] = nodes(values);
+    let (left, right) = (expr(left), expr(right));
+    let span = left.span().to(right.span());
+    Node::Expr(E::BINARY(
+        Box::new(left),
+        operator(&token(op)),
+        Box::new(right),
+        span,
+    ))
+}
+
+fn if_(values: Vec<Node>) -> Node {
+    let [if_, cond, _, then, _, elze] = nodes(values);
+    let elze = expr(elze);
+    let span = token(if_).span.to(elze.span());
+    Node::Expr(E::IF(
+        Box::new(expr(cond)),
+        Box::new(expr(then)),
+        Box::new(elze),
+        span,
+    ))
+}
+
+fn var(values: Vec<Node>) -> Node {
+    let [t] = nodes(values);
+    match token(t) {
+        Token {
+            kind: TokenKind::IDENT(name),
+            span,
+        } => Node::Expr(E::VAR(name, span)),
+        _ => unreachable!("expected an identifier"),
+    }
+}
+
+fn let_(values: Vec<Node>) -> Node {
+    let [let_, n, _, value, _, body] = nodes(values);
+    let body = expr(body);
+    let span = token(let_).span.to(body.span());
+    Node::Expr(E::LET(name(n), Box::new(expr(value)), Box::new(body), span))
+}
+
+fn let_rec_(values: Vec<Node>) -> Node {
+    let [let_, _, n, _, value, _, body] = nodes(values);
+    let body = expr(body);
+    let span = token(let_).span.to(body.span());
+    Node::Expr(E::LETREC(
+        name(n),
+        Box::new(expr(value)),
+        Box::new(body),
+        span,
+    ))
+}
+
+fn fn_(values: Vec<Node>) -> Node {
+    let [fn_, params, _, body] = nodes(values);
+    let params = match params {
+        Node::Names(names) => names,
+        _ => unreachable!("expected parameters"),
+    };
+    let body = expr(body);
+    let span = token(fn_).span.to(body.span());
+    Node::Expr(E::FN(params, Box::new(body), span))
+}
+
+fn first_name(values: Vec<Node>) -> Node {
+    let [n] = nodes(values);
+    Node::Names(vec![name(n)])
+}
+
+fn next_name(values: Vec<Node>) -> Node {
+    match nodes(values) {
+        [Node::Names(mut names), _, n] => {
+            names.push(name(n));
+            Node::Names(names)
+        }
+        _ => unreachable!("expected parameters"),
+    }
+}
+
+fn call_(values: Vec<Node>) -> Node {
+    match nodes(values) {
+        [Node::Expr(callee), _, Node::Exprs(args), r] => {
+            let span = callee.span().to(token(r).span);
+            Node::Expr(E::CALL(Box::new(callee), args, span))
+        }
+        _ => unreachable!("expected a call"),
+    }
+}
+
+fn next_arg(values: Vec<Node>) -> Node {
+    match nodes(values) {
+        [Node::Exprs(mut args), _, e] => {
+            args.push(expr(e));
+            Node::Exprs(args)
+        }
+        _ => unreachable!("expected arguments"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pratt, span::Span, tokenizer::Tokenizer};
+
+    fn parse_source(source: &str) -> Result<E, Box<ParseError>> {
+        let tokens = Tokenizer::new(source).run().unwrap();
+        parse(&tokens)
+    }
+
+    fn sexp(source: &str) -> String {
+        format!("{:?}", parse_source(source).unwrap())
+    }
+
+    fn error(source: &str) -> String {
+        parse_source(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn no_conflicts() {
+        let conflicts: Vec<String> = expressions()
+            .tables
+            .conflicts
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(Vec::<String>::new(), conflicts);
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!("(PLUS 1 (MULT 2 3))", sexp("1 + 2 * 3"));
+        assert_eq!("(MINUS (MINUS 10 2) 3)", sexp("10 - 2 - 3"));
+        assert_eq!("(POW 2 (POW 3 2))", sexp("2 ^ 3 ^ 2"));
+        assert_eq!("(POW (MINUS 2) 2)", sexp("-2 ^ 2"));
+        assert_eq!("(LESS (PLUS 1 2) 3)", sexp("1 + 2 < 3"));
+        assert_eq!("(if true 1 (PLUS 2 1))", sexp("if true | 1 else 2 + 1"));
+        assert_eq!(
+            "(PLUS 1 (if c 2 (MULT 3 4)))",
+            sexp("1 + if c | 2 else 3 * 4")
+        );
+        assert_eq!("(let x 1 (PLUS x 1))", sexp("let x = 1 in x + 1"));
+    }
+
+    #[test]
+    fn same_trees_as_pratt() {
+        for source in [
+            "(1)",
+            "-(1 + 2) * 3 / +4",
+            "1 == 2",
+            "(1 < 2) == false",
+            "if 1 <= 2 | if false | 1 else 2 else 3 ^ 2",
+            "2 ^ if true | 1 else 2 * 3",
+            "let x = 1 + 2 in x * x",
+            "2 * let x = 1 in x ^ 2 - 1",
+            "let x = let y = 1 in y in if x > 0 | x else -x",
+            "fn x, y => x + y * 2",
+            "f() + g(1)(2, 3 < 4)",
+            "-f(1) ^ (fn => 2)()",
+            "let rec f = fn n => if n == 0 | 1 else f(n - 1) in f(3)",
+        ] {
+            let tokens = Tokenizer::new(source).run().unwrap();
+            assert_eq!(pratt::parse(&tokens), parse(&tokens), "{}", source);
+        }
+    }
+
+    #[test]
+    fn spans() {
+        let e = parse_source("if true | -1 else (2)").unwrap();
+        assert_eq!(Span::new(0, 21, 1, 1), e.span());
+        match e {
+            E::IF(_, then, elze, _) => {
+                assert_eq!(Span::new(10, 12, 1, 11), then.span());
+                assert_eq!(Span::new(18, 21, 1, 19), elze.span());
+            }
+            e => panic!("expected if, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!("expected an expression, found end of input", error(""));
+        assert_eq!("expected an expression, found end of input", error("1 +"));
+        assert_eq!(
+            "expected an operator or end of input, found `2`",
+            error("1 2")
+        );
+        assert_eq!("expected an operator or `)`, found `2`", error("(1 2"));
+        assert_eq!(
+            "expected an operator or `)`, found end of input",
+            error("(if true | 1 else 2")
+        );
+        assert_eq!(
+            "expected an operator or `|` after if-condition, found `else`",
+            error("if 1 else 2")
+        );
+        assert_eq!(
+            "expected an operator or `else` after then-branch, found end of input",
+            error("if true | 1")
+        );
+        assert_eq!("comparison operators cannot be chained", error("1 < 2 < 3"));
+        assert_eq!(
+            "expected an identifier after `let`, found `1`",
+            error("let 1 = 2 in 3")
+        );
+        assert_eq!(
+            "expected `=` after let-bound name, found `1`",
+            error("let x 1 in x")
+        );
+        assert_eq!(
+            "expected an operator or `in` after let-bound value, found end of input",
+            error("let x = 1")
+        );
+        assert_eq!(
+            "expected an identifier or `=>` after `fn`, found `1`",
+            error("fn 1 => 1")
+        );
+        assert_eq!(
+            "expected `,` or `=>` after parameter, found `1`",
+            error("fn x 1")
+        );
+        assert_eq!(
+            "expected an identifier after `,`, found `=>`",
+            error("fn x, => 1")
+        );
+        assert_eq!(
+            "expected an operator, `,` or `)`, found `2`",
+            error("f(1 2)")
+        );
+    }
+
+    #[test]
+    fn error_location() {
+        let e = parse_source("(1 +\n(2)").unwrap_err();
+        assert_eq!(Span::new(8, 8, 2, 4), e.span);
+        assert_eq!(Some((Span::new(0, 1, 1, 1), "unclosed `(`")), e.related);
+
+        let e = parse_source("1 < 2 == 3").unwrap_err();
+        assert_eq!(Span::new(6, 8, 1, 7), e.span);
+        assert_eq!(Some((Span::new(0, 5, 1, 1), "first comparison")), e.related);
+    }
+}
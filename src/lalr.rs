@@ -0,0 +1,709 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use crate::pratt::Assoc;
+
+/// A terminal or nonterminal of a `Grammar`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(usize);
+
+/// The end of input, the first terminal of every grammar
+pub const EOF: Symbol = Symbol(0);
+
+/// The start symbol of the augmented grammar, `ACCEPT -> start`
+const ACCEPT: Symbol = Symbol(1);
+
+/// Stands for "any lookahead" while lookaheads are propagated between states
+const ANY: Symbol = Symbol(usize::MAX);
+
+struct Production<V> {
+    lhs: Symbol,
+    rhs: Vec<Symbol>,
+    /// Set with `production_prec`, otherwise the last terminal of `rhs`
+    /// decides the precedence of the production
+    prec: Option<Symbol>,
+    /// Builds the value of `lhs` from the values of `rhs`
+    action: fn(Vec<V>) -> V,
+}
+
+/// A context-free grammar with yacc-style precedence declarations, whose
+/// productions build values of type `V`.
+///
+/// The first nonterminal declared is the start symbol.
+pub struct Grammar<V> {
+    names: Vec<String>,
+    terminal: Vec<bool>,
+    precedence: Vec<Option<(u8, Assoc)>>,
+    productions: Vec<Production<V>>,
+    levels: u8,
+}
+
+impl<V> Grammar<V> {
+    pub fn new() -> Grammar<V> {
+        Grammar {
+            names: vec!["$".to_string(), "ACCEPT".to_string()],
+            terminal: vec![true, false],
+            precedence: vec![None, None],
+            productions: vec![],
+            levels: 0,
+        }
+    }
+
+    fn symbol(&mut self, name: impl Into<String>, terminal: bool) -> Symbol {
+        self.names.push(name.into());
+        self.terminal.push(terminal);
+        self.precedence.push(None);
+        Symbol(self.names.len() - 1)
+    }
+
+    pub fn terminal(&mut self, name: impl Into<String>) -> Symbol {
+        self.symbol(name, true)
+    }
+
+    pub fn nonterminal(&mut self, name: impl Into<String>) -> Symbol {
+        self.symbol(name, false)
+    }
+
+    /// Declares `terminals` on a new precedence level, binding tighter than
+    /// the levels declared before it
+    pub fn precedence(&mut self, assoc: Assoc, terminals: &[Symbol]) {
+        self.levels += 1;
+        for t in terminals {
+            self.precedence[t.0] = Some((self.levels, assoc));
+        }
+    }
+
+    pub fn production(&mut self, lhs: Symbol, rhs: &[Symbol], action: fn(Vec<V>) -> V) {
+        self.productions.push(Production {
+            lhs,
+            rhs: rhs.to_vec(),
+            prec: None,
+            action,
+        });
+    }
+
+    /// A production with the precedence of `prec` rather than that of its
+    /// last terminal, like `%prec` in yacc
+    pub fn production_prec(
+        &mut self,
+        lhs: Symbol,
+        rhs: &[Symbol],
+        prec: Symbol,
+        action: fn(Vec<V>) -> V,
+    ) {
+        self.production(lhs, rhs, action);
+        self.productions.last_mut().unwrap().prec = Some(prec);
+    }
+
+    pub fn name(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0]
+    }
+
+    fn is_terminal(&self, symbol: Symbol) -> bool {
+        self.terminal[symbol.0]
+    }
+
+    fn production_precedence(&self, production: &Production<V>) -> Option<(u8, Assoc)> {
+        let symbol = production.prec.or_else(|| {
+            production
+                .rhs
+                .iter()
+                .rev()
+                .copied()
+                .find(|s| self.is_terminal(*s))
+        })?;
+        self.precedence[symbol.0]
+    }
+}
+
+/// An LR(0) item, a rule and the position of the dot in its right-hand side
+type Item = (usize, usize);
+
+/// Lookahead terminals of a set of items
+type Items = BTreeMap<Item, BTreeSet<Symbol>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Shift(usize),
+    /// Reduce by the production with this index
+    Reduce(usize),
+    Accept,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    ShiftReduce,
+    ReduceReduce,
+}
+
+/// Two actions for the same lookahead that precedence could not decide
+/// between. Shift/reduce conflicts are resolved by shifting, reduce/reduce
+/// conflicts by reducing the production declared first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    pub state: usize,
+    pub lookahead: String,
+    /// The items that ask for the conflicting actions
+    pub items: Vec<String>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            ConflictKind::ShiftReduce => "shift/reduce",
+            ConflictKind::ReduceReduce => "reduce/reduce",
+        };
+        write!(
+            f,
+            "{} conflict in state {} on `{}`",
+            kind, self.state, self.lookahead
+        )?;
+        for item in &self.items {
+            write!(f, "\n    {}", item)?;
+        }
+        Ok(())
+    }
+}
+
+/// The ACTION and GOTO tables of a grammar, rows are states and columns
+/// are symbols
+pub struct Tables {
+    action: Vec<Vec<Option<Action>>>,
+    goto: Vec<Vec<Option<usize>>>,
+    /// Left-hand side and length of each production
+    productions: Vec<(Symbol, usize)>,
+    terminals: Vec<Symbol>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Where the engine found no action for the next input symbol
+#[derive(Debug)]
+pub struct Stuck<V> {
+    /// Index of the offending input symbol, the length of the input at the end
+    pub position: usize,
+    /// The terminals that would have been accepted instead
+    pub expected: Vec<Symbol>,
+    /// The values on the stack, innermost last
+    pub stack: Vec<V>,
+}
+
+impl Tables {
+    /// Builds the LALR(1) tables of `grammar`: the LR(0) automaton, with
+    /// lookaheads found by propagating them between kernel items
+    pub fn build<V>(grammar: &Grammar<V>) -> Tables {
+        let builder = Builder::new(grammar);
+
+        let mut kernels: Vec<BTreeSet<Item>> = vec![BTreeSet::from([(0, 0)])];
+        let mut index: BTreeMap<BTreeSet<Item>, usize> = BTreeMap::from([(kernels[0].clone(), 0)]);
+        let mut transitions: Vec<BTreeMap<Symbol, usize>> = vec![];
+        let mut state = 0;
+        while state < kernels.len() {
+            let kernel = kernels[state]
+                .iter()
+                .map(|i| (*i, BTreeSet::new()))
+                .collect();
+            let mut targets: BTreeMap<Symbol, BTreeSet<Item>> = BTreeMap::new();
+            for (rule, dot) in builder.closure(kernel).into_keys() {
+                if let Some(x) = builder.next((rule, dot)) {
+                    targets.entry(x).or_default().insert((rule, dot + 1));
+                }
+            }
+            let mut edges = BTreeMap::new();
+            for (x, target) in targets {
+                let to = *index.entry(target.clone()).or_insert_with(|| {
+                    kernels.push(target);
+                    kernels.len() - 1
+                });
+                edges.insert(x, to);
+            }
+            transitions.push(edges);
+            state += 1;
+        }
+
+        // a lookahead is either generated inside a state, or propagated
+        // from the kernel item an item was derived from
+        let mut lookaheads: Vec<Items> = kernels
+            .iter()
+            .map(|k| k.iter().map(|i| (*i, BTreeSet::new())).collect())
+            .collect();
+        lookaheads[0].get_mut(&(0, 0)).unwrap().insert(EOF);
+        let mut propagate: Vec<((usize, Item), (usize, Item))> = vec![];
+        for (state, kernel) in kernels.iter().enumerate() {
+            for k in kernel {
+                let items = builder.closure(BTreeMap::from([(*k, BTreeSet::from([ANY]))]));
+                for (item, symbols) in items {
+                    let x = match builder.next(item) {
+                        Some(x) => x,
+                        None => continue,
+                    };
+                    let target = (transitions[state][&x], (item.0, item.1 + 1));
+                    for a in symbols {
+                        if a == ANY {
+                            propagate.push(((state, *k), target));
+                        } else {
+                            lookaheads[target.0].get_mut(&target.1).unwrap().insert(a);
+                        }
+                    }
+                }
+            }
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for ((from, item), (to, target)) in &propagate {
+                let symbols = lookaheads[*from][item].clone();
+                let entry = lookaheads[*to].get_mut(target).unwrap();
+                for a in symbols {
+                    changed |= entry.insert(a);
+                }
+            }
+        }
+
+        let width = grammar.names.len();
+        let mut tables = Tables {
+            action: vec![vec![None; width]; kernels.len()],
+            goto: vec![vec![None; width]; kernels.len()],
+            productions: grammar
+                .productions
+                .iter()
+                .map(|p| (p.lhs, p.rhs.len()))
+                .collect(),
+            terminals: (0..width)
+                .map(Symbol)
+                .filter(|s| grammar.is_terminal(*s))
+                .collect(),
+            conflicts: vec![],
+        };
+        for (state, kernel) in lookaheads.into_iter().enumerate() {
+            let items = builder.closure(kernel);
+            let mut reduces: BTreeMap<Symbol, Vec<usize>> = BTreeMap::new();
+            for (item, symbols) in &items {
+                if builder.next(*item).is_none() {
+                    for a in symbols {
+                        reduces.entry(*a).or_default().push(item.0);
+                    }
+                }
+            }
+            for (x, to) in &transitions[state] {
+                if !grammar.is_terminal(*x) {
+                    tables.goto[state][x.0] = Some(*to);
+                } else if !reduces.contains_key(x) {
+                    tables.action[state][x.0] = Some(Action::Shift(*to));
+                }
+            }
+            for (a, rules) in reduces {
+                let shift = transitions[state].get(&a).copied();
+                tables.action[state][a.0] =
+                    builder.decide(state, a, shift, &rules, &items, &mut tables.conflicts);
+            }
+        }
+        tables
+    }
+
+    /// Runs the shift-reduce engine over `input`, pairs of a terminal and
+    /// its value, and returns the value of the start symbol
+    pub fn parse<V>(
+        &self,
+        grammar: &Grammar<V>,
+        input: impl IntoIterator<Item = (Symbol, V)>,
+    ) -> Result<V, Stuck<V>> {
+        let mut input = input.into_iter();
+        let mut states = vec![0];
+        let mut stack: Vec<V> = vec![];
+        let mut position = 0;
+        let mut next = input.next();
+
+        loop {
+            let state = *states.last().unwrap();
+            let symbol = next.as_ref().map_or(EOF, |(s, _)| *s);
+            match self.action[state][symbol.0] {
+                Some(Action::Shift(to)) => {
+                    stack.push(next.take().unwrap().1);
+                    states.push(to);
+                    next = input.next();
+                    position += 1;
+                }
+                Some(Action::Reduce(p)) => {
+                    let (lhs, len) = self.productions[p];
+                    let values = stack.split_off(stack.len() - len);
+                    states.truncate(states.len() - len);
+                    stack.push((grammar.productions[p].action)(values));
+                    states.push(self.goto[*states.last().unwrap()][lhs.0].unwrap());
+                }
+                Some(Action::Accept) => return Ok(stack.pop().unwrap()),
+                None => {
+                    let expected = self
+                        .terminals
+                        .iter()
+                        .copied()
+                        .filter(|t| self.accepts(&states, *t))
+                        .collect();
+                    return Err(Stuck {
+                        position,
+                        expected,
+                        stack,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Whether `terminal` would be shifted after the reductions it causes.
+    /// LALR(1) tables may reduce before noticing an error, so the actions in
+    /// the current state alone can promise too much
+    fn accepts(&self, states: &[usize], terminal: Symbol) -> bool {
+        let mut states = states.to_vec();
+        loop {
+            match self.action[*states.last().unwrap()][terminal.0] {
+                Some(Action::Shift(_)) | Some(Action::Accept) => return true,
+                Some(Action::Reduce(p)) => {
+                    let (lhs, len) = self.productions[p];
+                    states.truncate(states.len() - len);
+                    states.push(self.goto[*states.last().unwrap()][lhs.0].unwrap());
+                }
+                None => return false,
+            }
+        }
+    }
+}
+
+/// What table construction needs to know about a grammar
+struct Builder<'g, V> {
+    grammar: &'g Grammar<V>,
+    /// `ACCEPT -> start` followed by the productions of the grammar
+    rules: Vec<(Symbol, Vec<Symbol>)>,
+    nullable: Vec<bool>,
+    first: Vec<BTreeSet<Symbol>>,
+}
+
+impl<'g, V> Builder<'g, V> {
+    fn new(grammar: &'g Grammar<V>) -> Builder<'g, V> {
+        let start = (0..grammar.names.len())
+            .map(Symbol)
+            .find(|s| *s != ACCEPT && !grammar.is_terminal(*s))
+            .expect("a grammar needs a nonterminal");
+        let mut rules = vec![(ACCEPT, vec![start])];
+        rules.extend(grammar.productions.iter().map(|p| (p.lhs, p.rhs.clone())));
+
+        let width = grammar.names.len();
+        let mut nullable = vec![false; width];
+        let mut first: Vec<BTreeSet<Symbol>> = (0..width)
+            .map(|s| match grammar.terminal[s] {
+                true => BTreeSet::from([Symbol(s)]),
+                false => BTreeSet::new(),
+            })
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (lhs, rhs) in &rules {
+                if !nullable[lhs.0] && rhs.iter().all(|s| nullable[s.0]) {
+                    nullable[lhs.0] = true;
+                    changed = true;
+                }
+                for s in rhs {
+                    for t in first[s.0].clone() {
+                        changed |= first[lhs.0].insert(t);
+                    }
+                    if !nullable[s.0] {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Builder {
+            grammar,
+            rules,
+            nullable,
+            first,
+        }
+    }
+
+    /// The symbol after the dot
+    fn next(&self, (rule, dot): Item) -> Option<Symbol> {
+        self.rules[rule].1.get(dot).copied()
+    }
+
+    /// The terminals that can start `symbols` followed by one of `lookaheads`
+    fn first_of(&self, symbols: &[Symbol], lookaheads: &BTreeSet<Symbol>) -> BTreeSet<Symbol> {
+        let mut first = BTreeSet::new();
+        for s in symbols {
+            first.extend(&self.first[s.0]);
+            if !self.nullable[s.0] {
+                return first;
+            }
+        }
+        first.extend(lookaheads);
+        first
+    }
+
+    /// Adds `B -> . γ` for every item `A -> α . B β`, with the lookaheads
+    /// that can follow `B`
+    fn closure(&self, mut items: Items) -> Items {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for ((rule, dot), symbols) in items.clone() {
+                let b = match self.next((rule, dot)) {
+                    Some(b) if !self.grammar.is_terminal(b) => b,
+                    _ => continue,
+                };
+                let first = self.first_of(&self.rules[rule].1[dot + 1..], &symbols);
+                for (r, (lhs, _)) in self.rules.iter().enumerate() {
+                    if *lhs != b {
+                        continue;
+                    }
+                    let entry = items.entry((r, 0)).or_insert_with(|| {
+                        changed = true;
+                        BTreeSet::new()
+                    });
+                    for a in &first {
+                        changed |= entry.insert(*a);
+                    }
+                }
+            }
+        }
+        items
+    }
+
+    /// The action for lookahead `a`, given the state it shifts to and the
+    /// rules it reduces by
+    fn decide(
+        &self,
+        state: usize,
+        a: Symbol,
+        shift: Option<usize>,
+        rules: &[usize],
+        items: &Items,
+        conflicts: &mut Vec<Conflict>,
+    ) -> Option<Action> {
+        let mut conflict = |kind, items: Vec<String>| {
+            conflicts.push(Conflict {
+                kind,
+                state,
+                lookahead: self.grammar.name(a).to_string(),
+                items,
+            })
+        };
+
+        let rule = rules[0];
+        if rules.len() > 1 {
+            let items = rules
+                .iter()
+                .map(|r| self.item((*r, self.rules[*r].1.len())))
+                .collect();
+            conflict(ConflictKind::ReduceReduce, items);
+        }
+        if rule == 0 {
+            return Some(Action::Accept);
+        }
+        let reduce = Action::Reduce(rule - 1);
+        let to = match shift {
+            Some(to) => to,
+            None => return Some(reduce),
+        };
+
+        let production = &self.grammar.productions[rule - 1];
+        match (
+            self.grammar.production_precedence(production),
+            self.grammar.precedence[a.0],
+        ) {
+            (Some((p, _)), Some((q, _))) if p > q => Some(reduce),
+            (Some((p, _)), Some((q, _))) if p < q => Some(Action::Shift(to)),
+            (Some(_), Some((_, Assoc::Left))) => Some(reduce),
+            (Some(_), Some((_, Assoc::Right))) => Some(Action::Shift(to)),
+            (Some(_), Some((_, Assoc::Non))) => None,
+            _ => {
+                let mut conflicting = vec![self.item((rule, self.rules[rule].1.len()))];
+                conflicting.extend(
+                    items
+                        .keys()
+                        .filter(|i| self.next(**i) == Some(a))
+                        .map(|i| self.item(*i)),
+                );
+                conflict(ConflictKind::ShiftReduce, conflicting);
+                Some(Action::Shift(to))
+            }
+        }
+    }
+
+    /// `E -> E . + E`
+    fn item(&self, (rule, dot): Item) -> String {
+        let (lhs, rhs) = &self.rules[rule];
+        let mut out = format!("{} ->", self.grammar.name(*lhs));
+        for (i, s) in rhs.iter().enumerate() {
+            if i == dot {
+                out += " .";
+            }
+            out += " ";
+            out += self.grammar.name(*s);
+        }
+        if dot == rhs.len() {
+            out += " .";
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sum(values: Vec<i64>) -> i64 {
+        values[0] + values[2]
+    }
+
+    fn product(values: Vec<i64>) -> i64 {
+        values[0] * values[2]
+    }
+
+    fn first(values: Vec<i64>) -> i64 {
+        values[0]
+    }
+
+    /// `E -> E + E | E * E | n`, `n` has the value of the digit
+    fn arithmetic(precedence: bool) -> (Grammar<i64>, Vec<Symbol>) {
+        let mut g = Grammar::new();
+        let e = g.nonterminal("E");
+        let (plus, times, n) = (g.terminal("+"), g.terminal("*"), g.terminal("n"));
+        if precedence {
+            g.precedence(Assoc::Left, &[plus]);
+            g.precedence(Assoc::Left, &[times]);
+        }
+        g.production(e, &[e, plus, e], sum);
+        g.production(e, &[e, times, e], product);
+        g.production(e, &[n], first);
+        (g, vec![plus, times, n])
+    }
+
+    /// Lexes digits, `+` and `*` for `arithmetic`
+    fn input(source: &str, symbols: &[Symbol]) -> Vec<(Symbol, i64)> {
+        source
+            .chars()
+            .map(|c| match c {
+                '+' => (symbols[0], 0),
+                '*' => (symbols[1], 0),
+                _ => (symbols[2], c.to_digit(10).unwrap() as i64),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        let (g, symbols) = arithmetic(true);
+        let tables = Tables::build(&g);
+        assert!(tables.conflicts.is_empty());
+        assert_eq!(7, tables.parse(&g, input("1+2*3", &symbols)).unwrap());
+        assert_eq!(10, tables.parse(&g, input("2*3+4", &symbols)).unwrap());
+    }
+
+    #[test]
+    fn shift_reduce_conflicts() {
+        let (g, _) = arithmetic(false);
+        let conflicts = Tables::build(&g).conflicts;
+        assert_eq!(4, conflicts.len());
+        let conflict = conflicts
+            .iter()
+            .find(|c| c.items[0] == "E -> E + E ." && c.lookahead == "*")
+            .unwrap();
+        assert_eq!(ConflictKind::ShiftReduce, conflict.kind);
+        assert_eq!(vec!["E -> E + E .", "E -> E . * E"], conflict.items);
+    }
+
+    #[test]
+    fn shift_wins_unresolved_conflicts() {
+        let (g, symbols) = arithmetic(false);
+        let tables = Tables::build(&g);
+        // everything groups to the right
+        assert_eq!(12, tables.parse(&g, input("2*3+1+2", &symbols)).unwrap());
+        assert_eq!(12, tables.parse(&g, input("2*3+3", &symbols)).unwrap());
+    }
+
+    #[test]
+    fn reduce_reduce_conflict() {
+        let mut g: Grammar<i64> = Grammar::new();
+        let (s, a, b) = (g.nonterminal("S"), g.nonterminal("A"), g.nonterminal("B"));
+        let x = g.terminal("x");
+        g.production(s, &[a], first);
+        g.production(s, &[b], first);
+        g.production(a, &[x], |_| 1);
+        g.production(b, &[x], |_| 2);
+
+        let tables = Tables::build(&g);
+        let conflict = &tables.conflicts[0];
+        assert_eq!(1, tables.conflicts.len());
+        assert_eq!(
+            "reduce/reduce conflict in state 4 on `$`\n    A -> x .\n    B -> x .",
+            conflict.to_string()
+        );
+        // the production declared first wins
+        assert_eq!(1, tables.parse(&g, [(x, 0)]).unwrap());
+    }
+
+    /// The classic grammar that is LALR(1) but not SLR(1)
+    #[test]
+    fn lalr_not_slr() {
+        let mut g: Grammar<i64> = Grammar::new();
+        let (s, l, r) = (g.nonterminal("S"), g.nonterminal("L"), g.nonterminal("R"));
+        let (eq, star, id) = (g.terminal("="), g.terminal("*"), g.terminal("id"));
+        g.production(s, &[l, eq, r], |v| v[0] * 10 + v[2]);
+        g.production(s, &[r], first);
+        g.production(l, &[star, r], |v| v[1] + 1);
+        g.production(l, &[id], |_| 0);
+        g.production(r, &[l], first);
+
+        let tables = Tables::build(&g);
+        assert!(tables.conflicts.is_empty());
+        let input = [(star, 0), (id, 0), (eq, 0), (star, 0), (star, 0), (id, 0)];
+        assert_eq!(12, tables.parse(&g, input).unwrap());
+    }
+
+    #[test]
+    fn empty_productions() {
+        let mut g: Grammar<i64> = Grammar::new();
+        let list = g.nonterminal("L");
+        let x = g.terminal("x");
+        g.production(list, &[], |_| 0);
+        g.production(list, &[list, x], |v| v[0] + 1);
+
+        let tables = Tables::build(&g);
+        assert!(tables.conflicts.is_empty());
+        assert_eq!(0, tables.parse(&g, []).unwrap());
+        assert_eq!(3, tables.parse(&g, [(x, 0), (x, 0), (x, 0)]).unwrap());
+    }
+
+    #[test]
+    fn non_associative() {
+        let mut g: Grammar<i64> = Grammar::new();
+        let e = g.nonterminal("E");
+        let (less, n) = (g.terminal("<"), g.terminal("n"));
+        g.precedence(Assoc::Non, &[less]);
+        g.production(e, &[e, less, e], |v| (v[0] < v[2]) as i64);
+        g.production(e, &[n], first);
+
+        let tables = Tables::build(&g);
+        assert!(tables.conflicts.is_empty());
+        assert_eq!(1, tables.parse(&g, [(n, 1), (less, 0), (n, 2)]).unwrap());
+        let stuck = tables
+            .parse(&g, [(n, 1), (less, 0), (n, 2), (less, 0), (n, 3)])
+            .unwrap_err();
+        assert_eq!(3, stuck.position);
+        assert_eq!(vec![EOF], stuck.expected);
+        assert_eq!(vec![1, 0, 2], stuck.stack);
+    }
+
+    #[test]
+    fn expected_after_reductions() {
+        let (g, symbols) = arithmetic(true);
+        let tables = Tables::build(&g);
+        let stuck = tables.parse(&g, input("1+23", &symbols)).unwrap_err();
+        assert_eq!(3, stuck.position);
+        assert_eq!(vec![EOF, symbols[0], symbols[1]], stuck.expected);
+        let stuck = tables.parse(&g, input("1+", &symbols)).unwrap_err();
+        assert_eq!(vec![symbols[2]], stuck.expected);
+    }
+}
@@ -1,6 +1,185 @@
+#![allow(clippy::upper_case_acronyms)]
+
+mod bytecode;
+mod c;
+mod cli;
+mod codegen;
+mod diagnostic;
+#[cfg(test)]
+mod differential;
+mod elf;
+mod encoder;
+mod grammar;
+mod lalr;
 mod parser;
 mod pratt;
 mod resolver;
+mod span;
 mod tokenizer;
+mod typeck;
+mod wasm;
+
+use std::{fs, io, process::ExitCode};
+
+use cli::{Command, Emit, Engine, Input, Options, ParserKind, Target};
+use diagnostic::Diagnostic;
+use parser::Parser;
+use pratt::S;
+use resolver::{execute, Value};
+use tokenizer::Tokenizer;
+
+fn main() -> ExitCode {
+    let (options, output) = match cli::parse(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => (options, None),
+        Ok(Command::Build(options, output)) => (options, Some(output)),
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            return ExitCode::from(cli::EXIT_USAGE);
+        }
+    };
+
+    match run(&options, output.as_deref()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(code) => ExitCode::from(code),
+    }
+}
+
+/// Runs the program, or compiles it to the executable `output`
+fn run(options: &Options, output: Option<&str>) -> Result<(), u8> {
+    let (source, name) = match &options.input {
+        Input::Expr(expr) => (expr.clone(), "<expr>".to_string()),
+        Input::File(path) => match fs::read_to_string(path) {
+            Ok(source) => (source, path.clone()),
+            Err(e) => {
+                eprintln!("error: could not read `{}`: {}", path, e);
+                return Err(cli::EXIT_IO);
+            }
+        },
+    };
+    let color = options.color.enabled();
+    let report = |diagnostic: Diagnostic| eprintln!("{}", diagnostic.render(&source, &name, color));
+
+    let tokens = Tokenizer::new(&source).run().map_err(|errors| {
+        errors.iter().map(Diagnostic::from).for_each(report);
+        cli::EXIT_LEX
+    })?;
+    if options.emit == Emit::Tokens {
+        for token in &tokens {
+            println!("{}:{} {:?}", token.span.line, token.span.col, token.kind);
+        }
+        return Ok(());
+    }
+
+    // the LR and LALR parsers only parse single expressions
+    let program = match options.parser {
+        ParserKind::Pratt => pratt::program(&tokens),
+        ParserKind::LR => Parser::new(&tokens).run().map(|e| vec![S::EXPR(e)]),
+        ParserKind::LALR => grammar::parse(&tokens).map(|e| vec![S::EXPR(e)]),
+    }
+    .map_err(|e| {
+        report(Diagnostic::from(&*e));
+        cli::EXIT_PARSE
+    })?;
+    if options.emit == Emit::Ast {
+        for statement in &program {
+            println!("{:?}", statement);
+        }
+        return Ok(());
+    }
+
+    let types = typeck::check(&program).map_err(|e| {
+        report(Diagnostic::from(&e));
+        cli::EXIT_TYPE
+    })?;
+    if options.emit == Emit::Types {
+        for statement in &program {
+            if let S::LET(name, value, _) | S::LETREC(name, value, _) | S::VAR(name, value, _) =
+                statement
+            {
+                let t = types.get(value).expect("every expression has a type");
+                println!("{}: {}", name, t);
+            }
+        }
+        println!("{}", types.program);
+        return Ok(());
+    }
+    if options.target == Target::C && (options.emit == Emit::Asm || output.is_some()) {
+        let source = c::compile(&program);
+        let Some(output) = output else {
+            print!("{}", source);
+            return Ok(());
+        };
+        return fs::write(output, source).map_err(|e| {
+            eprintln!("error: could not write `{}`: {}", output, e);
+            cli::EXIT_IO
+        });
+    }
+    if options.target == Target::Wasm && (options.emit == Emit::Asm || output.is_some()) {
+        let module = wasm::compile(&program).map_err(|e| {
+            report(Diagnostic::from(&e));
+            cli::EXIT_CODEGEN
+        })?;
+        let Some(output) = output else {
+            println!("{}", module);
+            return Ok(());
+        };
+        return fs::write(output, module.encode()).map_err(|e| {
+            eprintln!("error: could not write `{}`: {}", output, e);
+            cli::EXIT_IO
+        });
+    }
+    if options.emit == Emit::Asm || output.is_some() {
+        let assembly = codegen::compile(&program, &types).map_err(|e| {
+            report(Diagnostic::from(&e));
+            cli::EXIT_CODEGEN
+        })?;
+        let Some(output) = output else {
+            print!("{}", assembly);
+            return Ok(());
+        };
+        let executable = elf::executable(&encoder::encode(&assembly));
+        return write_executable(output, &executable).map_err(|e| {
+            eprintln!("error: could not write `{}`: {}", output, e);
+            cli::EXIT_IO
+        });
+    }
+    let bytecode = match (options.engine, options.emit) {
+        (_, Emit::Bytecode) | (Engine::VM, _) => Some(bytecode::compile(&program)),
+        _ => None,
+    };
+    if options.emit == Emit::Bytecode {
+        print!("{}", bytecode.unwrap());
+        return Ok(());
+    }
+
+    // the value to print, none for unit
+    let value = match bytecode {
+        Some(bytecode) => bytecode::run(&bytecode, &mut io::stdout())
+            .map(|v| (!matches!(v, bytecode::Value::Unit)).then(|| v.to_string())),
+        None => {
+            execute(program, &mut io::stdout()).map(|v| (v != Value::Unit).then(|| v.to_string()))
+        }
+    }
+    .map_err(|e| {
+        report(Diagnostic::from(&e));
+        cli::EXIT_RUNTIME
+    })?;
+    if let Some(value) = value {
+        println!("{}", value);
+    }
+    Ok(())
+}
 
-fn main() {}
+fn write_executable(path: &str, bytes: &[u8]) -> io::Result<()> {
+    fs::write(path, bytes)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
@@ -1,178 +1,399 @@
-use core::panic;
 use std::{iter::Peekable, slice::Iter};
 
-use crate::{pratt::E, tokenizer::{Token, OP}};
+use crate::{
+    pratt::{end_of_input, infix, Assoc, Expected, ParseError, E, FN_HELP, IF_HELP, LET_HELP},
+    span::Span,
+    tokenizer::{Token, TokenKind, OP},
+};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 enum Type {
     EXPRESSION(E),
-    LITERAL(u32),
+    /// A binary operator
     OP(OP),
-    LPAREN,
-    RPAREN,
-}
-
-impl OP {
-    fn from(token: &Token) -> Option<OP> {
-        match token {
-            Token::OPERATOR(OP::PLUS) => Some(OP::PLUS),
-            Token::OPERATOR(OP::MINUS) => Some(OP::MINUS),
-            Token::OPERATOR(OP::MULT) => Some(OP::MULT),
-            Token::OPERATOR(OP::DIV) => Some(OP::DIV),
-            _ => None,
-        }
-    }
+    /// A prefix operator, it does not follow an expression
+    UNARY(OP, Span),
+    LPAREN(Span),
+    RPAREN(Span),
+    IF(Span),
+    THEN,
+    ELSE,
+    /// `let`, or `let rec` when the flag is set
+    LET(Span, bool),
+    /// The name bound by a `let`
+    NAME(String),
+    ASSIGN,
+    IN,
+    /// `fn` and the parameters read so far, a comma has been read when the
+    /// list is not empty and no `PARAM` follows
+    FN(Span, Vec<String>),
+    PARAM(String),
+    ARROW,
+    /// The `(` of a call and the arguments before the last comma
+    CALL(Span, Vec<E>),
 }
 
 #[derive(Debug)]
 pub struct Parser<'a> {
     parse_stack: Vec<Type>,
+    input: &'a [Token],
     tokens: Peekable<Iter<'a, Token>>,
 }
 
-// TODO complete me
 // BNF
-// VALUE ::= LITERAL
+// VALUE ::= LITERAL | BOOL | IDENT
+// PARAMS ::= IDENT | PARAMS COMMA IDENT
+// ARGS ::= EXPRESSION | ARGS COMMA EXPRESSION
+// OP_PREC_0 = EQUALITY | GREATER | LESS | GEQ | LEQ
 // OP_PREC_1 = PLUS | MINUS
 // OP_PREC_2 = MULT | DIV
-// EXPRESSION ::= EXPRESSION POW EXPRESSION
+// EXPRESSION ::= PLUS EXPRESSION | MINUS EXPRESSION
+//                | EXPRESSION POW EXPRESSION
 //                | EXPRESSION OP_PREC_2 EXPRESSION
 //                | EXPRESSION OP_PREC_1 EXPRESSION
+//                | EXPRESSION OP_PREC_0 EXPRESSION
+//                | IF EXPRESSION THEN EXPRESSION ELSE EXPRESSION
+//                | LET IDENT ASSIGN EXPRESSION IN EXPRESSION
+//                | LET REC IDENT ASSIGN EXPRESSION IN EXPRESSION
+//                | FN ARROW EXPRESSION | FN PARAMS ARROW EXPRESSION
+//                | EXPRESSION LPAREN RPAREN | EXPRESSION LPAREN ARGS RPAREN
 //                | LPAREN EXPRESSION RPAREN
 //                | VALUE
+//
+// Calls bind tightest. Prefix operators bind tighter than POW. The else
+// branch of IF and the bodies of LET and FN extend as far as they can.
+// POW is right-associative, OP_PREC_0 cannot be chained and the rest are
+// left-associative, the same as `pratt::infix`.
 impl<'a> Parser<'a> {
-    pub fn new(tokens: &'a Vec<Token>) -> Parser<'a> {
+    pub fn new(tokens: &'a [Token]) -> Parser<'a> {
         let a = tokens.iter();
         Parser {
             parse_stack: vec![],
+            input: tokens,
             tokens: a.peekable(),
         }
     }
 
-    fn shift(&mut self) -> bool {
-        match self.tokens.next() {
-            Some(t) => {
-                match t {
-                    // cheat and insert literal as expression immediatly
-                    Token::LITERAL(v) => self
-                        .parse_stack
-                        .push(Type::EXPRESSION(E::LITERAL(*v))),
-                    Token::OPERATOR(OP::PLUS) => self.parse_stack.push(Type::OP(OP::PLUS)),
-                    Token::OPERATOR(OP::MULT) => self.parse_stack.push(Type::OP(OP::MULT)),
-                    Token::LPAREN => self.parse_stack.push(Type::LPAREN),
-                    Token::RPAREN => self.parse_stack.push(Type::RPAREN),
-                    Token::OPERATOR(OP::POW) => self.parse_stack.push(Type::OP(OP::POW)),
-                    _ => todo!(),
+    /// Pushes the next token, returns false at the end of input. Tokens that
+    /// cannot follow the top of the stack are rejected here, so the stack is
+    /// always the start of a valid expression
+    fn shift(&mut self) -> Result<bool, Box<ParseError>> {
+        let t = match self.tokens.next() {
+            Some(t) => t,
+            None => return Ok(false),
+        };
+        match (&t.kind, self.parse_stack.last_mut()) {
+            (TokenKind::REC, Some(Type::LET(_, rec @ false))) => {
+                *rec = true;
+                return Ok(true);
+            }
+            (TokenKind::IDENT(name), Some(Type::LET(..))) => {
+                self.parse_stack.push(Type::NAME(name.clone()));
+                return Ok(true);
+            }
+            (TokenKind::ASSIGN, Some(Type::NAME(_))) => {
+                self.parse_stack.push(Type::ASSIGN);
+                return Ok(true);
+            }
+            (TokenKind::IDENT(name), Some(Type::FN(..))) => {
+                self.parse_stack.push(Type::PARAM(name.clone()));
+                return Ok(true);
+            }
+            (TokenKind::ARROW, Some(Type::FN(_, params))) if params.is_empty() => {
+                self.parse_stack.push(Type::ARROW);
+                return Ok(true);
+            }
+            (TokenKind::COMMA | TokenKind::ARROW, Some(Type::PARAM(_))) => {
+                let Some(Type::PARAM(name)) = self.parse_stack.pop() else {
+                    unreachable!("a parameter is on top")
+                };
+                if let Some(Type::FN(_, params)) = self.parse_stack.last_mut() {
+                    params.push(name);
+                }
+                if t.kind == TokenKind::ARROW {
+                    self.parse_stack.push(Type::ARROW);
                 }
-                true
+                return Ok(true);
+            }
+            (TokenKind::RPAREN, Some(Type::CALL(_, args))) if args.is_empty() => {
+                self.parse_stack.push(Type::RPAREN(t.span));
+                return Ok(true);
+            }
+            (_, Some(Type::LET(..) | Type::NAME(_) | Type::FN(..) | Type::PARAM(_))) => {
+                return Err(self.expected_next(Some(t)))
             }
-            None => false,
+            _ => (),
         }
-    }
 
-    fn peek_three(&self) -> Option<(&Type, &Type, &Type)> {
-        if self.parse_stack.len() < 3 {
-            None
-        } else {
-            let last_idx = self.parse_stack.len() - 1;
-            let last = self.parse_stack.get(last_idx).unwrap();
-            let second_last = self.parse_stack.get(last_idx - 1).unwrap();
-            let third_last = self.parse_stack.get(last_idx - 2).unwrap();
-            Some((third_last, second_last, last))
-        }
+        let after_expression = matches!(self.parse_stack.last(), Some(Type::EXPRESSION(_)));
+        let item = match (&t.kind, after_expression, self.enclosing()) {
+            // cheat and insert literal as expression immediatly
+            (TokenKind::LITERAL(v), false, _) => Type::EXPRESSION(E::LITERAL(*v, t.span)),
+            (TokenKind::BOOL(b), false, _) => Type::EXPRESSION(E::BOOL(*b, t.span)),
+            (TokenKind::IDENT(name), false, _) => Type::EXPRESSION(E::VAR(name.clone(), t.span)),
+            (TokenKind::OPERATOR(op @ (OP::PLUS | OP::MINUS)), false, _) => {
+                Type::UNARY(op.clone(), t.span)
+            }
+            (TokenKind::LPAREN, false, _) => Type::LPAREN(t.span),
+            (TokenKind::IF, false, _) => Type::IF(t.span),
+            (TokenKind::LET, false, _) => Type::LET(t.span, false),
+            (TokenKind::FN, false, _) => Type::FN(t.span, vec![]),
+            (_, false, _) => return Err(self.expected_next(Some(t))),
+            (TokenKind::OPERATOR(op), true, _) => Type::OP(op.clone()),
+            (TokenKind::LPAREN, true, _) => Type::CALL(t.span, vec![]),
+            (TokenKind::RPAREN, true, Some(Type::LPAREN(_) | Type::CALL(..))) => {
+                Type::RPAREN(t.span)
+            }
+            (TokenKind::COMMA, true, Some(Type::CALL(..))) => {
+                // the argument joins the others in the call
+                let Some(Type::EXPRESSION(arg)) = self.parse_stack.pop() else {
+                    unreachable!("an expression is on top")
+                };
+                if let Some(Type::CALL(_, args)) = self.parse_stack.last_mut() {
+                    args.push(arg);
+                }
+                return Ok(true);
+            }
+            (TokenKind::THEN, true, Some(Type::IF(_))) => Type::THEN,
+            (TokenKind::ELSE, true, Some(Type::THEN)) => Type::ELSE,
+            (TokenKind::IN, true, Some(Type::ASSIGN)) => Type::IN,
+            (_, true, _) => return Err(self.unexpected(Some(t))),
+        };
+        self.parse_stack.push(item);
+        Ok(true)
     }
 
-    fn pop(&mut self, n: u32) {
-        (0..n).for_each(|_| {
-            self.parse_stack.pop();
-        });
+    /// The item below the expression on top of the stack. Once everything
+    /// that can be is reduced, this is the construct the expression is part of
+    fn enclosing(&self) -> Option<&Type> {
+        self.parse_stack.iter().rev().nth(1)
     }
 
-    fn check_precedence(&mut self) -> bool {
-        if self.parse_stack.len() < 2 {
-            return false;
+    /// An error for `found` where it cannot be shifted, based on what is on
+    /// top of the stack
+    fn expected_next(&self, found: Option<&Token>) -> Box<ParseError> {
+        match self.parse_stack.iter().rev().take(2).collect::<Vec<_>>()[..] {
+            [Type::EXPRESSION(_), ..] => self.unexpected(found),
+            [Type::LET(..), ..] => ParseError::boxed(vec![Expected::Identifier], found)
+                .context("after `let`")
+                .help(LET_HELP),
+            [Type::NAME(_), Type::LET(span, _)] => {
+                ParseError::boxed(vec![Expected::Token(TokenKind::ASSIGN)], found)
+                    .context("after let-bound name")
+                    .related(*span, "`let` starts here")
+                    .help(LET_HELP)
+            }
+            [Type::FN(_, params), ..] if params.is_empty() => ParseError::boxed(
+                vec![Expected::Identifier, Expected::Token(TokenKind::ARROW)],
+                found,
+            )
+            .context("after `fn`")
+            .help(FN_HELP),
+            [Type::FN(..), ..] => ParseError::boxed(vec![Expected::Identifier], found)
+                .context("after `,`")
+                .help(FN_HELP),
+            [Type::PARAM(_), ..] => ParseError::boxed(
+                vec![
+                    Expected::Token(TokenKind::COMMA),
+                    Expected::Token(TokenKind::ARROW),
+                ],
+                found,
+            )
+            .context("after parameter")
+            .help(FN_HELP),
+            _ => ParseError::boxed(vec![Expected::Expression], found),
         }
+    }
 
-        let peek = self.tokens.peek();
-        let peek_precedence = match peek {
-            Some(Token::OPERATOR(OP::PLUS)) | Some(Token::OPERATOR(OP::MINUS)) => Some(1),
-            Some(Token::OPERATOR(OP::MULT)) => Some(2),
-            Some(Token::OPERATOR(OP::POW)) => Some(3),
-            _ => None,
-        };
-
-        let prev = self.parse_stack.get(self.parse_stack.len() - 2);
+    /// An error for `found` following a complete expression, it says what
+    /// would have continued the enclosing construct
+    fn unexpected(&self, found: Option<&Token>) -> Box<ParseError> {
+        match self.enclosing() {
+            Some(Type::LPAREN(span)) => ParseError::boxed(
+                vec![Expected::Operator, Expected::Token(TokenKind::RPAREN)],
+                found,
+            )
+            .related(*span, "unclosed `(`"),
+            Some(Type::CALL(span, _)) => ParseError::boxed(
+                vec![
+                    Expected::Operator,
+                    Expected::Token(TokenKind::COMMA),
+                    Expected::Token(TokenKind::RPAREN),
+                ],
+                found,
+            )
+            .related(*span, "unclosed `(`"),
+            Some(Type::IF(span)) => {
+                ParseError::boxed(vec![Expected::Token(TokenKind::THEN)], found)
+                    .context("after if-condition")
+                    .related(*span, "`if` starts here")
+                    .help(IF_HELP)
+            }
+            Some(Type::THEN) => {
+                let error = ParseError::boxed(vec![Expected::Token(TokenKind::ELSE)], found)
+                    .context("after then-branch")
+                    .help(IF_HELP);
+                match self.parse_stack.iter().rev().nth(3) {
+                    Some(Type::IF(span)) => error.related(*span, "this `if` has no `else`"),
+                    _ => error,
+                }
+            }
+            Some(Type::ASSIGN) => {
+                let error = ParseError::boxed(vec![Expected::Token(TokenKind::IN)], found)
+                    .context("after let-bound value")
+                    .help(LET_HELP);
+                match self.parse_stack.iter().rev().nth(3) {
+                    Some(Type::LET(span, _)) => error.related(*span, "`let` starts here"),
+                    _ => error,
+                }
+            }
+            _ => ParseError::boxed(vec![Expected::Operator, Expected::EndOfInput], found),
+        }
+    }
 
-        let prev_precedence = match prev {
-            Some(Type::OP(OP::PLUS)) | Some(Type::OP(OP::MINUS)) => Some(1),
-            Some(Type::OP(OP::MULT)) => Some(2),
-            Some(Type::OP(OP::POW)) => Some(3),
-            _ => None,
+    /// Whether the operator after `left op right` binds tighter than `op`, in
+    /// which case it is shifted before `op` is reduced. `first` is the span of
+    /// `left op right`, for reporting chained comparisons
+    fn check_precedence(
+        op: &OP,
+        first: Span,
+        next: Option<&Token>,
+    ) -> Result<bool, Box<ParseError>> {
+        let (token, next_op) = match next {
+            Some(
+                t @ Token {
+                    kind: TokenKind::OPERATOR(next_op),
+                    ..
+                },
+            ) => (t, next_op),
+            _ => return Ok(false),
         };
 
-        match (prev_precedence, peek_precedence) {
-            (None, _) | (_, None) => false,
-            (Some(prev), Some(peek)) => peek > prev,
+        let ((power, assoc), (next_power, _)) = (infix(op), infix(next_op));
+        match assoc {
+            _ if power != next_power => Ok(next_power > power),
+            Assoc::Left => Ok(false),
+            Assoc::Right => Ok(true),
+            Assoc::Non => Err(ParseError::chained_comparison(token, first)),
         }
     }
 
-    fn reduce(&mut self) -> bool {
-        // TODO maybe check the last operator on the top level and compare precedence with the next
-        // reduce now if the incoming is lower, otherwise
-        // skip and shift
-        if self.check_precedence() {
-            return false;
+    fn reduce(&mut self) -> Result<bool, Box<ParseError>> {
+        let next = self.tokens.peek().copied();
+        // a call binds tighter than anything the callee could be part of
+        if let (Some(Type::EXPRESSION(_)), Some(TokenKind::LPAREN)) =
+            (self.parse_stack.last(), next.map(|t| &t.kind))
+        {
+            return Ok(false);
         }
-
-        if match self.peek_three() {
-            Some(tokens) => match tokens {
-                // EXPRESSION ::= EXPRESSION OP EXPRESSION
-                (Type::EXPRESSION(v1), Type::OP(op), Type::EXPRESSION(v2)) => {
-                    let e =
-                        E::BINARY(Box::new(v1.clone()), op.clone(), Box::new(v2.clone()));
-                    self.pop(3);
-                    self.parse_stack.push(Type::EXPRESSION(e));
-                    true
+        let (size, e) = match self.parse_stack.as_slice() {
+            // EXPRESSION ::= LPAREN EXPRESSION RPAREN
+            [.., Type::LPAREN(l), Type::EXPRESSION(e), Type::RPAREN(r)] => {
+                (3, E::PAREN(Box::new(e.clone()), l.to(*r)))
+            }
+            // EXPRESSION ::= PLUS EXPRESSION | MINUS EXPRESSION
+            [.., Type::UNARY(op, span), Type::EXPRESSION(e)] => {
+                let span = span.to(e.span());
+                (2, E::UNARY(op.clone(), Box::new(e.clone()), span))
+            }
+            // EXPRESSION ::= IF EXPRESSION THEN EXPRESSION ELSE EXPRESSION
+            [.., Type::IF(span), Type::EXPRESSION(cond), Type::THEN, Type::EXPRESSION(then), Type::ELSE, Type::EXPRESSION(elze)] =>
+            {
+                // like the body of a let, the else branch takes any operator
+                // that follows it
+                if let Some(TokenKind::OPERATOR(_)) = next.map(|t| &t.kind) {
+                    return Ok(false);
                 }
-                (Type::LPAREN, Type::EXPRESSION(e), Type::RPAREN) => {
-                    let e = E::PAREN(Box::new(e.clone()));
-                    self.pop(3);
-                    self.parse_stack.push(Type::EXPRESSION(e));
-                    true
+                let span = span.to(elze.span());
+                let e = E::IF(
+                    Box::new(cond.clone()),
+                    Box::new(then.clone()),
+                    Box::new(elze.clone()),
+                    span,
+                );
+                (6, e)
+            }
+            // EXPRESSION ::= LET IDENT ASSIGN EXPRESSION IN EXPRESSION
+            //                | LET REC IDENT ASSIGN EXPRESSION IN EXPRESSION
+            [.., Type::LET(span, rec), Type::NAME(name), Type::ASSIGN, Type::EXPRESSION(value), Type::IN, Type::EXPRESSION(body)] =>
+            {
+                // the body takes any operator that follows it
+                if let Some(TokenKind::OPERATOR(_)) = next.map(|t| &t.kind) {
+                    return Ok(false);
                 }
-                _ => false,
-            },
-            _ => false,
-        } {
-            return true;
-        }
+                let span = span.to(body.span());
+                let (name, value, body) = (
+                    name.clone(),
+                    Box::new(value.clone()),
+                    Box::new(body.clone()),
+                );
+                let e = if *rec {
+                    E::LETREC(name, value, body, span)
+                } else {
+                    E::LET(name, value, body, span)
+                };
+                (6, e)
+            }
+            // EXPRESSION ::= FN ARROW EXPRESSION | FN PARAMS ARROW EXPRESSION
+            [.., Type::FN(span, params), Type::ARROW, Type::EXPRESSION(body)] => {
+                if let Some(TokenKind::OPERATOR(_)) = next.map(|t| &t.kind) {
+                    return Ok(false);
+                }
+                let span = span.to(body.span());
+                (3, E::FN(params.clone(), Box::new(body.clone()), span))
+            }
+            // EXPRESSION ::= EXPRESSION LPAREN RPAREN
+            [.., Type::EXPRESSION(callee), Type::CALL(_, args), Type::RPAREN(r)] => {
+                let span = callee.span().to(*r);
+                (3, E::CALL(Box::new(callee.clone()), args.clone(), span))
+            }
+            // EXPRESSION ::= EXPRESSION LPAREN ARGS RPAREN
+            [.., Type::EXPRESSION(callee), Type::CALL(_, args), Type::EXPRESSION(last), Type::RPAREN(r)] =>
+            {
+                let span = callee.span().to(*r);
+                let mut args = args.clone();
+                args.push(last.clone());
+                (4, E::CALL(Box::new(callee.clone()), args, span))
+            }
+            // EXPRESSION ::= EXPRESSION OP EXPRESSION
+            [.., Type::EXPRESSION(v1), Type::OP(op), Type::EXPRESSION(v2)] => {
+                let span = v1.span().to(v2.span());
+                if Parser::check_precedence(op, span, next)? {
+                    return Ok(false);
+                }
+                let e = E::BINARY(Box::new(v1.clone()), op.clone(), Box::new(v2.clone()), span);
+                (3, e)
+            }
+            _ => return Ok(false),
+        };
 
-        false
+        self.parse_stack.truncate(self.parse_stack.len() - size);
+        self.parse_stack.push(Type::EXPRESSION(e));
+        Ok(true)
     }
 
-    pub fn run(&mut self) -> &E {
-        let mut go = true;
-        while go {
-            let shifted = self.shift();
-            let reduced = self.reduce();
-            let mut reduced_current = reduced;
-            while reduced_current {
-                reduced_current = self.reduce();
+    /// Parses all tokens as a single expression
+    pub fn run(&mut self) -> Result<E, Box<ParseError>> {
+        let result = self.parse();
+
+        // errors at the end of input get their location here, as in
+        // `pratt::parse`
+        result.map_err(|mut e| {
+            if e.found.is_none() {
+                e.span = end_of_input(self.input);
             }
-            go = shifted || reduced;
+            e
+        })
+    }
+
+    fn parse(&mut self) -> Result<E, Box<ParseError>> {
+        while self.shift()? {
+            while self.reduce()? {}
         }
 
-        if self.parse_stack.len() == 1 {
-            match self.parse_stack.first().unwrap() {
-                Type::EXPRESSION(a) => a,
-                a => {
-                    dbg!(a);
-                    panic!("Malformed program")
-                }
-            }
-        } else {
-            dbg!(&self.parse_stack);
-            panic!("Malformed program");
+        match self.parse_stack.as_slice() {
+            [Type::EXPRESSION(e)] => Ok(e.clone()),
+            _ => Err(self.expected_next(None)),
         }
     }
 }
@@ -180,27 +401,39 @@ impl<'a> Parser<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        pratt::{self, ParseErrorKind},
+        tokenizer::Tokenizer,
+    };
 
-    fn test(tokens: Vec<Token>, result: E) {
+    fn binary(left: Box<E>, op: OP, right: Box<E>) -> E {
+        E::BINARY(left, op, right, Span::default())
+    }
+
+    fn test(tokens: Vec<TokenKind>, result: E) {
+        let tokens: Vec<Token> = tokens.into_iter().map(Token::from).collect();
         let mut parser = Parser::new(&tokens);
-        let ast = parser.run();
-        assert_eq!(result, *ast)
+        assert_eq!(result, parser.run().unwrap())
     }
 
     #[test]
     fn single_literal() {
-        let tokens = vec![Token::LITERAL(10)];
-        let result = E::LITERAL(10);
+        let tokens = vec![TokenKind::LITERAL(10)];
+        let result = E::LITERAL(10, Span::default());
         test(tokens, result);
     }
 
     #[test]
     fn addition() {
-        let tokens = vec![Token::LITERAL(10), Token::OPERATOR(OP::PLUS), Token::LITERAL(15)];
-        let result = E::BINARY(
-            Box::new(E::LITERAL(10)),
+        let tokens = vec![
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(15),
+        ];
+        let result = binary(
+            Box::new(E::LITERAL(10, Span::default())),
             OP::PLUS,
-            Box::new(E::LITERAL(15)),
+            Box::new(E::LITERAL(15, Span::default())),
         );
         test(tokens, result);
     }
@@ -208,47 +441,47 @@ mod tests {
     #[test]
     fn sum() {
         let tokens = vec![
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::PLUS),
-            Token::LITERAL(15),
-            Token::OPERATOR(OP::PLUS),
-            Token::LITERAL(10),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(15),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(10),
         ];
-        let result = E::BINARY(
-            Box::new(E::BINARY(
-                Box::new(E::LITERAL(10)),
+        let result = binary(
+            Box::new(binary(
+                Box::new(E::LITERAL(10, Span::default())),
                 OP::PLUS,
-                Box::new(E::LITERAL(15)),
+                Box::new(E::LITERAL(15, Span::default())),
             )),
             OP::PLUS,
-            Box::new(E::LITERAL(10)),
+            Box::new(E::LITERAL(10, Span::default())),
         );
         test(tokens, result);
     }
 
     #[test]
     fn paren() {
-        let tokens = vec![Token::LPAREN, Token::LITERAL(15), Token::RPAREN];
-        let result = E::PAREN(Box::new(E::LITERAL(15)));
+        let tokens = vec![TokenKind::LPAREN, TokenKind::LITERAL(15), TokenKind::RPAREN];
+        let result = E::PAREN(Box::new(E::LITERAL(15, Span::default())), Span::default());
         test(tokens, result);
     }
 
     #[test]
     fn plus_then_mult() {
         let tokens = vec![
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::PLUS),
-            Token::LITERAL(15),
-            Token::OPERATOR(OP::MULT),
-            Token::LITERAL(10),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(15),
+            TokenKind::OPERATOR(OP::MULT),
+            TokenKind::LITERAL(10),
         ];
-        let result = E::BINARY(
-            Box::new(E::LITERAL(10)),
+        let result = binary(
+            Box::new(E::LITERAL(10, Span::default())),
             OP::PLUS,
-            Box::new(E::BINARY(
-                Box::new(E::LITERAL(15)),
+            Box::new(binary(
+                Box::new(E::LITERAL(15, Span::default())),
                 OP::MULT,
-                Box::new(E::LITERAL(10)),
+                Box::new(E::LITERAL(10, Span::default())),
             )),
         );
         test(tokens, result);
@@ -257,25 +490,25 @@ mod tests {
     #[test]
     fn plus_then_mult_then_mult() {
         let tokens = vec![
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::PLUS),
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::MULT),
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::MULT),
-            Token::LITERAL(10),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::MULT),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::MULT),
+            TokenKind::LITERAL(10),
         ];
-        let result = E::BINARY(
-            Box::new(E::LITERAL(10)),
+        let result = binary(
+            Box::new(E::LITERAL(10, Span::default())),
             OP::PLUS,
-            Box::new(E::BINARY(
-                Box::new(E::BINARY(
-                    Box::new(E::LITERAL(10)),
+            Box::new(binary(
+                Box::new(binary(
+                    Box::new(E::LITERAL(10, Span::default())),
                     OP::MULT,
-                    Box::new(E::LITERAL(10)),
+                    Box::new(E::LITERAL(10, Span::default())),
                 )),
                 OP::MULT,
-                Box::new(E::LITERAL(10)),
+                Box::new(E::LITERAL(10, Span::default())),
             )),
         );
         test(tokens, result);
@@ -284,26 +517,26 @@ mod tests {
     #[test]
     fn plus_then_mult_then_plus() {
         let tokens = vec![
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::PLUS),
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::MULT),
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::PLUS),
-            Token::LITERAL(10),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::MULT),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(10),
         ];
-        let result = E::BINARY(
-            Box::new(E::BINARY(
-                Box::new(E::LITERAL(10)),
+        let result = binary(
+            Box::new(binary(
+                Box::new(E::LITERAL(10, Span::default())),
                 OP::PLUS,
-                Box::new(E::BINARY(
-                    Box::new(E::LITERAL(10)),
+                Box::new(binary(
+                    Box::new(E::LITERAL(10, Span::default())),
                     OP::MULT,
-                    Box::new(E::LITERAL(10)),
+                    Box::new(E::LITERAL(10, Span::default())),
                 )),
             )),
             OP::PLUS,
-            Box::new(E::LITERAL(10)),
+            Box::new(E::LITERAL(10, Span::default())),
         );
         test(tokens, result);
     }
@@ -311,60 +544,295 @@ mod tests {
     #[test]
     fn mult_then_plus_then_mult() {
         let tokens = vec![
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::MULT),
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::PLUS),
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::MULT),
-            Token::LITERAL(10),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::MULT),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::MULT),
+            TokenKind::LITERAL(10),
         ];
 
-        let lhs = Box::new(E::BINARY(
-            Box::new(E::LITERAL(10)),
+        let lhs = Box::new(binary(
+            Box::new(E::LITERAL(10, Span::default())),
             OP::MULT,
-            Box::new(E::LITERAL(10)),
+            Box::new(E::LITERAL(10, Span::default())),
         ));
         let rhs = lhs.clone();
 
-        let result = E::BINARY(lhs, OP::PLUS, rhs);
+        let result = binary(lhs, OP::PLUS, rhs);
         test(tokens, result);
     }
 
     #[test]
     fn plus_mult_pow_plus() {
         let tokens = vec![
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::PLUS),
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::MULT),
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::POW),
-            Token::LITERAL(10),
-            Token::OPERATOR(OP::PLUS),
-            Token::LITERAL(99),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::MULT),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::POW),
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(99),
         ];
 
-        let pow = Box::new(E::BINARY(
-            Box::new(E::LITERAL(10)),
+        let pow = Box::new(binary(
+            Box::new(E::LITERAL(10, Span::default())),
             OP::POW,
-            Box::new(E::LITERAL(10)),
+            Box::new(E::LITERAL(10, Span::default())),
         ));
 
-        let mult = Box::new(E::BINARY(
-            Box::new(E::LITERAL(10)),
+        let mult = Box::new(binary(
+            Box::new(E::LITERAL(10, Span::default())),
             OP::MULT,
             pow,
         ));
 
-        let first_plus = Box::new(E::BINARY(
-            Box::new(E::LITERAL(10)),
+        let first_plus = Box::new(binary(
+            Box::new(E::LITERAL(10, Span::default())),
             OP::PLUS,
             mult,
         ));
 
-        let last_plus = E::BINARY(first_plus, OP::PLUS, Box::new(E::LITERAL(99)));
+        let last_plus = binary(
+            first_plus,
+            OP::PLUS,
+            Box::new(E::LITERAL(99, Span::default())),
+        );
 
         test(tokens, last_plus);
     }
+
+    #[test]
+    fn minus_left_assoc() {
+        let tokens = vec![
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::MINUS),
+            TokenKind::LITERAL(2),
+            TokenKind::OPERATOR(OP::MINUS),
+            TokenKind::LITERAL(3),
+        ];
+        let result = binary(
+            Box::new(binary(
+                Box::new(E::LITERAL(10, Span::default())),
+                OP::MINUS,
+                Box::new(E::LITERAL(2, Span::default())),
+            )),
+            OP::MINUS,
+            Box::new(E::LITERAL(3, Span::default())),
+        );
+        test(tokens, result);
+    }
+
+    #[test]
+    fn div_left_assoc() {
+        let tokens = vec![
+            TokenKind::LITERAL(100),
+            TokenKind::OPERATOR(OP::DIV),
+            TokenKind::LITERAL(5),
+            TokenKind::OPERATOR(OP::DIV),
+            TokenKind::LITERAL(2),
+        ];
+        let result = binary(
+            Box::new(binary(
+                Box::new(E::LITERAL(100, Span::default())),
+                OP::DIV,
+                Box::new(E::LITERAL(5, Span::default())),
+            )),
+            OP::DIV,
+            Box::new(E::LITERAL(2, Span::default())),
+        );
+        test(tokens, result);
+    }
+
+    #[test]
+    fn minus_then_div() {
+        let tokens = vec![
+            TokenKind::LITERAL(8),
+            TokenKind::OPERATOR(OP::MINUS),
+            TokenKind::LITERAL(6),
+            TokenKind::OPERATOR(OP::DIV),
+            TokenKind::LITERAL(3),
+        ];
+        let result = binary(
+            Box::new(E::LITERAL(8, Span::default())),
+            OP::MINUS,
+            Box::new(binary(
+                Box::new(E::LITERAL(6, Span::default())),
+                OP::DIV,
+                Box::new(E::LITERAL(3, Span::default())),
+            )),
+        );
+        test(tokens, result);
+    }
+
+    #[test]
+    fn div_then_minus() {
+        let tokens = vec![
+            TokenKind::LITERAL(6),
+            TokenKind::OPERATOR(OP::DIV),
+            TokenKind::LITERAL(3),
+            TokenKind::OPERATOR(OP::MINUS),
+            TokenKind::LITERAL(1),
+        ];
+        let result = binary(
+            Box::new(binary(
+                Box::new(E::LITERAL(6, Span::default())),
+                OP::DIV,
+                Box::new(E::LITERAL(3, Span::default())),
+            )),
+            OP::MINUS,
+            Box::new(E::LITERAL(1, Span::default())),
+        );
+        test(tokens, result);
+    }
+
+    fn parse_source(source: &str) -> Result<E, Box<ParseError>> {
+        let tokens = Tokenizer::new(source).run().unwrap();
+        Parser::new(&tokens).run()
+    }
+
+    fn sexp(source: &str) -> String {
+        format!("{:?}", parse_source(source).unwrap())
+    }
+
+    /// Both parsers must give the same tree, spans included, or the same error
+    fn same_as_pratt(source: &str) {
+        let tokens = Tokenizer::new(source).run().unwrap();
+        assert_eq!(
+            pratt::parse(&tokens),
+            Parser::new(&tokens).run(),
+            "{}",
+            source
+        );
+    }
+
+    #[test]
+    fn pow_right_assoc() {
+        assert_eq!("(POW 2 (POW 3 2))", sexp("2 ^ 3 ^ 2"));
+    }
+
+    #[test]
+    fn unary_binds_tighter_than_pow() {
+        assert_eq!("(POW (MINUS 2) 2)", sexp("-2 ^ 2"));
+        assert_eq!("(POW 2 (MINUS (PLUS 2)))", sexp("2 ^ -+2"));
+    }
+
+    #[test]
+    fn comparison_below_arithmetic() {
+        assert_eq!("(LESS (PLUS 1 2) (MULT 3 4))", sexp("1 + 2 < 3 * 4"));
+    }
+
+    #[test]
+    fn if_else_extends_right() {
+        assert_eq!("(if true 1 (PLUS 2 1))", sexp("if true | 1 else 2 + 1"));
+        assert_eq!(
+            "(if (LESS n 2) 1 (MULT n (call f (MINUS n 1))))",
+            sexp("if n < 2 | 1 else n * f(n - 1)")
+        );
+        assert_eq!(
+            "(if (GREATER 1 2) (PLUS 1 1) (MINUS 2))",
+            sexp("if 1 > 2 | 1 + 1 else -2")
+        );
+    }
+
+    #[test]
+    fn call_binds_tightest() {
+        assert_eq!("(PLUS 1 (call f 2))", sexp("1 + f(2)"));
+        assert_eq!("(MINUS (call (call f) 1 2))", sexp("-f()(1, 2)"));
+    }
+
+    #[test]
+    fn let_body_extends_right() {
+        assert_eq!("(let x 1 (PLUS x 1))", sexp("let x = 1 in x + 1"));
+        assert_eq!(
+            "(MULT 2 (let x 1 (POW x 2)))",
+            sexp("2 * let x = 1 in x ^ 2")
+        );
+    }
+
+    #[test]
+    fn same_trees_as_pratt() {
+        for source in [
+            "1",
+            "true",
+            "-(1 + 2) * 3",
+            "10 - 2 - 3 / 4 ^ 2",
+            "1 == 2",
+            "(1 < 2) == false",
+            "if 1 <= 2 | if false | 1 else 2 else 3 ^ 2",
+            "2 ^ if true | 1 else 2 * 3",
+            "((1))\n+ -(-2)",
+            "let x = 1 + 2 in x * x",
+            "2 * let x = 1 in x ^ 2 - 1",
+            "let x = let y = 1 in y in if x > 0 | x else -x",
+            "fn x, y => x + y * 2",
+            "fn => 1",
+            "f() + g(1)(2, 3 < 4)",
+            "-f(1) ^ (fn x => x)(2)",
+            "if f(1) | fn => 1 else f(2)",
+            "let rec f = fn n => if n == 0 | 1 else f(n - 1) in f(3)",
+            "1 + fn x => x + 1",
+            "f(fn x => x, let y = 1 in y)",
+        ] {
+            same_as_pratt(source);
+        }
+    }
+
+    #[test]
+    fn same_errors_as_pratt() {
+        for source in [
+            "",
+            "1 +",
+            "1 2",
+            "(1 + 2",
+            "(1 2)",
+            "1)",
+            "* 2",
+            "if 1 else 2",
+            "if true | 1",
+            "if true | 1 2",
+            "if (1 | 2 else 3",
+            "1 < 2 < 3",
+            "1 + 2 == 3 > 4",
+            "let 1 = 2 in 3",
+            "let x 1 in x",
+            "let x = 1",
+            "let x = 1 2 in x",
+            "let x = 1 in",
+            "let = 1 in 2",
+            "1 = 1",
+            "fn 1 => 1",
+            "fn x 1",
+            "fn x, => 1",
+            "fn x,",
+            "fn x =>",
+            "let rec rec = 1 in 1",
+            "let rec 1",
+            "f(1 2)",
+            "f(1,)",
+            "f(",
+            "f(1",
+            "(f(1, 2)",
+            "f(1 < 2 < 3)",
+        ] {
+            same_as_pratt(source);
+        }
+    }
+
+    #[test]
+    fn chained_comparison() {
+        let e = parse_source("1 < 2 == 3").unwrap_err();
+        assert_eq!(ParseErrorKind::ChainedComparison, e.kind);
+        assert_eq!(Span::new(6, 8, 1, 7), e.span);
+        assert_eq!(Some((Span::new(0, 5, 1, 1), "first comparison")), e.related);
+    }
+
+    #[test]
+    fn end_of_input_span() {
+        let e = parse_source("(1 +").unwrap_err();
+        assert_eq!(Span::new(4, 4, 1, 5), e.span);
+    }
 }
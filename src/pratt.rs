@@ -1,229 +1,1489 @@
 use core::fmt;
 use std::{iter::Peekable, slice::Iter};
 
-use crate::tokenizer::{Token, OP};
+use crate::{
+    diagnostic::Diagnostic,
+    span::Span,
+    tokenizer::{Token, TokenKind, OP},
+};
 
+/// Expressions, each variant carries the span of source it was parsed from
 #[derive(Clone, PartialEq, Eq)]
 pub enum E {
-    LITERAL(u32),
-    UNARY(OP, Box<E>),
-    BINARY(Box<E>, OP, Box<E>),
-    PAREN(Box<E>),
-    IF(Box<E>, Box<E>, Box<E>)
+    LITERAL(u32, Span),
+    BOOL(bool, Span),
+    UNARY(OP, Box<E>, Span),
+    BINARY(Box<E>, OP, Box<E>, Span),
+    PAREN(Box<E>, Span),
+    IF(Box<E>, Box<E>, Box<E>, Span),
+    /// `let name = value in body`
+    LET(String, Box<E>, Box<E>, Span),
+    /// `let rec name = value in body`, `name` is bound in `value` as well
+    LETREC(String, Box<E>, Box<E>, Span),
+    VAR(String, Span),
+    /// `fn x, y => body`
+    FN(Vec<String>, Box<E>, Span),
+    /// `callee(arguments)`
+    CALL(Box<E>, Vec<E>, Span),
+    /// `{ statements }`, see `S` for its value
+    BLOCK(Vec<S>, Span),
 }
 
+/// Statements, separated by `;` in programs and blocks. The value of a
+/// sequence of statements is the value of the last one if it is an
+/// expression, and `()` otherwise.
 #[derive(Clone, PartialEq, Eq)]
 pub enum S {
+    EXPR(E),
+    /// `let name = value`, in scope for the rest of the sequence
+    LET(String, E, Span),
+    /// `let rec name = value`
+    LETREC(String, E, Span),
+    /// `print value`
+    PRINT(E, Span),
+    /// `var name = value`, a variable that can be assigned to
+    VAR(String, E, Span),
+    /// `name = value`
+    ASSIGN(String, E, Span),
+    /// `while condition { body }`
+    WHILE(E, Vec<S>, Span),
+    BREAK(Span),
+    CONTINUE(Span),
+}
+
+impl E {
+    pub fn span(&self) -> Span {
+        match self {
+            E::LITERAL(_, span)
+            | E::BOOL(_, span)
+            | E::UNARY(_, _, span)
+            | E::BINARY(_, _, _, span)
+            | E::PAREN(_, span)
+            | E::IF(_, _, _, span)
+            | E::LET(_, _, _, span)
+            | E::LETREC(_, _, _, span)
+            | E::VAR(_, span)
+            | E::FN(_, _, span)
+            | E::CALL(_, _, span)
+            | E::BLOCK(_, span) => *span,
+        }
+    }
+}
+
+impl S {
+    pub fn span(&self) -> Span {
+        match self {
+            S::EXPR(e) => e.span(),
+            S::LET(_, _, span)
+            | S::LETREC(_, _, span)
+            | S::PRINT(_, span)
+            | S::VAR(_, _, span)
+            | S::ASSIGN(_, _, span)
+            | S::WHILE(_, _, span)
+            | S::BREAK(span)
+            | S::CONTINUE(span) => *span,
+        }
+    }
 }
 
 impl fmt::Debug for E {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::LITERAL(v) => write!(f, "{}", v),
-            Self::UNARY(op, v) => write!(f, "({:?} {:?})", op, v),
-            Self::BINARY(v1, op, v2) => write!(f, "({:?} {:?} {:?})", op, v1, v2),
-            Self::PAREN(v) => write!(f, "({:?})", v),
-            Self::IF(cond, then, elze) => write!(f, "(if {:?} {:?} {:?}", cond, then, elze),
+            Self::LITERAL(v, _) => write!(f, "{}", v),
+            Self::BOOL(b, _) => write!(f, "{}", b),
+            Self::UNARY(op, v, _) => write!(f, "({:?} {:?})", op, v),
+            Self::BINARY(v1, op, v2, _) => write!(f, "({:?} {:?} {:?})", op, v1, v2),
+            Self::PAREN(v, _) => write!(f, "({:?})", v),
+            Self::IF(cond, then, elze, _) => write!(f, "(if {:?} {:?} {:?})", cond, then, elze),
+            Self::LET(name, value, body, _) => write!(f, "(let {} {:?} {:?})", name, value, body),
+            Self::LETREC(name, value, body, _) => {
+                write!(f, "(let rec {} {:?} {:?})", name, value, body)
+            }
+            Self::VAR(name, _) => write!(f, "{}", name),
+            Self::FN(params, body, _) => write!(f, "(fn ({}) {:?})", params.join(" "), body),
+            Self::CALL(callee, args, _) => {
+                write!(f, "(call {:?}", callee)?;
+                for arg in args {
+                    write!(f, " {:?}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Self::BLOCK(statements, _) => {
+                write!(f, "(block")?;
+                for statement in statements {
+                    write!(f, " {:?}", statement)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl fmt::Debug for S {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EXPR(e) => write!(f, "{:?}", e),
+            Self::LET(name, value, _) => write!(f, "(let {} {:?})", name, value),
+            Self::LETREC(name, value, _) => write!(f, "(let rec {} {:?})", name, value),
+            Self::PRINT(e, _) => write!(f, "(print {:?})", e),
+            Self::VAR(name, value, _) => write!(f, "(var {} {:?})", name, value),
+            Self::ASSIGN(name, value, _) => write!(f, "(set {} {:?})", name, value),
+            Self::WHILE(cond, body, _) => {
+                write!(f, "(while {:?}", cond)?;
+                for statement in body {
+                    write!(f, " {:?}", statement)?;
+                }
+                write!(f, ")")
+            }
+            Self::BREAK(_) => write!(f, "(break)"),
+            Self::CONTINUE(_) => write!(f, "(continue)"),
         }
     }
 }
 
-pub fn expression(tokens: &mut Peekable<Iter<Token>>, prev_bp: u8) -> E {
-    let mut lhs = nud(tokens);
-    dbg!(prev_bp);
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Expected {
+    Token(TokenKind),
+    Expression,
+    Operator,
+    Identifier,
+    EndOfInput,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ParseErrorKind {
+    /// `found` is not one of the `expected` tokens
+    Unexpected,
+    /// `a < b < c`, `found` is the second comparison operator
+    ChainedComparison,
+    /// `found` starts an expression inside `MAX_DEPTH` others
+    TooDeep,
+}
+
+/// Parse errors are large next to what the parsers return, so they are
+/// built and passed around boxed
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub expected: Vec<Expected>,
+    /// `None` at the end of input
+    pub found: Option<TokenKind>,
+    pub span: Span,
+    /// Where the expectation comes from, e.g. "after if-condition"
+    pub context: Option<&'static str>,
+    /// A related location, e.g. the `(` that was never closed
+    pub related: Option<(Span, &'static str)>,
+    pub help: Option<&'static str>,
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Token(kind) => write!(f, "`{}`", kind),
+            Expected::Expression => write!(f, "an expression"),
+            Expected::Operator => write!(f, "an operator"),
+            Expected::Identifier => write!(f, "an identifier"),
+            Expected::EndOfInput => write!(f, "end of input"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ParseErrorKind::ChainedComparison => {
+                return write!(f, "comparison operators cannot be chained")
+            }
+            ParseErrorKind::TooDeep => return write!(f, "expression nested too deeply"),
+            ParseErrorKind::Unexpected => (),
+        }
+        write!(f, "expected ")?;
+        for (i, expected) in self.expected.iter().enumerate() {
+            match i {
+                0 => (),
+                _ if i == self.expected.len() - 1 => write!(f, " or ")?,
+                _ => write!(f, ", ")?,
+            }
+            write!(f, "{}", expected)?;
+        }
+        if let Some(context) = self.context {
+            write!(f, " {}", context)?;
+        }
+        match &self.found {
+            Some(kind) => write!(f, ", found `{}`", kind),
+            None => write!(f, ", found end of input"),
+        }
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(e: &ParseError) -> Diagnostic {
+        let expected: Vec<String> = e.expected.iter().map(|e| e.to_string()).collect();
+        let label = match e.kind {
+            ParseErrorKind::Unexpected => format!("expected {}", expected.join(" or ")),
+            ParseErrorKind::ChainedComparison => "second comparison".to_string(),
+            ParseErrorKind::TooDeep => format!("more than {} levels deep", MAX_DEPTH),
+        };
+        let mut diagnostic = Diagnostic::error(e.to_string()).primary(e.span, label);
+        if let Some((span, label)) = e.related {
+            diagnostic = diagnostic.secondary(span, label);
+        }
+        if let Some(help) = e.help {
+            diagnostic = diagnostic.help(help);
+        }
+        if e.found == Some(TokenKind::ASSIGN) && e.expected.contains(&Expected::Operator) {
+            diagnostic = diagnostic.help("use `==` to compare two values");
+        }
+        diagnostic
+    }
+}
+
+impl ParseError {
+    pub fn boxed(expected: Vec<Expected>, found: Option<&Token>) -> Box<ParseError> {
+        Box::new(ParseError {
+            kind: ParseErrorKind::Unexpected,
+            expected,
+            found: found.map(|t| t.kind.clone()),
+            span: found.map_or(Span::default(), |t| t.span),
+            context: None,
+            related: None,
+            help: None,
+        })
+    }
+
+    /// `token` is the second comparison in `a < b < c`, `first` spans `a < b`
+    pub fn chained_comparison(token: &Token, first: Span) -> Box<ParseError> {
+        let mut e = ParseError::boxed(vec![], Some(token));
+        e.kind = ParseErrorKind::ChainedComparison;
+        e.related(first, "first comparison")
+            .help("use parentheses to group the comparisons")
+    }
+
+    /// `found` starts an expression nested deeper than `MAX_DEPTH`
+    pub fn too_deep(found: Option<&Token>) -> Box<ParseError> {
+        let mut e = ParseError::boxed(vec![], found);
+        e.kind = ParseErrorKind::TooDeep;
+        e.help("bind parts of it with `let`")
+    }
+
+    pub fn context(mut self: Box<Self>, context: &'static str) -> Box<ParseError> {
+        self.context = Some(context);
+        self
+    }
+
+    pub fn related(mut self: Box<Self>, span: Span, label: &'static str) -> Box<ParseError> {
+        self.related = Some((span, label));
+        self
+    }
+
+    pub fn help(mut self: Box<Self>, help: &'static str) -> Box<ParseError> {
+        self.help = Some(help);
+        self
+    }
+}
+
+pub const IF_HELP: &str = "if-expressions are written `if <condition> | <then> else <else>`";
+pub const LET_HELP: &str = "let-expressions are written `let <name> = <value> in <body>`";
+pub const FN_HELP: &str = "functions are written `fn <parameters> => <body>`";
+pub const VAR_HELP: &str = "variables are declared `var <name> = <value>`";
+pub const WHILE_HELP: &str = "loops are written `while <condition> { <statements> }`";
+
+/// Expressions nested deeper than this are an error instead of overflowing
+/// the stack of the parser, or of the passes after it
+pub const MAX_DEPTH: usize = 128;
+/// Binding power of prefix operators
+const PREFIX_BP: u8 = 80;
+/// Left binding power of the `(` of a call, above everything else so that
+/// `-f(x)` negates the result of the call
+const CALL_BP: u8 = 90;
+
+/// Parses `tokens` as a single expression, all tokens must be consumed.
+/// Programs go through `program`, this is kept to compare with the LR and
+/// LALR parsers, which parse single expressions only.
+#[cfg(test)]
+pub fn parse(tokens: &[Token]) -> Result<E, Box<ParseError>> {
+    let mut iter = tokens.iter().peekable();
+    let result = expression(&mut iter, 0, 0).and_then(|e| match iter.next() {
+        None => Ok(e),
+        token => Err(ParseError::boxed(
+            vec![Expected::Operator, Expected::EndOfInput],
+            token,
+        )),
+    });
+    result.map_err(|e| locate(e, tokens))
+}
+
+/// Parses `tokens` as a program, a sequence of statements
+pub fn program(tokens: &[Token]) -> Result<Vec<S>, Box<ParseError>> {
+    statements(&mut tokens.iter().peekable(), None, 0).map_err(|e| locate(e, tokens))
+}
+
+/// The token iterator cannot tell where the input ended, so errors at the
+/// end get their location here
+fn locate(mut e: Box<ParseError>, tokens: &[Token]) -> Box<ParseError> {
+    if e.found.is_none() {
+        e.span = end_of_input(tokens);
+    }
+    e
+}
+
+pub fn end_of_input(tokens: &[Token]) -> Span {
+    match tokens.last() {
+        Some(t) => {
+            // columns count characters, only identifiers can have ones
+            // that take more than a byte
+            let width = match &t.kind {
+                TokenKind::IDENT(name) => name.chars().count() as u32,
+                _ => t.span.end - t.span.start,
+            };
+            Span::new(t.span.end, t.span.end, t.span.line, t.span.col + width)
+        }
+        None => Span::new(0, 0, 1, 1),
+    }
+}
+
+/// An expression inside `depth` others
+pub fn expression(
+    tokens: &mut Peekable<Iter<Token>>,
+    min_bp: u8,
+    depth: usize,
+) -> Result<E, Box<ParseError>> {
+    if depth == MAX_DEPTH {
+        return Err(ParseError::too_deep(tokens.peek().copied()));
+    }
+    let mut lhs = nud(tokens, depth + 1)?;
 
     loop {
         let next = tokens.peek();
 
-        let eof = next.is_none();
-        if eof {
+        // anything that is not an infix operator ends the expression,
+        // the caller decides whether it is allowed there
+        let (l_bp, _) = match next.and_then(|t| bp(&t.kind)) {
+            Some(bp) => bp,
+            None => break,
+        };
+        if l_bp < min_bp {
             break;
         }
 
-        let curr_bp = bp(next.unwrap());
-        if curr_bp < prev_bp {
-            break;
+        // a non-associative operator cannot follow another one of the same
+        // precedence, `a < b < c` is rejected
+        let token = next.unwrap();
+        if let (E::BINARY(_, prev, _, _), TokenKind::OPERATOR(op)) = (&lhs, &token.kind) {
+            if let ((power, Assoc::Non), (next_power, _)) = (infix(prev), infix(op)) {
+                if power == next_power {
+                    return Err(ParseError::chained_comparison(token, lhs.span()));
+                }
+            }
         }
 
-        lhs = led(lhs, tokens);
+        lhs = led(lhs, tokens, depth + 1)?;
     }
 
-    lhs
+    Ok(lhs)
+}
+
+/// Consumes the next token if it is `kind`, otherwise reports what was found
+fn expect<'a>(
+    tokens: &mut Peekable<Iter<'a, Token>>,
+    kind: TokenKind,
+) -> Result<&'a Token, Box<ParseError>> {
+    match tokens.next_if(|t| t.kind == kind) {
+        Some(token) => Ok(token),
+        None => Err(ParseError::boxed(
+            vec![Expected::Token(kind)],
+            tokens.peek().copied(),
+        )),
+    }
+}
+
+/// Consumes the next token if it is an identifier and returns its name
+fn identifier(tokens: &mut Peekable<Iter<Token>>) -> Result<String, Box<ParseError>> {
+    match tokens.next_if(|t| matches!(t.kind, TokenKind::IDENT(_))) {
+        Some(Token {
+            kind: TokenKind::IDENT(name),
+            ..
+        }) => Ok(name.clone()),
+        _ => Err(ParseError::boxed(
+            vec![Expected::Identifier],
+            tokens.peek().copied(),
+        )),
+    }
 }
 
-fn nud(tokens: &mut Peekable<Iter<Token>>) -> E {
-    match tokens.next().unwrap() {
-        Token::LITERAL(v) => E::LITERAL(*v),
-        Token::OPERATOR(op) => {
-            let bp = 80; // TODO maybe make unary precedence more explicit?
-            E::UNARY(op.clone(), Box::new(expression(tokens, bp)))
+/// Statements separated by `;`, up to the `}` matching `open` or up to the
+/// end of input without one. A `;` after the last statement is allowed.
+fn statements(
+    tokens: &mut Peekable<Iter<Token>>,
+    open: Option<&Token>,
+    depth: usize,
+) -> Result<Vec<S>, Box<ParseError>> {
+    let end = |next: Option<&&Token>| match open {
+        Some(_) => next.is_some_and(|t| t.kind == TokenKind::RBRACE),
+        None => next.is_none(),
+    };
+    let mut statements = vec![];
+    while !end(tokens.peek()) {
+        if let (Some(open), None) = (open, tokens.peek()) {
+            let expected = vec![Expected::Expression, Expected::Token(TokenKind::RBRACE)];
+            return Err(ParseError::boxed(expected, None).related(open.span, "unclosed `{`"));
         }
-        Token::LPAREN => {
-            let e = E::PAREN(Box::new(expression(tokens, 1)));
+        statements.push(statement(tokens, depth)?);
+        if tokens.next_if(|t| t.kind == TokenKind::SEMICOLON).is_none() && !end(tokens.peek()) {
+            let found = tokens.peek().copied();
+            let mut expected = vec![Expected::Operator, Expected::Token(TokenKind::SEMICOLON)];
+            return Err(match open {
+                Some(open) => {
+                    expected.push(Expected::Token(TokenKind::RBRACE));
+                    ParseError::boxed(expected, found).related(open.span, "unclosed `{`")
+                }
+                None => {
+                    expected.push(Expected::EndOfInput);
+                    ParseError::boxed(expected, found)
+                }
+            });
+        }
+    }
+    Ok(statements)
+}
+
+fn statement(tokens: &mut Peekable<Iter<Token>>, depth: usize) -> Result<S, Box<ParseError>> {
+    let token = match tokens.peek() {
+        Some(&token) => token,
+        None => return Ok(S::EXPR(expression(tokens, 0, depth)?)),
+    };
+    match &token.kind {
+        TokenKind::PRINT => {
+            tokens.next();
+            let e = expression(tokens, 0, depth)?;
+            let span = token.span.to(e.span());
+            Ok(S::PRINT(e, span))
+        }
+        TokenKind::LET => {
+            tokens.next();
+            declaration(token, tokens, depth)
+        }
+        TokenKind::VAR => {
             tokens.next();
-            e
+            let name = identifier(tokens).map_err(|e| e.context("after `var`").help(VAR_HELP))?;
+            expect(tokens, TokenKind::ASSIGN).map_err(|e| {
+                e.context("after variable name")
+                    .related(token.span, "`var` starts here")
+                    .help(VAR_HELP)
+            })?;
+            let value = expression(tokens, 0, depth)?;
+            let span = token.span.to(value.span());
+            Ok(S::VAR(name, value, span))
         }
-        Token::IF => {
-            let cond = Box::new(expression(tokens, 1));
-            assert!(*tokens.next().unwrap() == Token::THEN);
-            let then = Box::new(expression(tokens, 1));
-            assert!(*tokens.next().unwrap() == Token::ELSE);
-            let elze = Box::new(expression(tokens, 1));
-            E::IF(cond, then, elze)
+        // an identifier followed by `=` is an assignment, anything else
+        // starting with one is an expression
+        TokenKind::IDENT(name)
+            if tokens
+                .clone()
+                .nth(1)
+                .is_some_and(|t| t.kind == TokenKind::ASSIGN) =>
+        {
+            tokens.nth(1);
+            let value = expression(tokens, 0, depth)?;
+            let span = token.span.to(value.span());
+            Ok(S::ASSIGN(name.clone(), value, span))
+        }
+        TokenKind::WHILE => {
+            tokens.next();
+            let cond = expression(tokens, 0, depth)?;
+            let open = expect(tokens, TokenKind::LBRACE).map_err(|e| {
+                e.context("after while-condition")
+                    .related(token.span, "`while` starts here")
+                    .help(WHILE_HELP)
+            })?;
+            let body = statements(tokens, Some(open), depth)?;
+            // `statements` only returns before the closing `}`
+            let end = tokens.next().unwrap().span;
+            Ok(S::WHILE(cond, body, token.span.to(end)))
         }
-        _ => panic!(),
+        TokenKind::BREAK => {
+            tokens.next();
+            Ok(S::BREAK(token.span))
+        }
+        TokenKind::CONTINUE => {
+            tokens.next();
+            Ok(S::CONTINUE(token.span))
+        }
+        _ => Ok(S::EXPR(expression(tokens, 0, depth)?)),
+    }
+}
+
+/// A statement starting with the `let` in `token`, with `in` it is a
+/// let-expression, without it a declaration
+fn declaration(
+    token: &Token,
+    tokens: &mut Peekable<Iter<Token>>,
+    depth: usize,
+) -> Result<S, Box<ParseError>> {
+    let (rec, name, value) = binding(token, tokens, depth)?;
+    if tokens.next_if(|t| t.kind == TokenKind::IN).is_some() {
+        return Ok(S::EXPR(let_in(token, rec, name, value, tokens, depth)?));
+    }
+    let span = token.span.to(value.span());
+    if rec {
+        Ok(S::LETREC(name, value, span))
+    } else {
+        Ok(S::LET(name, value, span))
+    }
+}
+
+/// `[rec] name = value` after the `let` in `token`
+fn binding(
+    token: &Token,
+    tokens: &mut Peekable<Iter<Token>>,
+    depth: usize,
+) -> Result<(bool, String, E), Box<ParseError>> {
+    let rec = tokens.next_if(|t| t.kind == TokenKind::REC).is_some();
+    let name = identifier(tokens).map_err(|e| e.context("after `let`").help(LET_HELP))?;
+    expect(tokens, TokenKind::ASSIGN).map_err(|e| {
+        e.context("after let-bound name")
+            .related(token.span, "`let` starts here")
+            .help(LET_HELP)
+    })?;
+    let value = expression(tokens, 0, depth)?;
+    Ok((rec, name, value))
+}
+
+/// The body of a let-expression, after its `in`
+fn let_in(
+    token: &Token,
+    rec: bool,
+    name: String,
+    value: E,
+    tokens: &mut Peekable<Iter<Token>>,
+    depth: usize,
+) -> Result<E, Box<ParseError>> {
+    // the body extends as far as possible, `let x = 1 in x + 1` adds
+    let body = Box::new(expression(tokens, 0, depth)?);
+    let span = token.span.to(body.span());
+    if rec {
+        Ok(E::LETREC(name, Box::new(value), body, span))
+    } else {
+        Ok(E::LET(name, Box::new(value), body, span))
+    }
+}
+
+fn nud(tokens: &mut Peekable<Iter<Token>>, depth: usize) -> Result<E, Box<ParseError>> {
+    let token = match tokens.next() {
+        Some(token) => token,
+        None => return Err(ParseError::boxed(vec![Expected::Expression], None)),
+    };
+    match &token.kind {
+        TokenKind::LITERAL(v) => Ok(E::LITERAL(*v, token.span)),
+        TokenKind::BOOL(b) => Ok(E::BOOL(*b, token.span)),
+        TokenKind::OPERATOR(op @ (OP::PLUS | OP::MINUS)) => {
+            let e = expression(tokens, PREFIX_BP, depth)?;
+            let span = token.span.to(e.span());
+            Ok(E::UNARY(op.clone(), Box::new(e), span))
+        }
+        TokenKind::LPAREN => {
+            let e = expression(tokens, 0, depth)?;
+            let end = match tokens.peek() {
+                Some(t) if t.kind == TokenKind::RPAREN => t.span,
+                found => {
+                    let expected = vec![Expected::Operator, Expected::Token(TokenKind::RPAREN)];
+                    return Err(ParseError::boxed(expected, found.copied())
+                        .related(token.span, "unclosed `(`"));
+                }
+            };
+            tokens.next();
+            Ok(E::PAREN(Box::new(e), token.span.to(end)))
+        }
+        TokenKind::IF => {
+            let cond = Box::new(expression(tokens, 0, depth)?);
+            expect(tokens, TokenKind::THEN).map_err(|e| {
+                e.context("after if-condition")
+                    .related(token.span, "`if` starts here")
+                    .help(IF_HELP)
+            })?;
+            let then = Box::new(expression(tokens, 0, depth)?);
+            expect(tokens, TokenKind::ELSE).map_err(|e| {
+                e.context("after then-branch")
+                    .related(token.span, "this `if` has no `else`")
+                    .help(IF_HELP)
+            })?;
+            // like the body of a let, the else branch extends as far as possible,
+            // so `if c | 1 else 2 + 1` is `if c | 1 else (2 + 1)`
+            let elze = Box::new(expression(tokens, 0, depth)?);
+            let span = token.span.to(elze.span());
+            Ok(E::IF(cond, then, elze, span))
+        }
+        TokenKind::LET => {
+            let (rec, name, value) = binding(token, tokens, depth)?;
+            expect(tokens, TokenKind::IN).map_err(|e| {
+                e.context("after let-bound value")
+                    .related(token.span, "`let` starts here")
+                    .help(LET_HELP)
+            })?;
+            let_in(token, rec, name, value, tokens, depth)
+        }
+        TokenKind::LBRACE => {
+            let statements = statements(tokens, Some(token), depth)?;
+            // `statements` only returns before the closing `}`
+            let end = tokens.next().unwrap().span;
+            Ok(E::BLOCK(statements, token.span.to(end)))
+        }
+        TokenKind::IDENT(name) => Ok(E::VAR(name.clone(), token.span)),
+        TokenKind::FN => {
+            let mut params = vec![];
+            // `fn => 1` takes no parameters, otherwise they are separated by commas
+            if tokens.next_if(|t| t.kind == TokenKind::ARROW).is_none() {
+                loop {
+                    let name = identifier(tokens).map_err(|mut e| {
+                        if params.is_empty() {
+                            e.expected.push(Expected::Token(TokenKind::ARROW));
+                            e.context("after `fn`").help(FN_HELP)
+                        } else {
+                            e.context("after `,`").help(FN_HELP)
+                        }
+                    })?;
+                    params.push(name);
+                    match tokens.next_if(|t| matches!(t.kind, TokenKind::COMMA | TokenKind::ARROW))
+                    {
+                        Some(t) if t.kind == TokenKind::ARROW => break,
+                        Some(_) => (),
+                        None => {
+                            let expected = vec![
+                                Expected::Token(TokenKind::COMMA),
+                                Expected::Token(TokenKind::ARROW),
+                            ];
+                            return Err(ParseError::boxed(expected, tokens.peek().copied())
+                                .context("after parameter")
+                                .help(FN_HELP));
+                        }
+                    }
+                }
+            }
+            // like the body of a let, the body extends as far as possible
+            let body = Box::new(expression(tokens, 0, depth)?);
+            let span = token.span.to(body.span());
+            Ok(E::FN(params, body, span))
+        }
+        _ => Err(ParseError::boxed(vec![Expected::Expression], Some(token))),
     }
 }
 
-fn led(left: E, tokens: &mut Peekable<Iter<Token>>) -> E {
+fn led(left: E, tokens: &mut Peekable<Iter<Token>>, depth: usize) -> Result<E, Box<ParseError>> {
     let token = tokens.next().unwrap();
-    let bp = bp(token);
-    match token {
-        Token::OPERATOR(operator) => E::BINARY(
-            Box::new(left),
-            operator.clone(),
-            Box::new(expression(tokens, bp)),
-        ),
-        _ => todo!(),
+    match (&token.kind, bp(&token.kind)) {
+        (TokenKind::OPERATOR(operator), Some((_, r_bp))) => {
+            let right = expression(tokens, r_bp, depth)?;
+            let span = left.span().to(right.span());
+            Ok(E::BINARY(
+                Box::new(left),
+                operator.clone(),
+                Box::new(right),
+                span,
+            ))
+        }
+        (TokenKind::LPAREN, Some(_)) => {
+            let mut args = vec![];
+            let end = loop {
+                // `f()` takes no arguments
+                if args.is_empty() {
+                    if let Some(t) = tokens.next_if(|t| t.kind == TokenKind::RPAREN) {
+                        break t.span;
+                    }
+                }
+                args.push(expression(tokens, 0, depth)?);
+                match tokens.next_if(|t| matches!(t.kind, TokenKind::COMMA | TokenKind::RPAREN)) {
+                    Some(t) if t.kind == TokenKind::RPAREN => break t.span,
+                    Some(_) => (),
+                    None => {
+                        let expected = vec![
+                            Expected::Operator,
+                            Expected::Token(TokenKind::COMMA),
+                            Expected::Token(TokenKind::RPAREN),
+                        ];
+                        return Err(ParseError::boxed(expected, tokens.peek().copied())
+                            .related(token.span, "unclosed `(`"));
+                    }
+                }
+            };
+            let span = left.span().to(end);
+            Ok(E::CALL(Box::new(left), args, span))
+        }
+        _ => Err(ParseError::boxed(vec![Expected::Operator], Some(token))),
     }
 }
 
-pub fn bp(token: &Token) -> u8 {
-    match token {
-        Token::OPERATOR(op) => match op {
-            OP::PLUS | OP::MINUS => 10,
-            OP::MULT => 20,
-            OP::POW => 30,
-            _ => todo!(),
-        },
-        Token::RPAREN => 0, // TODO should this be "lower" than initial?
-        Token::THEN | Token::ELSE => 0,
-        _ => todo!(),
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Assoc {
+    Left,
+    Right,
+    /// Chaining the operator is an error
+    Non,
+}
+
+/// The infix operators, their precedence is given by `infix`
+pub const OPERATORS: [OP; 10] = [
+    OP::PLUS,
+    OP::MINUS,
+    OP::MULT,
+    OP::DIV,
+    OP::POW,
+    OP::EQUALITY,
+    OP::GREATER,
+    OP::LESS,
+    OP::GEQ,
+    OP::LEQ,
+];
+
+/// Precedence and associativity of the infix operators
+pub fn infix(op: &OP) -> (u8, Assoc) {
+    match op {
+        OP::EQUALITY | OP::GREATER | OP::LESS | OP::GEQ | OP::LEQ => (5, Assoc::Non),
+        OP::PLUS | OP::MINUS => (10, Assoc::Left),
+        OP::MULT | OP::DIV => (20, Assoc::Left),
+        OP::POW => (30, Assoc::Right),
+    }
+}
+
+/// Left and right binding power of infix operators and calls, `None` for
+/// tokens that cannot continue an expression.
+///
+/// An operator keeps parsing its right operand while the next operator's
+/// left power is at least its own right power, so a right power above the
+/// left power groups to the left and vice versa.
+pub fn bp(token: &TokenKind) -> Option<(u8, u8)> {
+    let (power, assoc) = match token {
+        TokenKind::OPERATOR(op) => infix(op),
+        TokenKind::LPAREN => return Some((CALL_BP, 0)),
+        _ => return None,
+    };
+    match assoc {
+        Assoc::Left | Assoc::Non => Some((power, power + 1)),
+        Assoc::Right => Some((power + 1, power)),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    fn test(tokens: Vec<TokenKind>, expected: E) {
+        let tokens: Vec<Token> = tokens.into_iter().map(Token::from).collect();
+        let mut iter = tokens.iter().peekable();
+        let result = expression(&mut iter, 0, 0).unwrap();
+        assert_eq!(expected, result);
+    }
 
     fn literal(i: u32) -> Box<E> {
-        Box::new(E::LITERAL(i))
+        Box::new(E::LITERAL(i, Span::default()))
     }
 
     fn boxx(e: E) -> Box<E> {
         Box::new(e)
     }
 
+    fn binary(left: Box<E>, op: OP, right: Box<E>) -> E {
+        E::BINARY(left, op, right, Span::default())
+    }
+
     #[test]
     fn single_literal() {
-        let tokens = vec![Token::LITERAL(10)];
-        let mut iter = tokens.iter().peekable();
-        let result = expression(&mut iter, 0);
-        assert_eq!(E::LITERAL(10), result);
+        test(vec![TokenKind::LITERAL(10)], *literal(10));
     }
 
     #[test]
     fn single_literal_parens() {
-        let tokens = vec![Token::LPAREN, Token::LITERAL(10), Token::RPAREN];
-        let mut iter = tokens.iter().peekable();
-        let result = expression(&mut iter, 0);
-        assert_eq!(E::PAREN(literal(10)), result);
+        let tokens = vec![TokenKind::LPAREN, TokenKind::LITERAL(10), TokenKind::RPAREN];
+        test(tokens, E::PAREN(literal(10), Span::default()));
     }
 
     #[test]
     fn single_literal_parens_x2() {
         let tokens = vec![
-            Token::LPAREN,
-            Token::LPAREN,
-            Token::LITERAL(10),
-            Token::RPAREN,
-            Token::RPAREN,
+            TokenKind::LPAREN,
+            TokenKind::LPAREN,
+            TokenKind::LITERAL(10),
+            TokenKind::RPAREN,
+            TokenKind::RPAREN,
         ];
-        let mut iter = tokens.iter().peekable();
-        let result = expression(&mut iter, 0);
-        assert_eq!(E::PAREN(Box::new(E::PAREN(literal(10)))), result);
+        let inner = E::PAREN(literal(10), Span::default());
+        test(tokens, E::PAREN(boxx(inner), Span::default()));
     }
 
     #[test]
     fn unary_minus() {
-        let tokens = vec![Token::OPERATOR(OP::MINUS), Token::LITERAL(10)];
-        let mut iter = tokens.iter().peekable();
-        let result = expression(&mut iter, 0);
-        let asd = E::UNARY(OP::MINUS, Box::new(E::LITERAL(10)));
-        assert_eq!(asd, result);
+        let tokens = vec![TokenKind::OPERATOR(OP::MINUS), TokenKind::LITERAL(10)];
+        let asd = E::UNARY(OP::MINUS, literal(10), Span::default());
+        test(tokens, asd);
     }
 
     #[test]
     fn unary_minus_plus() {
         let tokens = vec![
-            Token::OPERATOR(OP::MINUS),
-            Token::LITERAL(1),
-            Token::OPERATOR(OP::PLUS),
-            Token::LITERAL(2),
-            Token::OPERATOR(OP::MULT),
-            Token::LITERAL(3),
+            TokenKind::OPERATOR(OP::MINUS),
+            TokenKind::LITERAL(1),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(2),
+            TokenKind::OPERATOR(OP::MULT),
+            TokenKind::LITERAL(3),
         ];
-        let mut iter = tokens.iter().peekable();
-        let result = expression(&mut iter, 0);
-        let unary = E::UNARY(OP::MINUS, literal(1));
-        let mult = E::BINARY(literal(2), OP::MULT, literal(3));
-        let asd = E::BINARY(boxx(unary), OP::PLUS, boxx(mult));
-        assert_eq!(asd, result);
+        let unary = E::UNARY(OP::MINUS, literal(1), Span::default());
+        let mult = binary(literal(2), OP::MULT, literal(3));
+        let asd = binary(boxx(unary), OP::PLUS, boxx(mult));
+        test(tokens, asd);
     }
 
     #[test]
     fn addition() {
         let tokens = vec![
-            Token::LITERAL(1),
-            Token::OPERATOR(OP::PLUS),
-            Token::LITERAL(2),
+            TokenKind::LITERAL(1),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(2),
         ];
-        let mut iter = tokens.iter().peekable();
-        let result = expression(&mut iter, 0);
-        let lit1 = Box::new(E::LITERAL(1));
-        let lit2 = Box::new(E::LITERAL(2));
-        let bin = E::BINARY(lit1, OP::PLUS, lit2);
-        assert_eq!(bin, result);
+        let bin = binary(literal(1), OP::PLUS, literal(2));
+        test(tokens, bin);
     }
 
     #[test]
     fn plus_mult() {
-        let tok_lit = Token::LITERAL(10);
+        let tok_lit = TokenKind::LITERAL(10);
         let tokens = vec![
             tok_lit.clone(),
-            Token::OPERATOR(OP::PLUS),
+            TokenKind::OPERATOR(OP::PLUS),
             tok_lit.clone(),
-            Token::OPERATOR(OP::MULT),
+            TokenKind::OPERATOR(OP::MULT),
             tok_lit.clone(),
-            Token::OPERATOR(OP::PLUS),
+            TokenKind::OPERATOR(OP::PLUS),
             tok_lit,
         ];
-        let mut iter = tokens.iter().peekable();
-        let result = expression(&mut iter, 0);
-        let lit = Box::new(E::LITERAL(10));
-        let mult = Box::new(E::BINARY(lit.clone(), OP::MULT, lit.clone()));
-        let bin = Box::new(E::BINARY(mult, OP::PLUS, lit.clone()));
-        let bin = E::BINARY(lit, OP::PLUS, bin);
-        assert_eq!(bin, result);
+        let lit = literal(10);
+        let mult = boxx(binary(lit.clone(), OP::MULT, lit.clone()));
+        let bin = boxx(binary(lit.clone(), OP::PLUS, mult));
+        let bin = binary(bin, OP::PLUS, lit);
+        test(tokens, bin);
+    }
+
+    fn sexp(source: &str) -> String {
+        format!("{:?}", parse_source(source).unwrap())
+    }
+
+    #[test]
+    fn plus_left_assoc() {
+        assert_eq!("(PLUS (PLUS 1 2) 3)", sexp("1 + 2 + 3"));
+    }
+
+    #[test]
+    fn minus_left_assoc() {
+        assert_eq!("(MINUS (MINUS 10 2) 3)", sexp("10 - 2 - 3"));
+    }
+
+    #[test]
+    fn plus_minus_left_assoc() {
+        assert_eq!("(PLUS (MINUS 1 2) 3)", sexp("1 - 2 + 3"));
+        assert_eq!("(MINUS (PLUS 1 2) 3)", sexp("1 + 2 - 3"));
+    }
+
+    #[test]
+    fn mult_left_assoc() {
+        assert_eq!("(MULT (MULT 1 2) 3)", sexp("1 * 2 * 3"));
+    }
+
+    #[test]
+    fn div_left_assoc() {
+        assert_eq!("(DIV (DIV 100 5) 2)", sexp("100 / 5 / 2"));
+    }
+
+    #[test]
+    fn mult_div_left_assoc() {
+        assert_eq!("(MULT (DIV 6 3) 2)", sexp("6 / 3 * 2"));
+        assert_eq!("(DIV (MULT 6 3) 2)", sexp("6 * 3 / 2"));
+    }
+
+    #[test]
+    fn div_binds_tighter_than_minus() {
+        let tokens = vec![
+            TokenKind::LITERAL(8),
+            TokenKind::OPERATOR(OP::MINUS),
+            TokenKind::LITERAL(6),
+            TokenKind::OPERATOR(OP::DIV),
+            TokenKind::LITERAL(3),
+        ];
+        let div = binary(literal(6), OP::DIV, literal(3));
+        test(tokens, binary(literal(8), OP::MINUS, boxx(div)));
+    }
+
+    #[test]
+    fn pow_right_assoc() {
+        assert_eq!("(POW 2 (POW 3 2))", sexp("2 ^ 3 ^ 2"));
+    }
+
+    #[test]
+    fn pow_binds_tighter_than_mult() {
+        assert_eq!("(MULT (POW 2 3) (POW 4 5))", sexp("2 ^ 3 * 4 ^ 5"));
+    }
+
+    #[test]
+    fn unary_binds_tighter_than_pow() {
+        assert_eq!("(POW (MINUS 2) 2)", sexp("-2 ^ 2"));
+    }
+
+    #[test]
+    fn comparisons_non_assoc() {
+        for op in ["==", "<", ">", "<=", ">="] {
+            let e = error(&format!("1 {} 2 {} 3", op, op));
+            assert_eq!(ParseErrorKind::ChainedComparison, e.kind, "{}", op);
+        }
+    }
+
+    #[test]
+    fn bp_table() {
+        assert_eq!(Some((10, 11)), bp(&TokenKind::OPERATOR(OP::PLUS)));
+        assert_eq!(Some((31, 30)), bp(&TokenKind::OPERATOR(OP::POW)));
+        assert_eq!(None, bp(&TokenKind::RPAREN));
+    }
+
+    #[test]
+    fn bool_literal() {
+        test(vec![TokenKind::BOOL(true)], E::BOOL(true, Span::default()));
     }
 
     #[test]
     fn if_test() {
-        let tok_lit = Token::LITERAL(1);
+        let tok_lit = TokenKind::LITERAL(1);
         let tokens = vec![
-            Token::IF,
+            TokenKind::IF,
             tok_lit.clone(),
-            Token::THEN,
+            TokenKind::THEN,
             tok_lit.clone(),
-            Token::ELSE,
-            Token::LITERAL(2)
+            TokenKind::ELSE,
+            TokenKind::LITERAL(2),
         ];
-        let mut iter = tokens.iter().peekable();
-        let result = expression(&mut iter, 0);
-        let cond = Box::new(E::LITERAL(1));
-        let then = Box::new(E::LITERAL(1));
-        let elze = Box::new(E::LITERAL(2));
-        let if_exp = E::IF(cond, then, elze);
-        assert_eq!(if_exp, result);
+        let if_exp = E::IF(literal(1), literal(1), literal(2), Span::default());
+        test(tokens, if_exp);
+    }
+
+    #[test]
+    fn then_keyword() {
+        assert_eq!("(if true 1 2)", sexp("if true then 1 else 2"));
+    }
+
+    fn parse_source(source: &str) -> Result<E, Box<ParseError>> {
+        let tokens = Tokenizer::new(source).run().unwrap();
+        parse(&tokens)
+    }
+
+    fn error(source: &str) -> ParseError {
+        *parse_source(source).unwrap_err()
+    }
+
+    #[test]
+    fn binary_span() {
+        let e = parse_source("12 + 3").unwrap();
+        assert_eq!(Span::new(0, 6, 1, 1), e.span());
+        match e {
+            E::BINARY(left, _, right, _) => {
+                assert_eq!(Span::new(0, 2, 1, 1), left.span());
+                assert_eq!(Span::new(5, 6, 1, 6), right.span());
+            }
+            e => panic!("expected binary, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn unary_and_paren_span() {
+        let e = parse_source(" -(1)").unwrap();
+        assert_eq!(Span::new(1, 5, 1, 2), e.span());
+        match e {
+            E::UNARY(_, paren, _) => assert_eq!(Span::new(2, 5, 1, 3), paren.span()),
+            e => panic!("expected unary, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn if_span() {
+        let e = parse_source("if 1 |\n 2 else 3").unwrap();
+        assert_eq!(Span::new(0, 16, 1, 1), e.span());
+        match e {
+            E::IF(_, then, _, _) => assert_eq!(Span::new(8, 9, 2, 2), then.span()),
+            e => panic!("expected if, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn missing_then() {
+        let e = error("if 1 else 2");
+        assert_eq!(vec![Expected::Token(TokenKind::THEN)], e.expected);
+        assert_eq!(Some(TokenKind::ELSE), e.found);
+        assert_eq!(Span::new(5, 9, 1, 6), e.span);
+        assert_eq!(Some((Span::new(0, 2, 1, 1), "`if` starts here")), e.related);
+        assert_eq!(
+            "expected `|` after if-condition, found `else`",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn missing_else() {
+        let e = error("if 1 | 2");
+        assert_eq!(None, e.found);
+        assert_eq!(Span::new(8, 8, 1, 9), e.span);
+        assert_eq!(
+            "expected `else` after then-branch, found end of input",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn unclosed_paren() {
+        let e = error("(1 + 2");
+        assert_eq!(None, e.found);
+        assert_eq!(Some((Span::new(0, 1, 1, 1), "unclosed `(`")), e.related);
+        assert_eq!(
+            "expected an operator or `)`, found end of input",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn end_of_input_column() {
+        let e = error("(1 + 2");
+        assert_eq!(Span::new(6, 6, 1, 7), e.span);
+        // `nämé` is six bytes but four columns wide
+        let e = error("(nämé");
+        assert_eq!(Span::new(7, 7, 1, 6), e.span);
+    }
+
+    #[test]
+    fn unclosed_paren_before_token() {
+        let e = error("(1 2)");
+        assert_eq!(Some(TokenKind::LITERAL(2)), e.found);
+        assert_eq!(Span::new(3, 4, 1, 4), e.span);
+    }
+
+    #[test]
+    fn missing_operand() {
+        let e = error("1 +");
+        assert_eq!(vec![Expected::Expression], e.expected);
+        assert_eq!("expected an expression, found end of input", e.to_string());
+    }
+
+    #[test]
+    fn empty_input() {
+        let e = error("");
+        assert_eq!(Span::new(0, 0, 1, 1), e.span);
+    }
+
+    #[test]
+    fn unexpected_token() {
+        let e = error("1 + )");
+        assert_eq!(Some(TokenKind::RPAREN), e.found);
+        assert_eq!("expected an expression, found `)`", e.to_string());
+    }
+
+    #[test]
+    fn trailing_tokens() {
+        let e = error("1 2");
+        assert_eq!(
+            "expected an operator or end of input, found `2`",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn stray_rparen() {
+        let e = error("1)");
+        assert_eq!(Some(TokenKind::RPAREN), e.found);
+    }
+
+    #[test]
+    fn binary_operator_as_prefix() {
+        let e = error("* 2");
+        assert_eq!(Some(TokenKind::OPERATOR(OP::MULT)), e.found);
+    }
+
+    #[test]
+    fn comparison_below_arithmetic() {
+        let tokens = vec![
+            TokenKind::LITERAL(1),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(2),
+            TokenKind::OPERATOR(OP::LESS),
+            TokenKind::LITERAL(3),
+            TokenKind::OPERATOR(OP::MULT),
+            TokenKind::LITERAL(4),
+        ];
+        let plus = binary(literal(1), OP::PLUS, literal(2));
+        let mult = binary(literal(3), OP::MULT, literal(4));
+        test(tokens, binary(boxx(plus), OP::LESS, boxx(mult)));
+    }
+
+    #[test]
+    fn comparison_operators() {
+        for op in [OP::EQUALITY, OP::GREATER, OP::LESS, OP::GEQ, OP::LEQ] {
+            let tokens = vec![
+                TokenKind::LITERAL(1),
+                TokenKind::OPERATOR(op.clone()),
+                TokenKind::LITERAL(2),
+            ];
+            test(tokens, binary(literal(1), op, literal(2)));
+        }
+    }
+
+    #[test]
+    fn if_comparison() {
+        let e = parse_source("if 2 > 3 | 1 else 2").unwrap();
+        assert_eq!("(if (GREATER 2 3) 1 2)", format!("{:?}", e));
+    }
+
+    #[test]
+    fn chained_comparison() {
+        let e = error("1 < 2 < 3");
+        assert_eq!(ParseErrorKind::ChainedComparison, e.kind);
+        assert_eq!(Some(TokenKind::OPERATOR(OP::LESS)), e.found);
+        assert_eq!(Span::new(6, 7, 1, 7), e.span);
+        assert_eq!(Some((Span::new(0, 5, 1, 1), "first comparison")), e.related);
+        assert_eq!("comparison operators cannot be chained", e.to_string());
+    }
+
+    #[test]
+    fn chained_mixed_comparison() {
+        let e = error("1 + 1 == 2 >= 0");
+        assert_eq!(ParseErrorKind::ChainedComparison, e.kind);
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse_source(&nested(MAX_DEPTH - 1)).is_ok());
+        let e = error(&nested(20000));
+        assert_eq!(ParseErrorKind::TooDeep, e.kind);
+        assert_eq!(Span::new(128, 129, 1, 129), e.span);
+        assert_eq!("expression nested too deeply", e.to_string());
+
+        let e = error(&format!("{}1", "-".repeat(50000)));
+        assert_eq!(ParseErrorKind::TooDeep, e.kind);
+        let blocks = format!("{}1{}", "{".repeat(MAX_DEPTH), "}".repeat(MAX_DEPTH));
+        let tokens = Tokenizer::new(&blocks).run().unwrap();
+        assert_eq!(ParseErrorKind::TooDeep, program(&tokens).unwrap_err().kind);
+    }
+
+    #[test]
+    fn parenthesized_comparison() {
+        let e = parse_source("(1 < 2) == true").unwrap();
+        assert_eq!("(EQUALITY ((LESS 1 2)) true)", format!("{:?}", e));
+    }
+
+    #[test]
+    fn let_binding() {
+        assert_eq!(
+            "(let x (PLUS 1 2) (MULT x x))",
+            sexp("let x = 1 + 2 in x * x")
+        );
+    }
+
+    #[test]
+    fn let_body_extends_right() {
+        assert_eq!("(let x 1 (PLUS x 1))", sexp("let x = 1 in x + 1"));
+        assert_eq!(
+            "(PLUS 2 (let x 1 (PLUS x 1)))",
+            sexp("2 + let x = 1 in x + 1")
+        );
+    }
+
+    #[test]
+    fn nested_let() {
+        assert_eq!("(let x (let y 1 y) x)", sexp("let x = let y = 1 in y in x"));
+    }
+
+    #[test]
+    fn let_span() {
+        let e = parse_source(
+            "let x = 1 in
+ x",
+        )
+        .unwrap();
+        assert_eq!(Span::new(0, 15, 1, 1), e.span());
+        match e {
+            E::LET(_, _, body, _) => assert_eq!(Span::new(14, 15, 2, 2), body.span()),
+            e => panic!("expected let, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn let_without_name() {
+        let e = error("let 1 = 2 in 3");
+        assert_eq!(vec![Expected::Identifier], e.expected);
+        assert_eq!(Span::new(4, 5, 1, 5), e.span);
+        assert_eq!(Some(LET_HELP), e.help);
+        assert_eq!(
+            "expected an identifier after `let`, found `1`",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn let_without_assign() {
+        let e = error("let x 1 in x");
+        assert_eq!(
+            Some((Span::new(0, 3, 1, 1), "`let` starts here")),
+            e.related
+        );
+        assert_eq!(
+            "expected `=` after let-bound name, found `1`",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn let_without_in() {
+        let e = error("let x = 1");
+        assert_eq!(None, e.found);
+        assert_eq!(
+            "expected `in` after let-bound value, found end of input",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn assign_instead_of_equality() {
+        let e = error("1 = 1");
+        assert_eq!(Some(TokenKind::ASSIGN), e.found);
+        let diagnostic = Diagnostic::from(&e);
+        assert_eq!(
+            vec!["use `==` to compare two values".to_string()],
+            diagnostic.help
+        );
+    }
+
+    #[test]
+    fn function() {
+        assert_eq!("(fn (x) (MULT x x))", sexp("fn x => x * x"));
+        assert_eq!("(fn (x y) (PLUS x y))", sexp("fn x, y => x + y"));
+        assert_eq!("(fn () 1)", sexp("fn => 1"));
+    }
+
+    #[test]
+    fn function_body_extends_right() {
+        assert_eq!("(PLUS 1 (fn (x) (PLUS x 1)))", sexp("1 + fn x => x + 1"));
+    }
+
+    #[test]
+    fn call() {
+        assert_eq!("(call f)", sexp("f()"));
+        assert_eq!("(call f 1 (PLUS 2 3))", sexp("f(1, 2 + 3)"));
+        assert_eq!("(call (call f 1) 2)", sexp("f(1)(2)"));
+        assert_eq!("(call ((fn (x) x)) 1)", sexp("(fn x => x)(1)"));
+    }
+
+    #[test]
+    fn call_binds_tightest() {
+        assert_eq!("(MINUS (call f 1))", sexp("-f(1)"));
+        assert_eq!("(POW 2 (call f 1))", sexp("2 ^ f(1)"));
+        assert_eq!("(if c 1 (call f 2))", sexp("if c | 1 else f(2)"));
+    }
+
+    #[test]
+    fn else_extends_right() {
+        assert_eq!("(if c 1 (PLUS 2 1))", sexp("if c | 1 else 2 + 1"));
+        assert_eq!(
+            "(if (LESS n 2) 1 (MULT n (call f (MINUS n 1))))",
+            sexp("if n < 2 | 1 else n * f(n - 1)")
+        );
+        assert_eq!(
+            "(MINUS 1 (if c 2 (MINUS 3 4)))",
+            sexp("1 - if c | 2 else 3 - 4")
+        );
+    }
+
+    #[test]
+    fn let_rec() {
+        assert_eq!(
+            "(let rec f (fn (n) (call f n)) (call f 1))",
+            sexp("let rec f = fn n => f(n) in f(1)")
+        );
+    }
+
+    #[test]
+    fn call_span() {
+        let e = parse_source(
+            "f(1,
+ 2)",
+        )
+        .unwrap();
+        assert_eq!(Span::new(0, 8, 1, 1), e.span());
+        match e {
+            E::CALL(_, args, _) => assert_eq!(Span::new(6, 7, 2, 2), args[1].span()),
+            e => panic!("expected call, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn function_errors() {
+        let e = error("fn 1 => 1");
+        assert_eq!(Some(FN_HELP), e.help);
+        assert_eq!(
+            "expected an identifier or `=>` after `fn`, found `1`",
+            e.to_string()
+        );
+        assert_eq!(
+            "expected `,` or `=>` after parameter, found `1`",
+            error("fn x 1").to_string()
+        );
+        assert_eq!(
+            "expected an identifier after `,`, found `=>`",
+            error("fn x, => 1").to_string()
+        );
+        assert_eq!(
+            "expected an identifier after `let`, found `rec`",
+            error("let rec rec = 1 in 1").to_string()
+        );
+    }
+
+    #[test]
+    fn unclosed_call() {
+        let e = error("f(1 2)");
+        assert_eq!(Some((Span::new(1, 2, 1, 2), "unclosed `(`")), e.related);
+        assert_eq!("expected an operator, `,` or `)`, found `2`", e.to_string());
+        assert_eq!(
+            "expected an expression, found `)`",
+            error("f(1,)").to_string()
+        );
+    }
+
+    fn statements(source: &str) -> Result<Vec<String>, Box<ParseError>> {
+        let tokens = Tokenizer::new(source).run().unwrap();
+        let program = program(&tokens)?;
+        Ok(program.iter().map(|s| format!("{:?}", s)).collect())
+    }
+
+    #[test]
+    fn statement_sequence() {
+        assert_eq!(
+            Ok(vec![
+                "(let x 1)".to_string(),
+                "(print x)".to_string(),
+                "(PLUS x 1)".to_string()
+            ]),
+            statements("let x = 1; print x; x + 1")
+        );
+        assert_eq!(Ok(vec![]), statements(""));
+        assert_eq!(Ok(vec!["1".to_string()]), statements("1;"));
+    }
+
+    #[test]
+    fn let_expression_statement() {
+        assert_eq!(
+            Ok(vec!["(let x 1 x)".to_string(), "2".to_string()]),
+            statements("let x = 1 in x; 2")
+        );
+        assert_eq!(
+            Ok(vec!["(let rec f (fn (x) (call f x)))".to_string()]),
+            statements("let rec f = fn x => f(x)")
+        );
+    }
+
+    #[test]
+    fn block() {
+        assert_eq!("(block (let x 1) x)", sexp("{ let x = 1; x }"));
+        assert_eq!("(PLUS (block 1) 2)", sexp("{ 1 } + 2"));
+        assert_eq!("(block)", sexp("{}"));
+    }
+
+    #[test]
+    fn block_span() {
+        let e = parse_source("1 + {\n  2;\n}").unwrap();
+        match e {
+            E::BINARY(_, _, right, _) => assert_eq!(Span::new(4, 12, 1, 5), right.span()),
+            e => panic!("expected binary, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn statement_errors() {
+        let e = statements("1 2").unwrap_err();
+        assert_eq!(
+            "expected an operator, `;` or end of input, found `2`",
+            e.to_string()
+        );
+        let e = error("{ 1 2 }");
+        assert_eq!(Some((Span::new(0, 1, 1, 1), "unclosed `{`")), e.related);
+        assert_eq!("expected an operator, `;` or `}`, found `2`", e.to_string());
+        let e = error("{ 1; ");
+        assert_eq!(Some((Span::new(0, 1, 1, 1), "unclosed `{`")), e.related);
+        assert_eq!(
+            "expected an expression or `}`, found end of input",
+            e.to_string()
+        );
+        assert_eq!(
+            "expected an expression, found `print`",
+            error("1 + print 2").to_string()
+        );
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(
+            Ok(vec![
+                "(var x 1)".to_string(),
+                "(set x (PLUS x 1))".to_string(),
+                "(EQUALITY x 2)".to_string()
+            ]),
+            statements("var x = 1; x = x + 1; x == 2")
+        );
+    }
+
+    #[test]
+    fn while_loop() {
+        assert_eq!(
+            Ok(vec![
+                "(while (LESS i 10) (set i (PLUS i 1)) (block (break)) (continue))".to_string()
+            ]),
+            statements("while i < 10 { i = i + 1; { break }; continue; }")
+        );
+        assert_eq!(
+            Ok(vec!["(while true)".to_string()]),
+            statements("while true {}")
+        );
+    }
+
+    #[test]
+    fn while_span() {
+        let tokens = Tokenizer::new("while x {\n}").run().unwrap();
+        match &program(&tokens).unwrap()[..] {
+            [S::WHILE(_, _, span)] => assert_eq!(Span::new(0, 11, 1, 1), *span),
+            program => panic!("expected while, got {:?}", program),
+        }
+    }
+
+    #[test]
+    fn assignment_is_a_statement() {
+        let expected = "expected an operator, `;` or end of input, found `=`";
+        assert_eq!(expected, statements("1 + x = 2").unwrap_err().to_string());
+        assert_eq!(expected, statements("(x) = 2").unwrap_err().to_string());
+    }
+
+    #[test]
+    fn loop_errors() {
+        let e = statements("while x y").unwrap_err();
+        assert_eq!(
+            "expected `{` after while-condition, found `y`",
+            e.to_string()
+        );
+        assert_eq!(
+            Some((Span::new(0, 5, 1, 1), "`while` starts here")),
+            e.related
+        );
+        assert_eq!(Some(WHILE_HELP), e.help);
+        let e = statements("var 1 = 2").unwrap_err();
+        assert_eq!(
+            "expected an identifier after `var`, found `1`",
+            e.to_string()
+        );
+        let e = statements("var x 2").unwrap_err();
+        assert_eq!("expected `=` after variable name, found `2`", e.to_string());
+        assert_eq!(
+            "expected an expression, found `break`",
+            error("1 + break").to_string()
+        );
     }
 }
@@ -1,49 +1,656 @@
-use crate::{pratt::E, tokenizer::OP};
+use std::{
+    fmt,
+    io::Write,
+    sync::{Arc, Mutex},
+    thread,
+};
 
-fn resolve(expression: E) -> i32 {
+use crate::{
+    diagnostic::Diagnostic,
+    pratt::{E, S},
+    span::Span,
+    tokenizer::OP,
+};
+
+/// `eval` recurses along with the program, so it runs on a thread of its own.
+/// The stack is only reserved, pages are used as the calls nest.
+const STACK_SIZE: usize = 1 << 30;
+/// The stack a call may use. Unoptimized, a call of a small function takes
+/// about 22 KiB of `eval` frames, a body nesting a dozen expressions around
+/// the call about 75 KiB.
+const CALL_FRAME_SIZE: usize = 128 << 10;
+/// Calls nested deeper than this are reported instead of overflowing the
+/// stack of the interpreter
+pub const MAX_CALL_DEPTH: usize = STACK_SIZE / CALL_FRAME_SIZE;
+/// A body nesting many expressions around the call can use more than
+/// `CALL_FRAME_SIZE`, so `eval` also stops once less than this is left
+const STACK_MARGIN: usize = 1 << 20;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Value {
+    Int(i32),
+    Bool(bool),
+    Function(Arc<Closure>),
+    /// The value of statements that are not expressions, and of sequences
+    /// that end in one
+    Unit,
+}
+
+/// A function together with the variables in scope where it was defined
+#[derive(Debug)]
+pub struct Closure {
+    params: Vec<String>,
+    body: E,
+    env: Vec<(String, Binding)>,
+    /// The name a `let rec` binds the function to inside its own body
+    name: Option<String>,
+    /// Where the function was defined
+    span: Span,
+}
+
+/// A variable in scope. The value of a `var` lives in a cell shared with
+/// the closures that capture it, so they see its assignments.
+#[derive(Debug, Clone)]
+enum Binding {
+    Let(Value),
+    Var(Arc<Mutex<Value>>),
+}
+
+impl Binding {
+    fn value(&self) -> Value {
+        match self {
+            Binding::Let(value) => value.clone(),
+            Binding::Var(cell) => cell.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Functions are only equal to themselves
+impl PartialEq for Closure {
+    fn eq(&self, other: &Closure) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for Closure {}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::Bool(_) => "bool",
+            Value::Function(_) => "function",
+            Value::Unit => "unit",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Function(closure) => match &closure.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => write!(f, "<fn>"),
+            },
+            Value::Unit => write!(f, "()"),
+        }
+    }
+}
+
+impl From<i32> for Value {
+    fn from(v: i32) -> Value {
+        Value::Int(v)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    /// The result of the operator does not fit in an `i32`
+    Overflow(OP),
+    NegativeExponent(i32),
+    /// A literal larger than `i32::MAX`
+    LiteralOutOfRange(u32),
+    /// An operand or if-condition of the wrong type
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    UnboundVariable(String),
+    /// A function called with the wrong number of arguments
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+    },
+    RecursionLimit,
+    /// An assignment to a variable bound by `let`
+    AssignToImmutable(String),
+    /// A `break` or `continue` that is not inside a loop of the function
+    /// it is in
+    OutsideLoop(&'static str),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    /// The span of the node that failed
+    pub span: Span,
+    /// The operand responsible for the failure, e.g. the divisor, or the
+    /// definition of a function called with the wrong number of arguments
+    pub operand: Option<Span>,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            RuntimeErrorKind::DivisionByZero => write!(f, "attempt to divide by zero"),
+            RuntimeErrorKind::Overflow(op) => write!(f, "arithmetic overflow in `{}`", op),
+            RuntimeErrorKind::NegativeExponent(e) => {
+                write!(f, "attempt to raise to the negative power {}", e)
+            }
+            RuntimeErrorKind::LiteralOutOfRange(v) => {
+                write!(f, "literal {} does not fit in a 32-bit signed integer", v)
+            }
+            RuntimeErrorKind::TypeMismatch { expected, found } => {
+                write!(
+                    f,
+                    "mismatched types: expected `{}`, found `{}`",
+                    expected, found
+                )
+            }
+            RuntimeErrorKind::UnboundVariable(name) => {
+                write!(f, "cannot find variable `{}` in this scope", name)
+            }
+            RuntimeErrorKind::ArityMismatch { expected, found } => write!(
+                f,
+                "this function takes {} but {} {} supplied",
+                arguments(*expected),
+                arguments(*found),
+                if *found == 1 { "was" } else { "were" }
+            ),
+            RuntimeErrorKind::RecursionLimit => write!(f, "recursion limit reached"),
+            RuntimeErrorKind::AssignToImmutable(name) => {
+                write!(f, "cannot assign to immutable variable `{}`", name)
+            }
+            RuntimeErrorKind::OutsideLoop(keyword) => write!(f, "`{}` outside of a loop", keyword),
+        }
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(e: &RuntimeError) -> Diagnostic {
+        let diagnostic = Diagnostic::error(e.to_string());
+        let diagnostic =
+            match &e.kind {
+                RuntimeErrorKind::DivisionByZero => diagnostic.primary(e.span, "division by zero"),
+                RuntimeErrorKind::Overflow(_) => diagnostic
+                    .primary(e.span, "overflows")
+                    .help(format!("integers range from {} to {}", i32::MIN, i32::MAX)),
+                RuntimeErrorKind::NegativeExponent(_) => {
+                    diagnostic.primary(e.span, "negative exponent")
+                }
+                RuntimeErrorKind::LiteralOutOfRange(_) => diagnostic
+                    .primary(e.span, "out of range")
+                    .help(format!("the largest integer is {}", i32::MAX)),
+                RuntimeErrorKind::TypeMismatch { expected, found } => diagnostic.primary(
+                    e.operand.unwrap_or(e.span),
+                    format!("expected `{}`, found `{}`", expected, found),
+                ),
+                RuntimeErrorKind::UnboundVariable(_) => {
+                    diagnostic.primary(e.span, "not found in this scope")
+                }
+                RuntimeErrorKind::ArityMismatch { expected, .. } => {
+                    diagnostic.primary(e.span, format!("expected {}", arguments(*expected)))
+                }
+                RuntimeErrorKind::RecursionLimit => diagnostic
+                    .primary(e.span, "recursion too deep")
+                    .help(format!(
+                        "calls nest at most {} deep, check the base case",
+                        MAX_CALL_DEPTH
+                    )),
+                RuntimeErrorKind::AssignToImmutable(name) => {
+                    diagnostic.primary(e.span, "cannot assign").help(format!(
+                        "declare it with `var {} = ...` to make it mutable",
+                        name
+                    ))
+                }
+                RuntimeErrorKind::OutsideLoop(keyword) => {
+                    diagnostic.primary(e.span, format!("cannot `{}` outside of a loop", keyword))
+                }
+            };
+        match (&e.kind, e.operand) {
+            (RuntimeErrorKind::DivisionByZero, Some(operand)) => {
+                diagnostic.secondary(operand, "this evaluates to 0")
+            }
+            (RuntimeErrorKind::NegativeExponent(v), Some(operand)) => {
+                diagnostic.secondary(operand, format!("this evaluates to {}", v))
+            }
+            (RuntimeErrorKind::ArityMismatch { .. }, Some(operand)) => {
+                diagnostic.secondary(operand, "function defined here")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+/// "1 argument", "2 arguments"
+pub fn arguments(n: usize) -> String {
+    match n {
+        1 => "1 argument".to_string(),
+        n => format!("{} arguments", n),
+    }
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, span: Span) -> RuntimeError {
+        RuntimeError {
+            kind,
+            span,
+            operand: None,
+        }
+    }
+
+    pub fn operand(self, operand: Span) -> RuntimeError {
+        RuntimeError {
+            operand: Some(operand),
+            ..self
+        }
+    }
+}
+
+/// Why evaluation stopped before producing a value: an error, or a
+/// `break` or `continue` on its way to the enclosing loop
+enum Unwind {
+    Error(RuntimeError),
+    Break(Span),
+    Continue(Span),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(e: RuntimeError) -> Unwind {
+        Unwind::Error(e)
+    }
+}
+
+impl Unwind {
+    /// The error for unwinding out of a function or program, where a
+    /// `break` or `continue` has no loop left to go to
+    fn error(self) -> RuntimeError {
+        match self {
+            Unwind::Error(e) => e,
+            Unwind::Break(span) => RuntimeError::new(RuntimeErrorKind::OutsideLoop("break"), span),
+            Unwind::Continue(span) => {
+                RuntimeError::new(RuntimeErrorKind::OutsideLoop("continue"), span)
+            }
+        }
+    }
+}
+
+/// The variables in scope, later bindings shadow earlier ones
+struct Environment<'a> {
+    bindings: Vec<(String, Binding)>,
+    /// The number of calls being evaluated
+    depth: usize,
+    /// Where the stack of the interpreter thread starts
+    stack: usize,
+    /// Where `print` writes to
+    out: &'a mut (dyn Write + Send),
+}
+
+impl Environment<'_> {
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// The address of a local of the caller, for how much of the stack is in use
+#[inline(never)]
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+fn literal(v: u32, span: Span) -> Result<Value, RuntimeError> {
+    i32::try_from(v)
+        .map(Value::Int)
+        .map_err(|_| RuntimeError::new(RuntimeErrorKind::LiteralOutOfRange(v), span))
+}
+
+fn mismatch(expected: &'static str, found: &Value, span: Span, operand: Span) -> RuntimeError {
+    let kind = RuntimeErrorKind::TypeMismatch {
+        expected,
+        found: found.type_name(),
+    };
+    RuntimeError::new(kind, span).operand(operand)
+}
+
+/// Runs `program`, `print` writes to `out`. Returns the value of the
+/// program as a sequence of statements.
+pub fn execute(program: Vec<S>, out: &mut (dyn Write + Send)) -> Result<Value, RuntimeError> {
+    thread::scope(|scope| {
+        let mut env = Environment {
+            bindings: vec![],
+            depth: 0,
+            stack: 0,
+            out,
+        };
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, move || {
+                env.stack = stack_position();
+                run(&program, &mut env).map_err(Unwind::error)
+            })
+            .expect("failed to spawn the interpreter thread")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Runs statements in order, their bindings go out of scope at the end
+fn run(statements: &[S], env: &mut Environment) -> Result<Value, Unwind> {
+    let scope = env.bindings.len();
+    let result = statements
+        .iter()
+        .try_fold(Value::Unit, |_, statement| match statement {
+            S::EXPR(e) => eval(e, env),
+            S::LET(name, value, _) => {
+                let value = eval(value, env)?;
+                env.bindings.push((name.clone(), Binding::Let(value)));
+                Ok(Value::Unit)
+            }
+            S::LETREC(name, value, _) => {
+                let value = recursive(name, eval(value, env)?);
+                env.bindings.push((name.clone(), Binding::Let(value)));
+                Ok(Value::Unit)
+            }
+            S::PRINT(e, _) => {
+                let value = eval(e, env)?;
+                writeln!(env.out, "{}", value).expect("failed to write output");
+                Ok(Value::Unit)
+            }
+            S::VAR(name, value, _) => {
+                let value = eval(value, env)?;
+                env.bindings
+                    .push((name.clone(), Binding::Var(Arc::new(Mutex::new(value)))));
+                Ok(Value::Unit)
+            }
+            S::ASSIGN(name, value, span) => {
+                let cell = match env.lookup(name) {
+                    Some(Binding::Var(cell)) => cell.clone(),
+                    Some(Binding::Let(_)) => {
+                        let kind = RuntimeErrorKind::AssignToImmutable(name.clone());
+                        return Err(RuntimeError::new(kind, *span).into());
+                    }
+                    None => {
+                        let kind = RuntimeErrorKind::UnboundVariable(name.clone());
+                        return Err(RuntimeError::new(kind, *span).into());
+                    }
+                };
+                *cell.lock().unwrap() = eval(value, env)?;
+                Ok(Value::Unit)
+            }
+            S::WHILE(cond, body, span) => {
+                let operand = cond.span();
+                loop {
+                    match eval(cond, env)? {
+                        Value::Bool(true) => (),
+                        Value::Bool(false) => break,
+                        v => return Err(mismatch("bool", &v, *span, operand).into()),
+                    }
+                    match run(body, env) {
+                        Ok(_) | Err(Unwind::Continue(_)) => (),
+                        Err(Unwind::Break(_)) => break,
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(Value::Unit)
+            }
+            S::BREAK(span) => Err(Unwind::Break(*span)),
+            S::CONTINUE(span) => Err(Unwind::Continue(*span)),
+        });
+    env.bindings.truncate(scope);
+    result
+}
+
+/// A function bound by `let rec` sees itself through `name` when called, any
+/// other value cannot refer to itself
+fn recursive(name: &str, value: Value) -> Value {
+    match value {
+        Value::Function(closure) => Value::Function(Arc::new(Closure {
+            params: closure.params.clone(),
+            body: closure.body.clone(),
+            env: closure.env.clone(),
+            name: Some(name.to_string()),
+            span: closure.span,
+        })),
+        value => value,
+    }
+}
+
+fn eval(expression: &E, env: &mut Environment) -> Result<Value, Unwind> {
+    // the stack grows down
+    if env.stack - stack_position() > STACK_SIZE - STACK_MARGIN {
+        let span = expression.span();
+        return Err(RuntimeError::new(RuntimeErrorKind::RecursionLimit, span).into());
+    }
     match expression {
-        E::LITERAL(v) => v as i32,
-        E::UNARY(OP::PLUS, v) | E::PAREN(v) => resolve(*v),
-        E::UNARY(OP::MINUS, v) => -resolve(*v),
-        E::BINARY(v1, OP::MINUS, v2) => resolve(*v1) - resolve(*v2),
-        E::BINARY(v1, OP::PLUS, v2) => resolve(*v1) + resolve(*v2),
-        E::BINARY(v1, OP::MULT, v2) => resolve(*v1) * resolve(*v2),
-        E::BINARY(v1, OP::DIV, v2) => resolve(*v1) / resolve(*v2),
-        E::BINARY(v1, OP::POW, v2) => resolve(*v1).pow(resolve(*v2) as u32) as i32,
-        E::IF(cond, then, elze) => {
-            let cond = resolve(*cond);
-            if cond == 1 {
-                resolve(*then)
-            } else {
-                resolve(*elze)
+        E::LITERAL(v, span) => Ok(literal(*v, *span)?),
+        E::BOOL(b, _) => Ok(Value::Bool(*b)),
+        E::PAREN(v, _) => eval(v, env),
+        // `-2147483648` is the negation of a literal that is out of range on its own
+        E::UNARY(OP::MINUS, v, _) if matches!(**v, E::LITERAL(2147483648, _)) => {
+            Ok(Value::Int(i32::MIN))
+        }
+        E::UNARY(op, v, span) => {
+            let operand = v.span();
+            match (op, eval(v, env)?) {
+                (OP::PLUS, Value::Int(v)) => Ok(Value::Int(v)),
+                (OP::MINUS, Value::Int(v)) => v
+                    .checked_neg()
+                    .map(Value::Int)
+                    .ok_or(RuntimeError::new(RuntimeErrorKind::Overflow(OP::MINUS), *span).into()),
+                (_, v) => Err(mismatch("int", &v, *span, operand).into()),
+            }
+        }
+        E::BINARY(v1, op, v2, span) => {
+            let (left, right) = (v1.span(), v2.span());
+            match (eval(v1, env)?, eval(v2, env)?) {
+                (a, b) if *op == OP::EQUALITY && a.type_name() == b.type_name() => {
+                    Ok(Value::Bool(a == b))
+                }
+                (Value::Int(a), Value::Int(b)) => binary(op, a, b).map_err(|kind| {
+                    match kind {
+                        RuntimeErrorKind::DivisionByZero
+                        | RuntimeErrorKind::NegativeExponent(_) => {
+                            RuntimeError::new(kind, *span).operand(right)
+                        }
+                        _ => RuntimeError::new(kind, *span),
+                    }
+                    .into()
+                }),
+                (a, b) if *op == OP::EQUALITY => {
+                    Err(mismatch(a.type_name(), &b, *span, right).into())
+                }
+                (Value::Int(_), b) => Err(mismatch("int", &b, *span, right).into()),
+                (a, _) => Err(mismatch("int", &a, *span, left).into()),
+            }
+        }
+        E::IF(cond, then, elze, span) => {
+            let operand = cond.span();
+            match eval(cond, env)? {
+                Value::Bool(true) => eval(then, env),
+                Value::Bool(false) => eval(elze, env),
+                v => Err(mismatch("bool", &v, *span, operand).into()),
             }
         }
-        _ => todo!("Not implemented"),
+        E::LET(name, value, body, _) => {
+            let value = eval(value, env)?;
+            env.bindings.push((name.clone(), Binding::Let(value)));
+            let result = eval(body, env);
+            env.bindings.pop();
+            result
+        }
+        E::LETREC(name, value, body, _) => {
+            let value = recursive(name, eval(value, env)?);
+            env.bindings.push((name.clone(), Binding::Let(value)));
+            let result = eval(body, env);
+            env.bindings.pop();
+            result
+        }
+        E::VAR(name, span) => match env.lookup(name) {
+            Some(binding) => Ok(binding.value()),
+            None => {
+                let kind = RuntimeErrorKind::UnboundVariable(name.clone());
+                Err(RuntimeError::new(kind, *span).into())
+            }
+        },
+        E::FN(params, body, span) => Ok(Value::Function(Arc::new(Closure {
+            params: params.clone(),
+            body: (**body).clone(),
+            env: env.bindings.clone(),
+            name: None,
+            span: *span,
+        }))),
+        E::CALL(callee, args, span) => call(callee, args, *span, env),
+        E::BLOCK(statements, _) => run(statements, env),
+    }
+}
+
+/// Evaluates the arguments in the caller's environment and the body in the
+/// environment of the closure. Loops do not reach into the body, a `break`
+/// unwinding out of it is an error.
+fn call(callee: &E, args: &[E], span: Span, env: &mut Environment) -> Result<Value, Unwind> {
+    let operand = callee.span();
+    let closure = match eval(callee, env)? {
+        Value::Function(closure) => closure,
+        v => return Err(mismatch("function", &v, span, operand).into()),
+    };
+    if closure.params.len() != args.len() {
+        let kind = RuntimeErrorKind::ArityMismatch {
+            expected: closure.params.len(),
+            found: args.len(),
+        };
+        return Err(RuntimeError::new(kind, span).operand(closure.span).into());
+    }
+    if env.depth == MAX_CALL_DEPTH {
+        return Err(RuntimeError::new(RuntimeErrorKind::RecursionLimit, span).into());
+    }
+
+    let mut bindings = closure.env.clone();
+    if let Some(name) = &closure.name {
+        let function = Value::Function(closure.clone());
+        bindings.push((name.clone(), Binding::Let(function)));
+    }
+    for (param, arg) in closure.params.iter().zip(args) {
+        let arg = eval(arg, env)?;
+        bindings.push((param.clone(), Binding::Let(arg)));
+    }
+    let mut inner = Environment {
+        bindings,
+        depth: env.depth + 1,
+        stack: env.stack,
+        out: &mut *env.out,
+    };
+    eval(&closure.body, &mut inner).map_err(|unwind| unwind.error().into())
+}
+
+/// Operators on two integers
+pub fn binary(op: &OP, a: i32, b: i32) -> Result<Value, RuntimeErrorKind> {
+    let overflow = || RuntimeErrorKind::Overflow(op.clone());
+    let int = |v: Option<i32>| v.map(Value::Int).ok_or_else(overflow);
+    match op {
+        OP::PLUS => int(a.checked_add(b)),
+        OP::MINUS => int(a.checked_sub(b)),
+        OP::MULT => int(a.checked_mul(b)),
+        OP::DIV if b == 0 => Err(RuntimeErrorKind::DivisionByZero),
+        // i32::MIN / -1 is the only overflowing division
+        OP::DIV => int(a.checked_div(b)),
+        OP::POW if b < 0 => Err(RuntimeErrorKind::NegativeExponent(b)),
+        OP::POW => int(a.checked_pow(b as u32)),
+        OP::EQUALITY => Ok(Value::Bool(a == b)),
+        OP::GREATER => Ok(Value::Bool(a > b)),
+        OP::LESS => Ok(Value::Bool(a < b)),
+        OP::GEQ => Ok(Value::Bool(a >= b)),
+        OP::LEQ => Ok(Value::Bool(a <= b)),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{parser::Parser, pratt::expression, tokenizer::Tokenizer};
+    use std::io;
+
+    use crate::{
+        bytecode, grammar,
+        parser::Parser,
+        pratt::{parse, program},
+        tokenizer::Tokenizer,
+        typeck,
+    };
 
     use super::*;
 
-    fn test(input: &str, result: i32) {
+    fn resolve(e: E) -> Result<Value, RuntimeError> {
+        run(vec![S::EXPR(e)], &mut io::sink())
+    }
+
+    /// Executes `program` with both the resolver and the bytecode VM, which
+    /// must agree on the value, the error and the output
+    fn run(program: Vec<S>, out: &mut (dyn Write + Send)) -> Result<Value, RuntimeError> {
+        let mut vm_out = vec![];
+        let vm = bytecode::run(&bytecode::compile(&program), &mut vm_out);
+        let mut resolver_out = vec![];
+        let result = execute(program, &mut resolver_out);
+        assert_eq!(
+            result.as_ref().map(Value::to_string),
+            vm.as_ref().map(bytecode::Value::to_string),
+            "bytecode VM"
+        );
+        assert_eq!(resolver_out, vm_out, "bytecode VM output");
+        out.write_all(&resolver_out).unwrap();
+        result
+    }
+
+    fn test(input: &str, result: impl Into<Value>) {
+        let result = result.into();
         let binding = String::from(input);
         let mut tokenizer = Tokenizer::new(&binding);
-        let tokens = tokenizer.run();
+        let tokens = tokenizer.run().unwrap();
 
         // LR(1) parser
-        //let mut parser = Parser::new(tokens);
-        //let ast = parser.run();
-        //let resolved = resolve(ast.to_owned());
-        //assert_eq!(result, resolved, "LR(1)");
-
+        let mut parser = Parser::new(&tokens);
+        let lr_ast = parser.run().unwrap();
+        let resolved = resolve(lr_ast.to_owned()).unwrap();
+        assert_eq!(result, resolved, "LR(1)");
 
         // Pratt parser
-        let ast = expression(&mut tokens.iter().peekable(), 0);
-        let resolved = resolve(ast.to_owned());
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(ast, lr_ast, "both parsers build the same tree");
+        assert_eq!(Ok(ast.clone()), grammar::parse(&tokens), "LALR(1)");
+        let resolved = resolve(ast.to_owned()).unwrap();
         assert_eq!(result, resolved, "Pratt");
+        let checked = typeck::check(&[S::EXPR(ast)]);
+        assert!(
+            checked.is_ok(),
+            "rejected by the type checker: {:?}",
+            checked
+        );
+    }
+
+    fn error(input: &str) -> RuntimeError {
+        let tokens = Tokenizer::new(input).run().unwrap();
+        resolve(parse(&tokens).unwrap()).unwrap_err()
     }
 
     #[test]
@@ -66,6 +673,28 @@ mod tests {
         test("1 +\n 3 * 2", 7);
     }
 
+    #[test]
+    fn subtraction() {
+        test("10 - 2 - 3", 5);
+    }
+
+    #[test]
+    fn division() {
+        test("100 / 5 / 2", 10);
+    }
+
+    #[test]
+    fn division_truncates() {
+        test("7 / 2", 3);
+        test("-7 / 2", -3);
+    }
+
+    #[test]
+    fn mixed_arithmetic() {
+        test("8 - 6 / 3 * 2", 4);
+        test("2 ^ 3 ^ 2", 512);
+    }
+
     #[test]
     fn pow() {
         test("1 + 2 ^ 3 + 2 + 2 * 3", 17);
@@ -78,21 +707,544 @@ mod tests {
 
     #[test]
     fn true_if() {
-        test("if 1 | 1 else 2", 1);
+        test("if true | 1 else 2", 1);
     }
 
     #[test]
     fn false_if() {
-        test("if 0 | 1 else 2", 2);
+        test("if false | 1 else 2", 2);
     }
 
     #[test]
     fn addition_if() {
-        test("1 + if 0 | 1 else 2", 3);
+        test("1 + if false | 1 else 2", 3);
     }
 
+    /// The else branch extends as far as possible, as it did in the first
+    /// Pratt parser, so `2 + 1` is all of it. The test used to expect 2, from
+    /// `(if 1 | 1 else 2) + 1`, which that parser never produced.
     #[test]
     fn addition_right_if() {
-        test("if 1 | 1 else 2 + 1", 2);
+        test("if true | 1 else 2 + 1", 1);
+        test("if false | 1 else 2 + 1", 3);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let e = error("1 + 4 / (2 - 2)");
+        assert_eq!(RuntimeErrorKind::DivisionByZero, e.kind);
+        assert_eq!(Span::new(4, 15, 1, 5), e.span);
+        assert_eq!(Some(Span::new(8, 15, 1, 9)), e.operand);
+    }
+
+    #[test]
+    fn division_overflow() {
+        assert_eq!(
+            RuntimeErrorKind::Overflow(OP::DIV),
+            error("-2147483648 / -1").kind
+        );
+    }
+
+    #[test]
+    fn addition_overflow() {
+        let e = error("2147483647 + 1");
+        assert_eq!(RuntimeErrorKind::Overflow(OP::PLUS), e.kind);
+        assert_eq!(Span::new(0, 14, 1, 1), e.span);
+    }
+
+    #[test]
+    fn mult_overflow() {
+        assert_eq!(
+            RuntimeErrorKind::Overflow(OP::MULT),
+            error("65536 * 65536").kind
+        );
+    }
+
+    #[test]
+    fn pow_overflow() {
+        assert_eq!(RuntimeErrorKind::Overflow(OP::POW), error("2 ^ 31").kind);
+    }
+
+    #[test]
+    fn pow_max() {
+        test("-2 ^ 31", i32::MIN);
+    }
+
+    #[test]
+    fn negative_exponent() {
+        let e = error("2 ^ (0 - 3)");
+        assert_eq!(RuntimeErrorKind::NegativeExponent(-3), e.kind);
+        assert_eq!(Some(Span::new(4, 11, 1, 5)), e.operand);
+    }
+
+    #[test]
+    fn literal_out_of_range() {
+        let e = error("1 + 2147483648");
+        assert_eq!(RuntimeErrorKind::LiteralOutOfRange(2147483648), e.kind);
+        assert_eq!(Span::new(4, 14, 1, 5), e.span);
+    }
+
+    #[test]
+    fn min_literal() {
+        test("-2147483648", i32::MIN);
+    }
+
+    #[test]
+    fn negation_overflow() {
+        assert_eq!(
+            RuntimeErrorKind::Overflow(OP::MINUS),
+            error("-(-2147483648)").kind
+        );
+    }
+
+    #[test]
+    fn bool_literal() {
+        test("true", true);
+        test("false", false);
+    }
+
+    #[test]
+    fn bool_branches() {
+        test("if true | false else true", false);
+    }
+
+    #[test]
+    fn int_condition() {
+        let e = error("if 1 | 2 else 3");
+        let expected = RuntimeErrorKind::TypeMismatch {
+            expected: "bool",
+            found: "int",
+        };
+        assert_eq!(expected, e.kind);
+        assert_eq!(Some(Span::new(3, 4, 1, 4)), e.operand);
+    }
+
+    #[test]
+    fn bool_operand() {
+        let e = error("1 + true");
+        let expected = RuntimeErrorKind::TypeMismatch {
+            expected: "int",
+            found: "bool",
+        };
+        assert_eq!(expected, e.kind);
+        assert_eq!(Some(Span::new(4, 8, 1, 5)), e.operand);
+    }
+
+    #[test]
+    fn bool_left_operand() {
+        let e = error("false * 2");
+        assert_eq!(Some(Span::new(0, 5, 1, 1)), e.operand);
+    }
+
+    #[test]
+    fn negate_bool() {
+        let e = error("-true");
+        assert_eq!(Some(Span::new(1, 5, 1, 2)), e.operand);
+    }
+
+    #[test]
+    fn comparisons() {
+        test("1 < 2", true);
+        test("2 < 2", false);
+        test("2 <= 2", true);
+        test("3 > 2", true);
+        test("2 >= 3", false);
+        test("2 == 2", true);
+    }
+
+    #[test]
+    fn comparison_precedence() {
+        test("1 + 2 == 3", true);
+        test("2 * 3 > 2 ^ 3", false);
+    }
+
+    #[test]
+    fn bool_equality() {
+        test("true == false", false);
+        test("(1 < 2) == true", true);
+    }
+
+    #[test]
+    fn if_comparison() {
+        test("if 5 > 3 | 10 else 20", 10);
+        test("1 + if 5 <= 3 | 10 else 20", 21);
+    }
+
+    #[test]
+    fn equality_mismatch() {
+        let e = error("1 == true");
+        let expected = RuntimeErrorKind::TypeMismatch {
+            expected: "int",
+            found: "bool",
+        };
+        assert_eq!(expected, e.kind);
+    }
+
+    #[test]
+    fn ordering_bools() {
+        let e = error("true < false");
+        assert_eq!(Some(Span::new(0, 4, 1, 1)), e.operand);
+    }
+
+    #[test]
+    fn let_binding() {
+        test("let x = 1 + 2 in x * x", 9);
+        test("let b = 1 < 2 in if b | 10 else 20", 10);
+    }
+
+    #[test]
+    fn shadowing() {
+        test("let x = 1 in let x = x + 1 in x", 2);
+        test("let x = 1 in (let x = 2 in x) + x", 3);
+    }
+
+    #[test]
+    fn nested_let() {
+        test("let x = 2 in let y = x * 3 in x + y", 8);
+    }
+
+    #[test]
+    fn then_keyword() {
+        test("if 1 < 2 then 10 else 20", 10);
+        test("if false then 1 else if true | 2 else 3", 2);
+    }
+
+    #[test]
+    fn unicode_variable() {
+        test("let größe = 3 in größe * größe", 9);
+    }
+
+    #[test]
+    fn unbound_variable() {
+        let e = error("let x = 1 in x + y");
+        assert_eq!(RuntimeErrorKind::UnboundVariable("y".to_string()), e.kind);
+        assert_eq!(Span::new(17, 18, 1, 18), e.span);
+    }
+
+    #[test]
+    fn binding_out_of_scope() {
+        let e = error("(let x = 1 in x) + x");
+        assert_eq!(RuntimeErrorKind::UnboundVariable("x".to_string()), e.kind);
+        assert_eq!(Span::new(19, 20, 1, 20), e.span);
+    }
+
+    #[test]
+    fn unbound_variable_diagnostic() {
+        let diagnostic = Diagnostic::from(&error("1 + foo"));
+        assert_eq!(
+            "cannot find variable `foo` in this scope",
+            diagnostic.message
+        );
+        assert_eq!("not found in this scope", diagnostic.labels[0].message);
+    }
+
+    fn value(input: &str) -> Value {
+        let tokens = Tokenizer::new(input).run().unwrap();
+        resolve(parse(&tokens).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn function_call() {
+        test("let sq = fn x => x * x in sq(4)", 16);
+        test("(fn x, y => x - y)(5, 3)", 2);
+        test("(fn => 7)()", 7);
+    }
+
+    #[test]
+    fn call_binds_tighter_than_prefix() {
+        test("let f = fn x => x in -f(2) ^ 2", 4);
+    }
+
+    #[test]
+    fn closure_captures_environment() {
+        test(
+            "let x = 1 in let f = fn y => x + y in let x = 100 in f(1)",
+            2,
+        );
+    }
+
+    #[test]
+    fn curried_function() {
+        test("let add = fn x => fn y => x + y in add(1)(2)", 3);
+    }
+
+    #[test]
+    fn higher_order_function() {
+        test(
+            "let twice = fn f, x => f(f(x)) in twice(fn x => x * 3, 2)",
+            18,
+        );
+    }
+
+    #[test]
+    fn factorial() {
+        test(
+            "let rec fact = fn n => if n == 0 | 1 else (n * fact(n - 1)) in fact(10)",
+            3628800,
+        );
+        test(
+            "let rec f = fn n => if n < 2 then 1 else n * f(n - 1) in f(5)",
+            120,
+        );
+    }
+
+    #[test]
+    fn fibonacci() {
+        test(
+            "let rec fib = fn n => if n < 2 | n else (fib(n - 1) + fib(n - 2)) in fib(15)",
+            610,
+        );
+        test(
+            "let rec fib = fn n => if n < 2 | n else fib(n - 1) + fib(n - 2) in fib(15)",
+            610,
+        );
+    }
+
+    #[test]
+    fn recursion_needs_rec() {
+        let e = error("let f = fn n => f(n) in f(1)");
+        assert_eq!(RuntimeErrorKind::UnboundVariable("f".to_string()), e.kind);
+    }
+
+    #[test]
+    fn recursion_limit() {
+        let deepest = format!(
+            "let rec f = fn n => if n == 0 | 0 else 1 + f(n - 1) in f({})",
+            MAX_CALL_DEPTH - 1
+        );
+        test(&deepest, MAX_CALL_DEPTH as i32 - 1);
+        let e = error("let rec f = fn n => 1 + f(n + 1) in f(0)");
+        assert_eq!(RuntimeErrorKind::RecursionLimit, e.kind);
+        assert_eq!(Span::new(24, 32, 1, 25), e.span);
+    }
+
+    /// A body nesting many expressions around the call takes more stack per
+    /// call than `MAX_CALL_DEPTH` allows for, the interpreter stops before it
+    /// runs out. The VM keeps its frames on the heap, so it is not compared.
+    #[test]
+    fn nested_recursion_limit() {
+        let body = format!("{}f(n - 1){}", "1 + (".repeat(20), ")".repeat(20));
+        let source = format!("let rec f = fn n => if n == 0 | 0 else {} in f(8000)", body);
+        let tokens = Tokenizer::new(&source).run().unwrap();
+        let e = execute(program(&tokens).unwrap(), &mut io::sink()).unwrap_err();
+        assert_eq!(RuntimeErrorKind::RecursionLimit, e.kind);
+    }
+
+    #[test]
+    fn arity_mismatch() {
+        let e = error("let f = fn x, y => x in f(1)");
+        let expected = RuntimeErrorKind::ArityMismatch {
+            expected: 2,
+            found: 1,
+        };
+        assert_eq!(expected, e.kind);
+        assert_eq!(Span::new(24, 28, 1, 25), e.span);
+        assert_eq!(Some(Span::new(8, 20, 1, 9)), e.operand);
+        assert_eq!(
+            "this function takes 2 arguments but 1 argument was supplied",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn call_non_function() {
+        let e = error("1 + 2(3)");
+        let expected = RuntimeErrorKind::TypeMismatch {
+            expected: "function",
+            found: "int",
+        };
+        assert_eq!(expected, e.kind);
+        assert_eq!(Some(Span::new(4, 5, 1, 5)), e.operand);
+    }
+
+    #[test]
+    fn function_operand() {
+        let e = error("(fn => 1) + 1");
+        let expected = RuntimeErrorKind::TypeMismatch {
+            expected: "int",
+            found: "function",
+        };
+        assert_eq!(expected, e.kind);
+    }
+
+    #[test]
+    fn function_identity() {
+        test("let f = fn x => x in f == f", true);
+        test("(fn x => x) == (fn x => x)", false);
+    }
+
+    #[test]
+    fn function_display() {
+        assert_eq!("<fn>", value("fn x => x").to_string());
+        assert_eq!("<fn f>", value("let rec f = fn x => x in f").to_string());
+    }
+
+    /// Runs `input` as a program, returning its value and what it printed
+    fn run_program(input: &str) -> (Result<Value, RuntimeError>, String) {
+        let tokens = Tokenizer::new(input).run().unwrap();
+        let mut out = vec![];
+        let result = run(program(&tokens).unwrap(), &mut out);
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn print() {
+        let (result, out) = run_program("print 1; print true; print fn x => x");
+        assert_eq!(Ok(Value::Unit), result);
+        assert_eq!("1\ntrue\n<fn>\n", out);
+    }
+
+    #[test]
+    fn sequence() {
+        let (result, out) = run_program("let x = 2; let y = x * 3; print y; x + y");
+        assert_eq!(Ok(Value::Int(8)), result);
+        assert_eq!("6\n", out);
+    }
+
+    #[test]
+    fn empty_program() {
+        assert_eq!(Ok(Value::Unit), run_program("").0);
+        assert_eq!(Ok(Value::Unit), run_program("1; let x = 2").0);
+    }
+
+    #[test]
+    fn trailing_semicolon() {
+        assert_eq!(Ok(Value::Int(1)), run_program("1;").0);
+    }
+
+    #[test]
+    fn block() {
+        assert_eq!(Ok(Value::Int(7)), run_program("{ let x = 2; x * 3 } + 1").0);
+        assert_eq!(Ok(Value::Unit), run_program("{ print 1; }").0);
+        assert_eq!(Ok(Value::Unit), run_program("{}").0);
+    }
+
+    #[test]
+    fn block_scope() {
+        let (result, _) = run_program("let x = 1; { let x = 2; print x }; x");
+        assert_eq!(Ok(Value::Int(1)), result);
+        let (result, _) = run_program("{ let y = 2 }; y");
+        let expected = RuntimeErrorKind::UnboundVariable("y".to_string());
+        assert_eq!(expected, result.unwrap_err().kind);
+    }
+
+    #[test]
+    fn print_inside_function() {
+        let (result, out) = run_program(
+            "let rec count = fn n => if n == 0 | 0 else { print n; count(n - 1) }; count(3)",
+        );
+        assert_eq!(Ok(Value::Int(0)), result);
+        assert_eq!("3\n2\n1\n", out);
+    }
+
+    #[test]
+    fn output_before_error() {
+        let (result, out) = run_program("print 1; 1 / 0; print 2");
+        assert!(result.is_err());
+        assert_eq!("1\n", out);
+    }
+
+    #[test]
+    fn assignment() {
+        let (result, _) = run_program("var x = 1; x = x + 1; { x = x * 5 }; x");
+        assert_eq!(Ok(Value::Int(10)), result);
+    }
+
+    #[test]
+    fn sum() {
+        let source = "var i = 0; var sum = 0; while i < 100 { i = i + 1; sum = sum + i }; sum";
+        assert_eq!(Ok(Value::Int(5050)), run_program(source).0);
+    }
+
+    #[test]
+    fn gcd() {
+        let source = "
+            let gcd = fn a, b => {
+                var a = a; var b = b;
+                while b > 0 { let t = b; b = a - a / b * b; a = t };
+                a
+            };
+            gcd(1071, 462)";
+        assert_eq!(Ok(Value::Int(21)), run_program(source).0);
+    }
+
+    #[test]
+    fn primes() {
+        let source = "
+            var n = 2;
+            while n < 30 {
+                var d = 2;
+                var prime = true;
+                while d * d <= n {
+                    if n / d * d == n | { prime = false; break } else {};
+                    d = d + 1
+                };
+                n = n + 1;
+                if prime | {} else { continue };
+                print n - 1
+            }";
+        let (result, out) = run_program(source);
+        assert_eq!(Ok(Value::Unit), result);
+        assert_eq!("2\n3\n5\n7\n11\n13\n17\n19\n23\n29\n", out);
+    }
+
+    #[test]
+    fn break_leaves_innermost_loop() {
+        let source = "
+            var i = 0; var count = 0;
+            while i < 3 {
+                i = i + 1;
+                while true { count = count + 1; break }
+            };
+            count";
+        assert_eq!(Ok(Value::Int(3)), run_program(source).0);
+    }
+
+    #[test]
+    fn closure_shares_variable() {
+        let source = "var n = 0; let inc = fn => { n = n + 1; n }; inc(); inc(); n";
+        assert_eq!(Ok(Value::Int(2)), run_program(source).0);
+    }
+
+    #[test]
+    fn assign_to_immutable() {
+        let e = run_program("let x = 1; x = 2").0.unwrap_err();
+        assert_eq!(RuntimeErrorKind::AssignToImmutable("x".to_string()), e.kind);
+        assert_eq!(Span::new(11, 16, 1, 12), e.span);
+        let diagnostic = Diagnostic::from(&e);
+        assert_eq!(
+            "cannot assign to immutable variable `x`",
+            diagnostic.message
+        );
+        assert_eq!(
+            vec!["declare it with `var x = ...` to make it mutable".to_string()],
+            diagnostic.help
+        );
+        let e = run_program("y = 1").0.unwrap_err();
+        assert_eq!(RuntimeErrorKind::UnboundVariable("y".to_string()), e.kind);
+    }
+
+    #[test]
+    fn break_outside_loop() {
+        let e = run_program("1; break").0.unwrap_err();
+        assert_eq!(RuntimeErrorKind::OutsideLoop("break"), e.kind);
+        assert_eq!("`break` outside of a loop", e.to_string());
+        assert_eq!(Span::new(3, 8, 1, 4), e.span);
+        // a function called in a loop cannot break out of it
+        let e = run_program("let f = fn => { continue }; while true { f() }")
+            .0
+            .unwrap_err();
+        assert_eq!(RuntimeErrorKind::OutsideLoop("continue"), e.kind);
+    }
+
+    #[test]
+    fn while_condition_type() {
+        let e = run_program("while 1 {}").0.unwrap_err();
+        let expected = RuntimeErrorKind::TypeMismatch {
+            expected: "bool",
+            found: "int",
+        };
+        assert_eq!(expected, e.kind);
+        assert_eq!(Some(Span::new(6, 7, 1, 7)), e.operand);
     }
 }
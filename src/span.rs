@@ -0,0 +1,51 @@
+/// A region of the source text.
+///
+/// `start` and `end` are byte offsets (end exclusive), `line` and `col` are the
+/// 1-based position of `start`, counted in characters.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Span {
+    pub start: u32,
+    pub end: u32,
+    pub line: u32,
+    pub col: u32,
+}
+
+impl Span {
+    pub fn new(start: u32, end: u32, line: u32, col: u32) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            col,
+        }
+    }
+
+    /// The span from the start of `self` to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end.max(self.end),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to() {
+        let a = Span::new(2, 3, 1, 3);
+        let b = Span::new(6, 9, 1, 7);
+        assert_eq!(Span::new(2, 9, 1, 3), a.to(b));
+    }
+
+    #[test]
+    fn to_keeps_start_position() {
+        let a = Span::new(0, 4, 1, 1);
+        let b = Span::new(10, 11, 2, 3);
+        let merged = a.to(b);
+        assert_eq!((1, 1), (merged.line, merged.col));
+        assert_eq!((0, 11), (merged.start, merged.end));
+    }
+}
@@ -1,5 +1,8 @@
-use core::panic;
-use std::{iter::Peekable, str::Chars};
+use std::{fmt, iter::Peekable, str::Chars};
+
+use unicode_xid::UnicodeXID;
+
+use crate::{diagnostic::Diagnostic, span::Span};
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum OP {
@@ -16,124 +19,320 @@ pub enum OP {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum Token {
+pub enum TokenKind {
     LITERAL(u32),
     BOOL(bool),
     OPERATOR(OP),
     LPAREN,
     RPAREN,
+    IDENT(String),
     IF,
+    /// `|` or `then`
     THEN,
-    ELSE
+    ELSE,
+    LET,
+    IN,
+    /// `=` in `let x = 1 in x` and `x = x + 1`
+    ASSIGN,
+    FN,
+    REC,
+    /// `=>` between the parameters and the body of a function
+    ARROW,
+    COMMA,
+    LBRACE,
+    RBRACE,
+    SEMICOLON,
+    PRINT,
+    VAR,
+    WHILE,
+    BREAK,
+    CONTINUE,
+}
+
+impl fmt::Display for OP {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            OP::PLUS => "+",
+            OP::MINUS => "-",
+            OP::MULT => "*",
+            OP::POW => "^",
+            OP::DIV => "/",
+            OP::EQUALITY => "==",
+            OP::GREATER => ">",
+            OP::LESS => "<",
+            OP::GEQ => ">=",
+            OP::LEQ => "<=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+/// Prints the token the way it is written in source
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::LITERAL(v) => write!(f, "{}", v),
+            TokenKind::BOOL(b) => write!(f, "{}", b),
+            TokenKind::OPERATOR(op) => write!(f, "{}", op),
+            TokenKind::LPAREN => write!(f, "("),
+            TokenKind::RPAREN => write!(f, ")"),
+            TokenKind::IF => write!(f, "if"),
+            TokenKind::THEN => write!(f, "|"),
+            TokenKind::ELSE => write!(f, "else"),
+            TokenKind::IDENT(name) => write!(f, "{}", name),
+            TokenKind::LET => write!(f, "let"),
+            TokenKind::IN => write!(f, "in"),
+            TokenKind::ASSIGN => write!(f, "="),
+            TokenKind::FN => write!(f, "fn"),
+            TokenKind::REC => write!(f, "rec"),
+            TokenKind::ARROW => write!(f, "=>"),
+            TokenKind::COMMA => write!(f, ","),
+            TokenKind::LBRACE => write!(f, "{{"),
+            TokenKind::RBRACE => write!(f, "}}"),
+            TokenKind::SEMICOLON => write!(f, ";"),
+            TokenKind::PRINT => write!(f, "print"),
+            TokenKind::VAR => write!(f, "var"),
+            TokenKind::WHILE => write!(f, "while"),
+            TokenKind::BREAK => write!(f, "break"),
+            TokenKind::CONTINUE => write!(f, "continue"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn new(kind: TokenKind, span: Span) -> Token {
+        Token { kind, span }
+    }
+}
+
+impl From<TokenKind> for Token {
+    fn from(kind: TokenKind) -> Token {
+        Token::new(kind, Span::default())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LexErrorKind {
+    UnexpectedCharacter(char),
+    /// An integer literal that does not fit in a `u32`
+    LiteralTooLarge,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            LexErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character `{}`", c),
+            LexErrorKind::LiteralTooLarge => write!(f, "integer literal is too large"),
+        }
+    }
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(e: &LexError) -> Diagnostic {
+        let diagnostic = Diagnostic::error(e.to_string());
+        match &e.kind {
+            LexErrorKind::UnexpectedCharacter(_) => diagnostic.primary(e.span, "not valid here"),
+            LexErrorKind::LiteralTooLarge => diagnostic
+                .primary(e.span, "does not fit in 32 bits")
+                .help(format!("the largest integer literal is {}", u32::MAX)),
+        }
+    }
+}
+
+/// Words with a meaning of their own, any other word is an identifier
+const KEYWORDS: [(&str, TokenKind); 14] = [
+    ("true", TokenKind::BOOL(true)),
+    ("false", TokenKind::BOOL(false)),
+    ("if", TokenKind::IF),
+    ("then", TokenKind::THEN),
+    ("else", TokenKind::ELSE),
+    ("let", TokenKind::LET),
+    ("in", TokenKind::IN),
+    ("fn", TokenKind::FN),
+    ("rec", TokenKind::REC),
+    ("print", TokenKind::PRINT),
+    ("var", TokenKind::VAR),
+    ("while", TokenKind::WHILE),
+    ("break", TokenKind::BREAK),
+    ("continue", TokenKind::CONTINUE),
+];
+
+/// Identifiers follow Unicode's XID rules: an `XID_Start` character or `_`,
+/// then `XID_Continue` characters, which include digits, `_` and combining
+/// marks
+fn is_ident_start(c: char) -> bool {
+    c.is_xid_start() || c == '_'
+}
+
+fn is_ident_continue(c: char) -> bool {
+    c.is_xid_continue()
 }
 
 pub struct Tokenizer<'a> {
     source: Peekable<Chars<'a>>,
     tokens: Vec<Token>,
+    errors: Vec<LexError>,
+    offset: u32,
+    line: u32,
+    col: u32,
 }
 
 impl<'a> Tokenizer<'a> {
-    pub fn new(source: &'a String) -> Tokenizer<'a> {
+    pub fn new(source: &'a str) -> Tokenizer<'a> {
         let source = source.chars().peekable();
         Tokenizer {
             source,
             tokens: vec![],
+            errors: vec![],
+            offset: 0,
+            line: 1,
+            col: 1,
         }
     }
 
     fn next(&mut self) -> Option<char> {
-        self.source.next()
+        let c = self.source.next()?;
+        self.offset += c.len_utf8() as u32;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
     }
 
     fn peek(&mut self) -> Option<&char> {
         self.source.peek()
     }
 
-    pub fn run(&mut self) -> &Vec<Token> {
-        match self.next() {
-            Some(c) => {
-                match c {
-                    '+' => self.tokens.push(Token::OPERATOR(OP::PLUS)),
-                    '-' => self.tokens.push(Token::OPERATOR(OP::MINUS)),
-                    '*' => self.tokens.push(Token::OPERATOR(OP::MULT)),
-                    '/' => self.tokens.push(Token::OPERATOR(OP::DIV)),
-                    '^' => self.tokens.push(Token::OPERATOR(OP::POW)),
-                    '|' => self.tokens.push(Token::THEN),
-                    't' => {
-                        let next = vec!['r', 'u', 'e'];
-                        if next.iter().all(|c| *c == self.next().unwrap()) {
-                            self.tokens.push(Token::BOOL(true));
-                        } else {
-                            panic!("Malformed true");
-                        }
-                    }
-                    'f' => {
-                        let next = vec!['a', 'l', 's', 'e'];
-                        if next.iter().all(|c| *c == self.next().unwrap()) {
-                            self.tokens.push(Token::BOOL(false));
-                        } else {
-                            panic!("Malformed false");
-                        }
-                    }
-                    'i' => {
-                        if self.next().unwrap() == 'f' {
-                            self.tokens.push(Token::IF);
-                        } else {
-                            panic!("Malformed if");
-                        }
-                    }
-                    'e' => {
-                        let next = vec!['l', 's', 'e'];
-                        if next.iter().all(|c| *c == self.next().unwrap()) {
-                            self.tokens.push(Token::ELSE);
-                        } else {
-                            panic!("Malformed else");
-                        }
+    /// Consumes the next character if it is `expected`
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(&expected) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The span from `start` to the current position
+    fn span_from(&self, start: Span) -> Span {
+        Span {
+            end: self.offset,
+            ..start
+        }
+    }
+
+    /// Pushes a token spanning from `start` to the current position
+    fn push(&mut self, kind: TokenKind, start: Span) {
+        let span = self.span_from(start);
+        self.tokens.push(Token::new(kind, span));
+    }
+
+    fn error(&mut self, kind: LexErrorKind, start: Span) {
+        let span = self.span_from(start);
+        self.errors.push(LexError { kind, span });
+    }
+
+    /// Finishes the word starting with `first`, a keyword or an identifier
+    fn word(&mut self, first: char, start: Span) {
+        let mut word = String::from(first);
+        while let Some(&c) = self.peek() {
+            if !is_ident_continue(c) {
+                break;
+            }
+            self.next();
+            word.push(c);
+        }
+        let kind = match KEYWORDS.iter().find(|(keyword, _)| *keyword == word) {
+            Some((_, kind)) => kind.clone(),
+            None => TokenKind::IDENT(word),
+        };
+        self.push(kind, start);
+    }
+
+    /// Lexes the whole source. Lexing continues past errors, so all of them
+    /// are reported at once.
+    pub fn run(&mut self) -> Result<Vec<Token>, Vec<LexError>> {
+        loop {
+            let start = Span::new(self.offset, self.offset, self.line, self.col);
+            let c = match self.next() {
+                Some(c) => c,
+                None => break,
+            };
+
+            match c {
+                '+' => self.push(TokenKind::OPERATOR(OP::PLUS), start),
+                '-' => self.push(TokenKind::OPERATOR(OP::MINUS), start),
+                '*' => self.push(TokenKind::OPERATOR(OP::MULT), start),
+                '/' => self.push(TokenKind::OPERATOR(OP::DIV), start),
+                '^' => self.push(TokenKind::OPERATOR(OP::POW), start),
+                '|' => self.push(TokenKind::THEN, start),
+                '>' => {
+                    if self.eat('=') {
+                        self.push(TokenKind::OPERATOR(OP::GEQ), start);
+                    } else {
+                        self.push(TokenKind::OPERATOR(OP::GREATER), start);
                     }
-                    '>' => {
-                        if self.peek().is_some() && *self.peek().unwrap() == '=' {
-                            self.tokens.push(Token::OPERATOR(OP::GEQ));
-                            self.next();
-                        } else {
-                            self.tokens.push(Token::OPERATOR(OP::GREATER));
-                        }
+                }
+                '<' => {
+                    if self.eat('=') {
+                        self.push(TokenKind::OPERATOR(OP::LEQ), start);
+                    } else {
+                        self.push(TokenKind::OPERATOR(OP::LESS), start);
                     }
-                    '<' => {
-                        if self.peek().is_some() && *self.peek().unwrap() == '=' {
-                            self.tokens.push(Token::OPERATOR(OP::LEQ));
-                            self.next();
-                        } else {
-                            self.tokens.push(Token::OPERATOR(OP::LESS));
-                        }
+                }
+                '=' => {
+                    if self.eat('=') {
+                        self.push(TokenKind::OPERATOR(OP::EQUALITY), start);
+                    } else if self.eat('>') {
+                        self.push(TokenKind::ARROW, start);
+                    } else {
+                        self.push(TokenKind::ASSIGN, start);
                     }
-                    '=' => {
-                        if self.next().unwrap() == '=' {
-                            self.tokens.push(Token::OPERATOR(OP::EQUALITY));
-                        } else {
-                            panic!("MALFORMED");
-                        }
+                }
+                '(' => self.push(TokenKind::LPAREN, start),
+                ')' => self.push(TokenKind::RPAREN, start),
+                ',' => self.push(TokenKind::COMMA, start),
+                '{' => self.push(TokenKind::LBRACE, start),
+                '}' => self.push(TokenKind::RBRACE, start),
+                ';' => self.push(TokenKind::SEMICOLON, start),
+                _ if c.is_ascii_digit() => {
+                    let mut value = Some(c.to_digit(10).unwrap());
+                    while let Some(digit) = self.peek().and_then(|c| c.to_digit(10)) {
+                        self.next();
+                        value = value
+                            .and_then(|v| v.checked_mul(10))
+                            .and_then(|v| v.checked_add(digit));
                     }
-                    '(' => self.tokens.push(Token::LPAREN),
-                    ')' => self.tokens.push(Token::RPAREN),
-                    _ if c.is_numeric() => {
-                        let mut digits = vec![c.to_digit(10).unwrap()];
-                        while self.peek().is_some() && self.peek().unwrap().is_numeric() {
-                            digits.push(self.next().unwrap().to_digit(10).unwrap());
-                        }
-                        let q = digits.iter().rev().enumerate().fold(0, |acc, (i, j)| {
-                            let base: i32 = 10;
-                            let power = base.pow(i as u32) as u32;
-                            acc + (power * (*j))
-                        });
-                        self.tokens.push(Token::LITERAL(q));
+                    match value {
+                        Some(v) => self.push(TokenKind::LITERAL(v), start),
+                        None => self.error(LexErrorKind::LiteralTooLarge, start),
                     }
-                    _ if c.is_whitespace() => (),
-                    _ => todo!(),
                 }
-
-                self.run()
+                _ if is_ident_start(c) => self.word(c, start),
+                _ if c.is_whitespace() => (),
+                _ => self.error(LexErrorKind::UnexpectedCharacter(c), start),
             }
-            None => &self.tokens,
+        }
+
+        if self.errors.is_empty() {
+            Ok(std::mem::take(&mut self.tokens))
+        } else {
+            Err(std::mem::take(&mut self.errors))
         }
     }
 }
@@ -142,107 +341,406 @@ impl<'a> Tokenizer<'a> {
 mod tests {
     use super::*;
 
-    fn test(input: &str, expected: Vec<Token>) {
+    fn test(input: &str, expected: Vec<TokenKind>) {
         let test = String::from(input);
         let mut tokenizer = Tokenizer::new(&test);
-        let res = tokenizer.run();
+        let res: Vec<TokenKind> = tokenizer
+            .run()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect();
 
-        assert_eq!(expected, *res);
+        assert_eq!(expected, res);
+    }
+
+    fn spans(input: &str) -> Vec<Span> {
+        let mut tokenizer = Tokenizer::new(input);
+        tokenizer.run().unwrap().iter().map(|t| t.span).collect()
+    }
+
+    fn errors(input: &str) -> Vec<LexError> {
+        let mut tokenizer = Tokenizer::new(input);
+        tokenizer.run().unwrap_err()
     }
 
     #[test]
     fn single_literal() {
-        test("1", vec![Token::LITERAL(1)]);
+        test("1", vec![TokenKind::LITERAL(1)]);
     }
 
     #[test]
     fn single_big_literal() {
-        test("123", vec![Token::LITERAL(123)]);
+        test("123", vec![TokenKind::LITERAL(123)]);
     }
 
     #[test]
     fn unary_minus_number() {
-        let expected = vec![Token::OPERATOR(OP::MINUS), Token::LITERAL(123)];
+        let expected = vec![TokenKind::OPERATOR(OP::MINUS), TokenKind::LITERAL(123)];
         test("-123", expected);
     }
 
     #[test]
     fn addition() {
-        let expected = vec![Token::LITERAL(3), Token::OPERATOR(OP::PLUS), Token::LITERAL(5)];
+        let expected = vec![
+            TokenKind::LITERAL(3),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(5),
+        ];
         test("3+5", expected);
     }
 
     #[test]
     fn mult() {
-        let expected = vec![Token::LITERAL(5), Token::OPERATOR(OP::MULT), Token::LITERAL(123)];
+        let expected = vec![
+            TokenKind::LITERAL(5),
+            TokenKind::OPERATOR(OP::MULT),
+            TokenKind::LITERAL(123),
+        ];
         test("5*123", expected);
     }
 
     #[test]
     fn div() {
-        let expected = vec![Token::LITERAL(10), Token::OPERATOR(OP::DIV), Token::LITERAL(2)];
+        let expected = vec![
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::DIV),
+            TokenKind::LITERAL(2),
+        ];
         test("10/2", expected);
     }
 
     #[test]
     fn whitespace() {
-        let expected = vec![Token::LITERAL(5)];
+        let expected = vec![TokenKind::LITERAL(5)];
         test(" 5", expected);
     }
 
     #[test]
     fn whitespace_and_addition() {
-        let expected = vec![Token::LITERAL(5), Token::OPERATOR(OP::PLUS), Token::LITERAL(10)];
+        let expected = vec![
+            TokenKind::LITERAL(5),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(10),
+        ];
         test(" 5 +     10", expected);
     }
 
     #[test]
     fn equality() {
-        let expected = vec![Token::LITERAL(10), Token::OPERATOR(OP::EQUALITY), Token::LITERAL(10)];
+        let expected = vec![
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::EQUALITY),
+            TokenKind::LITERAL(10),
+        ];
         test("10==10", expected);
     }
 
     #[test]
     fn less() {
-        let expected = vec![Token::LITERAL(10), Token::OPERATOR(OP::LESS), Token::LITERAL(9)];
+        let expected = vec![
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::LESS),
+            TokenKind::LITERAL(9),
+        ];
         test("10 < 9", expected);
     }
 
     #[test]
     fn greater() {
-        let expected = vec![Token::LITERAL(10), Token::OPERATOR(OP::GREATER), Token::LITERAL(11)];
+        let expected = vec![
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::GREATER),
+            TokenKind::LITERAL(11),
+        ];
         test("10 > 11", expected);
     }
 
     #[test]
     fn less_equal() {
-        let expected = vec![Token::LITERAL(10), Token::OPERATOR(OP::LEQ), Token::LITERAL(10)];
+        let expected = vec![
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::LEQ),
+            TokenKind::LITERAL(10),
+        ];
         test("10 <= 10", expected);
     }
 
     #[test]
     fn greater_equal() {
-        let expected = vec![Token::LITERAL(10), Token::OPERATOR(OP::GEQ), Token::LITERAL(11)];
+        let expected = vec![
+            TokenKind::LITERAL(10),
+            TokenKind::OPERATOR(OP::GEQ),
+            TokenKind::LITERAL(11),
+        ];
         test("10 >= 11", expected);
     }
 
     #[test]
     fn parens() {
         let expected = vec![
-            Token::LPAREN,
-            Token::LITERAL(2),
-            Token::OPERATOR(OP::PLUS),
-            Token::LITERAL(5),
-            Token::RPAREN,
+            TokenKind::LPAREN,
+            TokenKind::LITERAL(2),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::LITERAL(5),
+            TokenKind::RPAREN,
         ];
         test("(2 + 5)", expected);
     }
 
     #[test]
     fn parse_true() {
+        let expected = vec![TokenKind::BOOL(true)];
+        test("true", expected);
+    }
+
+    #[test]
+    fn span_single_char() {
+        assert_eq!(vec![Span::new(0, 1, 1, 1)], spans("+"));
+    }
+
+    #[test]
+    fn span_multi_char() {
         let expected = vec![
-            Token::BOOL(true)
+            Span::new(1, 4, 1, 2),
+            Span::new(5, 7, 1, 6),
+            Span::new(8, 12, 1, 9),
         ];
-        test("true", expected);
+        assert_eq!(expected, spans(" 123 <= true"));
+    }
+
+    #[test]
+    fn span_lines() {
+        let expected = vec![
+            Span::new(0, 1, 1, 1),
+            Span::new(2, 3, 1, 3),
+            Span::new(6, 7, 3, 2),
+        ];
+        assert_eq!(expected, spans("1 +\n\n 2"));
+    }
+
+    #[test]
+    fn span_unicode_whitespace() {
+        // U+00A0 is two bytes but one column
+        assert_eq!(vec![Span::new(2, 3, 1, 2)], spans("\u{a0}1"));
+    }
+
+    #[test]
+    fn unexpected_character() {
+        let expected = vec![LexError {
+            kind: LexErrorKind::UnexpectedCharacter('$'),
+            span: Span::new(2, 3, 1, 3),
+        }];
+        assert_eq!(expected, errors("1 $ 2"));
+    }
+
+    #[test]
+    fn keywords() {
+        let expected = vec![
+            TokenKind::LET,
+            TokenKind::IN,
+            TokenKind::IF,
+            TokenKind::ELSE,
+            TokenKind::BOOL(false),
+        ];
+        test("let in if else false", expected);
+    }
+
+    #[test]
+    fn identifiers() {
+        let expected = vec![
+            TokenKind::IDENT("x".into()),
+            TokenKind::IDENT("tru".into()),
+            TokenKind::IDENT("_a1".into()),
+            TokenKind::IDENT("letter".into()),
+        ];
+        test("x tru _a1 letter", expected);
+    }
+
+    #[test]
+    fn let_binding() {
+        let expected = vec![
+            TokenKind::LET,
+            TokenKind::IDENT("x".into()),
+            TokenKind::ASSIGN,
+            TokenKind::LITERAL(1),
+            TokenKind::IN,
+            TokenKind::IDENT("x".into()),
+            TokenKind::OPERATOR(OP::EQUALITY),
+            TokenKind::LITERAL(1),
+        ];
+        test("let x = 1 in x == 1", expected);
+    }
+
+    #[test]
+    fn function() {
+        let expected = vec![
+            TokenKind::LET,
+            TokenKind::REC,
+            TokenKind::IDENT("f".into()),
+            TokenKind::ASSIGN,
+            TokenKind::FN,
+            TokenKind::IDENT("x".into()),
+            TokenKind::COMMA,
+            TokenKind::IDENT("y".into()),
+            TokenKind::ARROW,
+            TokenKind::IDENT("f".into()),
+            TokenKind::LPAREN,
+            TokenKind::IDENT("x".into()),
+            TokenKind::RPAREN,
+        ];
+        test("let rec f = fn x,y=>f(x)", expected);
+    }
+
+    #[test]
+    fn statements() {
+        let expected = vec![
+            TokenKind::PRINT,
+            TokenKind::LBRACE,
+            TokenKind::LITERAL(1),
+            TokenKind::SEMICOLON,
+            TokenKind::RBRACE,
+            TokenKind::SEMICOLON,
+            TokenKind::IDENT("printer".into()),
+        ];
+        test("print {1;};printer", expected);
+    }
+
+    #[test]
+    fn loops() {
+        let expected = vec![
+            TokenKind::VAR,
+            TokenKind::IDENT("i".into()),
+            TokenKind::ASSIGN,
+            TokenKind::LITERAL(0),
+            TokenKind::SEMICOLON,
+            TokenKind::WHILE,
+            TokenKind::BOOL(true),
+            TokenKind::LBRACE,
+            TokenKind::BREAK,
+            TokenKind::SEMICOLON,
+            TokenKind::CONTINUE,
+            TokenKind::RBRACE,
+            TokenKind::IDENT("variable".into()),
+        ];
+        test(
+            "var i = 0; while true { break; continue } variable",
+            expected,
+        );
+    }
+
+    #[test]
+    fn arrow_and_equality() {
+        let expected = vec![
+            TokenKind::ASSIGN,
+            TokenKind::OPERATOR(OP::GEQ),
+            TokenKind::ARROW,
+            TokenKind::OPERATOR(OP::EQUALITY),
+            TokenKind::OPERATOR(OP::GREATER),
+        ];
+        test("= >= => ==>", expected);
+    }
+
+    #[test]
+    fn then_keyword() {
+        let expected = vec![
+            TokenKind::IF,
+            TokenKind::BOOL(true),
+            TokenKind::THEN,
+            TokenKind::LITERAL(1),
+            TokenKind::ELSE,
+            TokenKind::THEN,
+        ];
+        test("if true then 1 else |", expected);
+    }
+
+    #[test]
+    fn keyword_prefixes_are_identifiers() {
+        let expected = vec![
+            TokenKind::IDENT("iffy".into()),
+            TokenKind::IDENT("elsewhere".into()),
+            TokenKind::IDENT("truest".into()),
+            TokenKind::IDENT("thence".into()),
+            TokenKind::IDENT("in_".into()),
+        ];
+        test("iffy elsewhere truest thence in_", expected);
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        let expected = vec![
+            TokenKind::IDENT("größe".into()),
+            TokenKind::OPERATOR(OP::MULT),
+            TokenKind::IDENT("π".into()),
+            TokenKind::OPERATOR(OP::PLUS),
+            TokenKind::IDENT("変数2".into()),
+        ];
+        test("größe*π+変数2", expected);
+    }
+
+    #[test]
+    fn combining_marks() {
+        // the virama `्` and the vowel sign `े` are combining marks
+        let expected = vec![
+            TokenKind::LET,
+            TokenKind::IDENT("नमस्ते".into()),
+            TokenKind::ASSIGN,
+            TokenKind::LITERAL(1),
+            TokenKind::IN,
+            TokenKind::IDENT("नमस्ते".into()),
+        ];
+        test("let नमस्ते = 1 in नमस्ते", expected);
+        // `e` followed by a combining acute accent
+        test("e\u{301}", vec![TokenKind::IDENT("e\u{301}".into())]);
+    }
+
+    #[test]
+    fn unicode_identifier_span() {
+        // `ß` and `ö` are two bytes each, `π` is two bytes
+        let expected = vec![
+            Span::new(0, 7, 1, 1),
+            Span::new(8, 9, 1, 7),
+            Span::new(10, 12, 1, 9),
+        ];
+        assert_eq!(expected, spans("größe + π"));
+    }
+
+    #[test]
+    fn identifier_cannot_start_with_digit() {
+        let expected = vec![TokenKind::LITERAL(2), TokenKind::IDENT("x".into())];
+        test("2x", expected);
+    }
+
+    #[test]
+    fn identifier_span() {
+        assert_eq!(vec![Span::new(1, 6, 1, 2)], spans(" hello"));
+    }
+
+    #[test]
+    fn literal_too_large() {
+        let expected = vec![LexError {
+            kind: LexErrorKind::LiteralTooLarge,
+            span: Span::new(0, 11, 1, 1),
+        }];
+        assert_eq!(expected, errors("99999999999"));
+    }
+
+    #[test]
+    fn max_literal() {
+        test("4294967295", vec![TokenKind::LITERAL(u32::MAX)]);
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let kinds: Vec<LexErrorKind> = errors("1 $ = 2 +\n fals # ifx ~")
+            .into_iter()
+            .map(|e| e.kind)
+            .collect();
+        let expected = vec![
+            LexErrorKind::UnexpectedCharacter('$'),
+            LexErrorKind::UnexpectedCharacter('#'),
+            LexErrorKind::UnexpectedCharacter('~'),
+        ];
+        assert_eq!(expected, kinds);
     }
 }
@@ -0,0 +1,957 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    diagnostic::Diagnostic,
+    pratt::{E, S},
+    resolver::arguments,
+    span::Span,
+    tokenizer::OP,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Type {
+    Int,
+    Bool,
+    Unit,
+    Function(Vec<Type>, Box<Type>),
+    /// A type variable, `'a`, standing for a type not inferred yet or for
+    /// any type in a generalized type
+    Var(usize),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::Unit => write!(f, "unit"),
+            Type::Function(params, result) => {
+                write!(f, "fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ") -> {}", result)
+            }
+            Type::Var(v) => match v / 26 {
+                0 => write!(f, "'{}", (b'a' + (v % 26) as u8) as char),
+                n => write!(f, "'{}{}", (b'a' + (v % 26) as u8) as char, n),
+            },
+        }
+    }
+}
+
+impl Type {
+    /// Renames the type variables in `types` to `'a`, `'b`, ... in the order
+    /// they appear, so that types print the same however they were inferred
+    fn normalize(types: &[&Type]) -> Vec<Type> {
+        fn rename(t: &Type, names: &mut Vec<usize>) -> Type {
+            match t {
+                Type::Var(v) => match names.iter().position(|n| n == v) {
+                    Some(i) => Type::Var(i),
+                    None => {
+                        names.push(*v);
+                        Type::Var(names.len() - 1)
+                    }
+                },
+                Type::Function(params, result) => {
+                    let params = params.iter().map(|p| rename(p, names)).collect();
+                    Type::Function(params, Box::new(rename(result, names)))
+                }
+                t => t.clone(),
+            }
+        }
+        let mut names = vec![];
+        types.iter().map(|t| rename(t, &mut names)).collect()
+    }
+
+    fn variables(&self, out: &mut Vec<usize>) {
+        match self {
+            Type::Var(v) if !out.contains(v) => out.push(*v),
+            Type::Function(params, result) => {
+                params.iter().for_each(|p| p.variables(out));
+                result.variables(out);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// A type generalized over the variables in `vars`, each use of a
+/// let-bound name gets fresh variables in their place
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<usize>,
+    t: Type,
+}
+
+impl Scheme {
+    fn mono(t: Type) -> Scheme {
+        Scheme { vars: vec![], t }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TypeErrorKind {
+    Mismatch {
+        expected: Type,
+        found: Type,
+    },
+    /// The then-branch of an `if` has type `then`, the else-branch `elze`
+    BranchMismatch {
+        then: Type,
+        elze: Type,
+    },
+    UnboundVariable(String),
+    /// A type that would have to contain itself, `var` in terms of `t`
+    InfiniteType {
+        var: Type,
+        t: Type,
+    },
+    /// A call of something that is not a function
+    NotAFunction(Type),
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// An assignment to a variable bound by `let`
+    AssignToImmutable(String),
+    /// A `break` or `continue` that is not inside a loop of the function it
+    /// is in
+    OutsideLoop(&'static str),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    /// The span of the offending subexpression
+    pub span: Span,
+    /// Where the expected type comes from, e.g. the left operand of `==`
+    pub origin: Option<Span>,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            TypeErrorKind::Mismatch { expected, found } => write!(
+                f,
+                "mismatched types: expected `{}`, found `{}`",
+                expected, found
+            ),
+            TypeErrorKind::BranchMismatch { .. } => {
+                write!(f, "`if` and `else` have incompatible types")
+            }
+            TypeErrorKind::UnboundVariable(name) => {
+                write!(f, "cannot find variable `{}` in this scope", name)
+            }
+            TypeErrorKind::InfiniteType { var, t } => {
+                write!(f, "cannot construct the infinite type `{} = {}`", var, t)
+            }
+            TypeErrorKind::NotAFunction(found) => {
+                write!(f, "expected function, found `{}`", found)
+            }
+            TypeErrorKind::ArityMismatch { expected, found } => write!(
+                f,
+                "this function takes {} but {} {} supplied",
+                arguments(*expected),
+                arguments(*found),
+                if *found == 1 { "was" } else { "were" }
+            ),
+            TypeErrorKind::AssignToImmutable(name) => {
+                write!(f, "cannot assign to immutable variable `{}`", name)
+            }
+            TypeErrorKind::OutsideLoop(keyword) => write!(f, "`{}` outside of a loop", keyword),
+        }
+    }
+}
+
+impl From<&TypeError> for Diagnostic {
+    fn from(e: &TypeError) -> Diagnostic {
+        let diagnostic = Diagnostic::error(e.to_string());
+        let diagnostic = match &e.kind {
+            TypeErrorKind::Mismatch { expected, found }
+            | TypeErrorKind::BranchMismatch {
+                then: expected,
+                elze: found,
+            } => diagnostic.primary(
+                e.span,
+                format!("expected `{}`, found `{}`", expected, found),
+            ),
+            TypeErrorKind::UnboundVariable(_) => {
+                diagnostic.primary(e.span, "not found in this scope")
+            }
+            TypeErrorKind::InfiniteType { .. } => {
+                diagnostic.primary(e.span, "the type of this contains itself")
+            }
+            TypeErrorKind::NotAFunction(_) => diagnostic.primary(e.span, "not a function"),
+            TypeErrorKind::ArityMismatch { expected, .. } => {
+                diagnostic.primary(e.span, format!("expected {}", arguments(*expected)))
+            }
+            TypeErrorKind::AssignToImmutable(name) => {
+                diagnostic.primary(e.span, "cannot assign").help(format!(
+                    "declare it with `var {} = ...` to make it mutable",
+                    name
+                ))
+            }
+            TypeErrorKind::OutsideLoop(keyword) => {
+                diagnostic.primary(e.span, format!("cannot `{}` outside of a loop", keyword))
+            }
+        };
+        match e.origin {
+            Some(origin) => diagnostic.secondary(origin, "expected because of this"),
+            None => diagnostic,
+        }
+    }
+}
+
+impl TypeError {
+    fn new(kind: TypeErrorKind, span: Span) -> TypeError {
+        TypeError {
+            kind,
+            span,
+            origin: None,
+        }
+    }
+
+    fn origin(self, origin: Option<Span>) -> TypeError {
+        TypeError { origin, ..self }
+    }
+}
+
+/// The inferred types of a program
+#[derive(Debug)]
+pub struct Types {
+    /// The type of the program as a sequence of statements
+    pub program: Type,
+    /// The type of every expression by its address, nodes can share a span
+    nodes: HashMap<*const E, Type>,
+}
+
+impl Types {
+    /// The type of `e`, a node of the program that was checked
+    pub fn get(&self, e: &E) -> Option<&Type> {
+        self.nodes.get(&(e as *const E))
+    }
+}
+
+/// Infers the type of every expression in `program` before it runs
+pub fn check(program: &[S]) -> Result<Types, TypeError> {
+    let mut checker = Checker {
+        bindings: vec![],
+        loops: 0,
+        substitution: vec![],
+        origins: vec![],
+        nodes: vec![],
+    };
+    let t = checker.statements(program)?;
+    Ok(Types {
+        program: checker.finish(&t),
+        nodes: checker
+            .nodes
+            .iter()
+            .map(|(e, t)| (*e, checker.finish(t)))
+            .collect(),
+    })
+}
+
+/// Why two types do not unify
+enum Conflict {
+    Mismatch,
+    /// The variable occurs in the type it would be bound to
+    Occurs(usize, Type),
+}
+
+/// Algorithm W: every expression gets a type with fresh variables for what
+/// is not known yet, which unification binds as the uses of the expression
+/// are checked
+struct Checker {
+    /// The variables in scope with their types, and whether they were
+    /// declared with `var`
+    bindings: Vec<(String, Scheme, bool)>,
+    /// The number of loops around the node being checked, inside the
+    /// innermost function
+    loops: usize,
+    /// What each type variable is bound to, `None` while it is free
+    substitution: Vec<Option<Type>>,
+    /// The expression whose checking bound each type variable, which is
+    /// where a type expected because of the variable comes from
+    origins: Vec<Option<Span>>,
+    /// Every expression checked so far with its type
+    nodes: Vec<(*const E, Type)>,
+}
+
+/// Whether `e` is a syntactic value. Only the types of values are
+/// generalized, a block could hide a `var` shared by the functions it
+/// returns, which must then all agree on its type.
+fn is_value(e: &E) -> bool {
+    match e {
+        E::LITERAL(..) | E::BOOL(..) | E::VAR(..) | E::FN(..) => true,
+        E::PAREN(e, _) => is_value(e),
+        _ => false,
+    }
+}
+
+impl Checker {
+    fn fresh(&mut self) -> Type {
+        self.substitution.push(None);
+        self.origins.push(None);
+        Type::Var(self.substitution.len() - 1)
+    }
+
+    /// `t` with its bound variables replaced, recursively
+    fn apply(&self, t: &Type) -> Type {
+        match t {
+            Type::Var(v) => match &self.substitution[*v] {
+                Some(t) => self.apply(t),
+                None => t.clone(),
+            },
+            Type::Function(params, result) => Type::Function(
+                params.iter().map(|p| self.apply(p)).collect(),
+                Box::new(self.apply(result)),
+            ),
+            t => t.clone(),
+        }
+    }
+
+    /// `t` with the variables it is bound through replaced, but not those
+    /// inside it, which keep where they were bound
+    fn shallow(&self, t: &Type) -> Type {
+        match t {
+            Type::Var(v) => match &self.substitution[*v] {
+                Some(t) => self.shallow(t),
+                None => t.clone(),
+            },
+            t => t.clone(),
+        }
+    }
+
+    /// `t` as it is shown to the user
+    fn finish(&self, t: &Type) -> Type {
+        Type::normalize(&[&self.apply(t)]).remove(0)
+    }
+
+    fn bind(&mut self, v: usize, t: Type, span: Span) {
+        self.substitution[v] = Some(t);
+        self.origins[v] = Some(span);
+    }
+
+    /// Makes `a` and `b` the same type while checking the expression at
+    /// `span`
+    fn unify(&mut self, a: &Type, b: &Type, span: Span) -> Result<(), Conflict> {
+        match (self.apply(a), self.apply(b)) {
+            (Type::Var(x), Type::Var(y)) if x == y => Ok(()),
+            (Type::Var(v), t) | (t, Type::Var(v)) => {
+                let mut vars = vec![];
+                t.variables(&mut vars);
+                if vars.contains(&v) {
+                    return Err(Conflict::Occurs(v, t));
+                }
+                self.bind(v, t, span);
+                Ok(())
+            }
+            (Type::Function(p, r), Type::Function(q, s)) if p.len() == q.len() => {
+                for (p, q) in p.iter().zip(&q) {
+                    self.unify(p, q, span)?;
+                }
+                self.unify(&r, &s, span)
+            }
+            (a, b) if a == b => Ok(()),
+            _ => Err(Conflict::Mismatch),
+        }
+    }
+
+    /// Where the type variable that `t` is, if it is one, was bound
+    fn origin(&self, t: &Type) -> Option<Span> {
+        match t {
+            Type::Var(v) => self.origins[*v].or_else(|| {
+                let bound = self.substitution[*v].as_ref()?;
+                self.origin(bound)
+            }),
+            _ => None,
+        }
+    }
+
+    /// Two types as they are shown together in an error
+    fn pair(&self, a: &Type, b: &Type) -> (Type, Type) {
+        let mut types = Type::normalize(&[&self.apply(a), &self.apply(b)]).into_iter();
+        (types.next().unwrap(), types.next().unwrap())
+    }
+
+    fn conflict(&self, conflict: Conflict, expected: &Type, found: &Type, span: Span) -> TypeError {
+        let kind = match conflict {
+            Conflict::Mismatch => {
+                let (expected, found) = self.pair(expected, found);
+                TypeErrorKind::Mismatch { expected, found }
+            }
+            Conflict::Occurs(v, t) => {
+                let (var, t) = self.pair(&Type::Var(v), &t);
+                TypeErrorKind::InfiniteType { var, t }
+            }
+        };
+        TypeError::new(kind, span)
+    }
+
+    /// Generalizes `t` over the variables that are not free in the
+    /// bindings in scope
+    fn generalize(&self, t: &Type) -> Scheme {
+        let mut env = vec![];
+        for (_, scheme, _) in &self.bindings {
+            let mut vars = vec![];
+            self.apply(&scheme.t).variables(&mut vars);
+            env.extend(vars.into_iter().filter(|v| !scheme.vars.contains(v)));
+        }
+        let mut vars = vec![];
+        self.apply(t).variables(&mut vars);
+        vars.retain(|v| !env.contains(v));
+        Scheme { vars, t: t.clone() }
+    }
+
+    /// The type of a use of a binding with `scheme`
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let fresh: Vec<(usize, Type)> = scheme.vars.iter().map(|v| (*v, self.fresh())).collect();
+        self.substitute(&scheme.t, &fresh)
+    }
+
+    /// `t` with the variables in `fresh` replaced. Bound variables are
+    /// kept for where they were bound, unless the replaced ones are in
+    /// what they are bound to.
+    fn substitute(&self, t: &Type, fresh: &[(usize, Type)]) -> Type {
+        match t {
+            Type::Var(v) => match (fresh.iter().find(|(u, _)| u == v), &self.substitution[*v]) {
+                (Some((_, t)), _) => t.clone(),
+                (None, Some(bound)) => {
+                    let mut vars = vec![];
+                    self.apply(bound).variables(&mut vars);
+                    if fresh.iter().any(|(u, _)| vars.contains(u)) {
+                        self.substitute(bound, fresh)
+                    } else {
+                        t.clone()
+                    }
+                }
+                (None, None) => t.clone(),
+            },
+            Type::Function(params, result) => Type::Function(
+                params.iter().map(|p| self.substitute(p, fresh)).collect(),
+                Box::new(self.substitute(result, fresh)),
+            ),
+            t => t.clone(),
+        }
+    }
+
+    /// The scheme a `let` binds `value` of type `t` with
+    fn binding(&self, value: &E, t: &Type) -> Scheme {
+        if is_value(value) {
+            self.generalize(t)
+        } else {
+            Scheme::mono(t.clone())
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&(String, Scheme, bool)> {
+        self.bindings.iter().rev().find(|(n, _, _)| n == name)
+    }
+
+    /// Checks the type of `e` is `expected`, which comes from `origin`, or
+    /// from where the type variable `expected` was bound
+    fn expect(&mut self, expected: &Type, e: &E, origin: Option<Span>) -> Result<(), TypeError> {
+        let found = self.expression(e)?;
+        self.unify(expected, &found, e.span()).map_err(|conflict| {
+            let origin = origin.or_else(|| self.origin(expected));
+            self.conflict(conflict, expected, &found, e.span())
+                .origin(origin)
+        })
+    }
+
+    /// Statements in order, their bindings go out of scope at the end
+    fn statements(&mut self, statements: &[S]) -> Result<Type, TypeError> {
+        let scope = self.bindings.len();
+        let result = statements
+            .iter()
+            .try_fold(Type::Unit, |_, statement| self.statement(statement));
+        self.bindings.truncate(scope);
+        result
+    }
+
+    fn statement(&mut self, statement: &S) -> Result<Type, TypeError> {
+        match statement {
+            S::EXPR(e) => self.expression(e),
+            S::LET(name, value, _) | S::LETREC(name, value, _) => {
+                let t = match statement {
+                    S::LETREC(..) => self.recursive(name, value)?,
+                    _ => self.expression(value)?,
+                };
+                let scheme = self.binding(value, &t);
+                self.bindings.push((name.clone(), scheme, false));
+                Ok(Type::Unit)
+            }
+            S::PRINT(e, _) => {
+                self.expression(e)?;
+                Ok(Type::Unit)
+            }
+            S::VAR(name, value, _) => {
+                let t = self.expression(value)?;
+                self.bindings.push((name.clone(), Scheme::mono(t), true));
+                Ok(Type::Unit)
+            }
+            S::ASSIGN(name, value, span) => {
+                let declared = match self.lookup(name) {
+                    Some((_, scheme, true)) => scheme.t.clone(),
+                    Some((_, _, false)) => {
+                        let kind = TypeErrorKind::AssignToImmutable(name.clone());
+                        return Err(TypeError::new(kind, *span));
+                    }
+                    None => {
+                        let kind = TypeErrorKind::UnboundVariable(name.clone());
+                        return Err(TypeError::new(kind, *span));
+                    }
+                };
+                self.expect(&declared, value, None)?;
+                Ok(Type::Unit)
+            }
+            S::WHILE(cond, body, _) => {
+                self.expect(&Type::Bool, cond, None)?;
+                self.loops += 1;
+                let result = self.statements(body);
+                self.loops -= 1;
+                result.map(|_| Type::Unit)
+            }
+            S::BREAK(span) => self.jump("break", *span),
+            S::CONTINUE(span) => self.jump("continue", *span),
+        }
+    }
+
+    fn jump(&self, keyword: &'static str, span: Span) -> Result<Type, TypeError> {
+        match self.loops {
+            0 => Err(TypeError::new(TypeErrorKind::OutsideLoop(keyword), span)),
+            _ => Ok(Type::Unit),
+        }
+    }
+
+    /// The value of `let rec name = value`, which sees itself with the type
+    /// it is being inferred to have
+    fn recursive(&mut self, name: &str, value: &E) -> Result<Type, TypeError> {
+        let t = self.fresh();
+        self.bindings
+            .push((name.to_string(), Scheme::mono(t.clone()), false));
+        let result = self.expression(value);
+        self.bindings.pop();
+        let found = result?;
+        self.unify(&t, &found, value.span()).map_err(|conflict| {
+            self.conflict(conflict, &t, &found, value.span())
+                .origin(self.origin(&t))
+        })?;
+        Ok(found)
+    }
+
+    /// Infers the type of `e` and records it
+    fn expression(&mut self, e: &E) -> Result<Type, TypeError> {
+        let t = self.infer(e)?;
+        self.nodes.push((e, t.clone()));
+        Ok(t)
+    }
+
+    fn infer(&mut self, e: &E) -> Result<Type, TypeError> {
+        match e {
+            E::LITERAL(..) => Ok(Type::Int),
+            E::BOOL(..) => Ok(Type::Bool),
+            E::PAREN(e, _) => self.expression(e),
+            E::UNARY(_, e, _) => self.expect(&Type::Int, e, None).map(|_| Type::Int),
+            E::BINARY(left, op, right, _) => match op {
+                OP::EQUALITY => {
+                    let t = self.expression(left)?;
+                    self.expect(&t, right, Some(left.span()))?;
+                    Ok(Type::Bool)
+                }
+                _ => {
+                    self.expect(&Type::Int, left, None)?;
+                    self.expect(&Type::Int, right, None)?;
+                    match op {
+                        OP::GREATER | OP::LESS | OP::GEQ | OP::LEQ => Ok(Type::Bool),
+                        _ => Ok(Type::Int),
+                    }
+                }
+            },
+            E::IF(cond, then, elze, _) => {
+                self.expect(&Type::Bool, cond, None)?;
+                let (a, b) = (self.expression(then)?, self.expression(elze)?);
+                self.unify(&a, &b, elze.span()).map_err(|_| {
+                    let (a, b) = self.pair(&a, &b);
+                    let kind = TypeErrorKind::BranchMismatch { then: a, elze: b };
+                    TypeError::new(kind, elze.span()).origin(Some(then.span()))
+                })?;
+                Ok(a)
+            }
+            E::LET(name, value, body, _) | E::LETREC(name, value, body, _) => {
+                let t = match e {
+                    E::LETREC(..) => self.recursive(name, value)?,
+                    _ => self.expression(value)?,
+                };
+                let scheme = self.binding(value, &t);
+                self.bindings.push((name.clone(), scheme, false));
+                let result = self.expression(body);
+                self.bindings.pop();
+                result
+            }
+            E::VAR(name, span) => match self.lookup(name) {
+                Some((_, scheme, _)) => {
+                    let scheme = scheme.clone();
+                    Ok(self.instantiate(&scheme))
+                }
+                None => {
+                    let kind = TypeErrorKind::UnboundVariable(name.clone());
+                    Err(TypeError::new(kind, *span))
+                }
+            },
+            E::FN(params, body, _) => {
+                // loops around the function do not reach into its body
+                let (scope, loops) = (self.bindings.len(), self.loops);
+                self.loops = 0;
+                let params: Vec<Type> = params
+                    .iter()
+                    .map(|param| {
+                        let t = self.fresh();
+                        self.bindings
+                            .push((param.clone(), Scheme::mono(t.clone()), false));
+                        t
+                    })
+                    .collect();
+                let result = self.expression(body);
+                self.bindings.truncate(scope);
+                self.loops = loops;
+                Ok(Type::Function(params, Box::new(result?)))
+            }
+            E::CALL(callee, args, span) => {
+                let t = self.expression(callee)?;
+                let (params, result) = match self.shallow(&t) {
+                    Type::Function(params, result) => (params, *result),
+                    // a function not known yet takes what it is called with
+                    Type::Var(v) => {
+                        let params: Vec<Type> = args.iter().map(|_| self.fresh()).collect();
+                        let result = self.fresh();
+                        let f = Type::Function(params.clone(), Box::new(result.clone()));
+                        self.bind(v, f, callee.span());
+                        (params, result)
+                    }
+                    t => {
+                        let kind = TypeErrorKind::NotAFunction(self.finish(&t));
+                        return Err(TypeError::new(kind, callee.span()));
+                    }
+                };
+                if params.len() != args.len() {
+                    let kind = TypeErrorKind::ArityMismatch {
+                        expected: params.len(),
+                        found: args.len(),
+                    };
+                    return Err(TypeError::new(kind, *span));
+                }
+                for (param, arg) in params.iter().zip(args) {
+                    self.expect(param, arg, None)?;
+                }
+                Ok(result)
+            }
+            E::BLOCK(statements, _) => self.statements(statements),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pratt::program, tokenizer::Tokenizer};
+
+    use super::*;
+
+    fn check_source(source: &str) -> Result<Type, TypeError> {
+        let tokens = Tokenizer::new(source).run().unwrap();
+        check(&program(&tokens).unwrap()).map(|types| types.program)
+    }
+
+    /// The type of the program as it is printed
+    fn show(source: &str) -> String {
+        check_source(source).unwrap().to_string()
+    }
+
+    fn test(source: &str, expected: Type) {
+        assert_eq!(Ok(expected), check_source(source), "{}", source);
+    }
+
+    fn error(source: &str) -> TypeError {
+        check_source(source).unwrap_err()
+    }
+
+    fn mismatch(expected: Type, found: Type) -> TypeErrorKind {
+        TypeErrorKind::Mismatch { expected, found }
+    }
+
+    /// Synthesized nodes have the default span, each still gets its type
+    #[test]
+    fn shared_spans() {
+        let span = Span::default();
+        let program = vec![
+            S::LET("a".into(), E::LITERAL(1, span), span),
+            S::LET("b".into(), E::BOOL(true, span), span),
+        ];
+        let types = check(&program).unwrap();
+        let [S::LET(_, a, _), S::LET(_, b, _)] = &program[..] else {
+            unreachable!()
+        };
+        assert_eq!(Some(&Type::Int), types.get(a));
+        assert_eq!(Some(&Type::Bool), types.get(b));
+        assert_eq!(None, types.get(&E::LITERAL(1, span)));
+    }
+
+    #[test]
+    fn literals() {
+        test("1", Type::Int);
+        test("true", Type::Bool);
+        test("", Type::Unit);
+        test("print 1", Type::Unit);
+    }
+
+    #[test]
+    fn operators() {
+        test("-(1 + 2 * 3 / 4 ^ 5)", Type::Int);
+        test("1 < 2", Type::Bool);
+        test("1 == 2", Type::Bool);
+        test("true == false", Type::Bool);
+    }
+
+    #[test]
+    fn operand_mismatch() {
+        let e = error("1 + 2 * true");
+        assert_eq!(mismatch(Type::Int, Type::Bool), e.kind);
+        assert_eq!(Span::new(8, 12, 1, 9), e.span);
+        let e = error("-(1 < 2)");
+        assert_eq!(mismatch(Type::Int, Type::Bool), e.kind);
+        assert_eq!(Span::new(1, 8, 1, 2), e.span);
+        let e = error("(1 == 2) > 0");
+        assert_eq!(Span::new(0, 8, 1, 1), e.span);
+    }
+
+    #[test]
+    fn equality_mismatch() {
+        let e = error("1 == true");
+        assert_eq!(mismatch(Type::Int, Type::Bool), e.kind);
+        assert_eq!(Span::new(5, 9, 1, 6), e.span);
+        assert_eq!(Some(Span::new(0, 1, 1, 1)), e.origin);
+    }
+
+    #[test]
+    fn if_expression() {
+        test("if true | 1 else 2", Type::Int);
+        let e = error("if 1 | true else 3");
+        assert_eq!(mismatch(Type::Bool, Type::Int), e.kind);
+        assert_eq!(Span::new(3, 4, 1, 4), e.span);
+    }
+
+    #[test]
+    fn branch_mismatch() {
+        let e = error("if true | 1 else false");
+        let expected = TypeErrorKind::BranchMismatch {
+            then: Type::Int,
+            elze: Type::Bool,
+        };
+        assert_eq!(expected, e.kind);
+        assert_eq!(Span::new(17, 22, 1, 18), e.span);
+        assert_eq!(Some(Span::new(10, 11, 1, 11)), e.origin);
+        let diagnostic = Diagnostic::from(&e);
+        assert_eq!(
+            "`if` and `else` have incompatible types",
+            diagnostic.message
+        );
+        assert_eq!("expected `int`, found `bool`", diagnostic.labels[0].message);
+        assert_eq!("expected because of this", diagnostic.labels[1].message);
+    }
+
+    #[test]
+    fn let_bindings() {
+        test("let x = 1 in x + 1", Type::Int);
+        test("let x = 1; let x = x == 1; x", Type::Bool);
+        let e = error("{ let y = 1 }; y");
+        assert_eq!(TypeErrorKind::UnboundVariable("y".to_string()), e.kind);
+    }
+
+    #[test]
+    fn functions() {
+        let int = || Box::new(Type::Int);
+        test("fn x => x + 1", Type::Function(vec![Type::Int], int()));
+        test("(fn x, y => x)(1, true)", Type::Int);
+        test("let f = fn => 1 in f() + 1", Type::Int);
+        test(
+            "let rec fact = fn n => if n == 0 | 1 else (n * fact(n - 1)) in fact",
+            Type::Function(vec![Type::Int], int()),
+        );
+    }
+
+    #[test]
+    fn function_errors() {
+        let e = error("let f = fn x => x in f(1, 2)");
+        let expected = TypeErrorKind::ArityMismatch {
+            expected: 1,
+            found: 2,
+        };
+        assert_eq!(expected, e.kind);
+        // the call decides the type `f` is checked against
+        let e = error("let rec f = fn x => f() in 1");
+        assert_eq!(Span::new(12, 23, 1, 13), e.span);
+        assert_eq!(Some(Span::new(20, 21, 1, 21)), e.origin);
+        let e = error("(fn => 1)() + 1 == false");
+        assert_eq!(mismatch(Type::Int, Type::Bool), e.kind);
+        let e = error("(fn => 1)()(2)");
+        assert_eq!(TypeErrorKind::NotAFunction(Type::Int), e.kind);
+        assert_eq!("expected function, found `int`", e.to_string());
+    }
+
+    #[test]
+    fn variables() {
+        test("var i = 0; while i < 10 { i = i + 1 }; i", Type::Int);
+        let e = error("var i = 0; i = true");
+        assert_eq!(mismatch(Type::Int, Type::Bool), e.kind);
+        assert_eq!(Span::new(15, 19, 1, 16), e.span);
+        let e = error("let i = 0; i = 1");
+        assert_eq!(TypeErrorKind::AssignToImmutable("i".to_string()), e.kind);
+        let e = error("while 1 {}");
+        assert_eq!(mismatch(Type::Bool, Type::Int), e.kind);
+    }
+
+    #[test]
+    fn loops() {
+        test(
+            "while true { if true | { break } else { continue } }",
+            Type::Unit,
+        );
+        let e = error("1; continue");
+        assert_eq!(TypeErrorKind::OutsideLoop("continue"), e.kind);
+        let e = error("while true { let f = fn => { break } }");
+        assert_eq!(TypeErrorKind::OutsideLoop("break"), e.kind);
+        assert_eq!(Span::new(29, 34, 1, 30), e.span);
+    }
+
+    #[test]
+    fn function_type_display() {
+        let t = Type::Function(
+            vec![Type::Int, Type::Var(0)],
+            Box::new(Type::Function(vec![], Box::new(Type::Unit))),
+        );
+        assert_eq!("fn(int, 'a) -> fn() -> unit", t.to_string());
+        assert_eq!("'z", Type::Var(25).to_string());
+        assert_eq!("'a1", Type::Var(26).to_string());
+    }
+
+    #[test]
+    fn most_general_types() {
+        assert_eq!("fn('a) -> 'a", show("fn x => x"));
+        assert_eq!("fn('a, 'b) -> 'a", show("fn x, y => x"));
+        assert_eq!(
+            "fn(fn('a) -> 'b, fn('c) -> 'a) -> fn('c) -> 'b",
+            show("fn f, g => fn x => f(g(x))")
+        );
+        assert_eq!("fn(fn(int) -> 'a) -> 'a", show("fn f => f(1)"));
+        assert_eq!("fn('a, 'a) -> bool", show("fn x, y => x == y"));
+    }
+
+    #[test]
+    fn let_polymorphism() {
+        test(
+            "let id = fn x => x in if id(true) | id(1) else 2",
+            Type::Int,
+        );
+        test("let id = fn x => x; id(id)(true)", Type::Bool);
+        test(
+            "let rec map = fn f, n => if n == 0 | 0 else f(n) in map(fn x => x, 1)",
+            Type::Int,
+        );
+    }
+
+    #[test]
+    fn lambda_bound_names_are_monomorphic() {
+        let e = error("(fn id => if id(true) | id(1) else 2)(fn x => x)");
+        assert_eq!(mismatch(Type::Bool, Type::Int), e.kind);
+        assert_eq!(Span::new(27, 28, 1, 28), e.span);
+        // `true` bound the parameter type of `id`
+        assert_eq!(Some(Span::new(16, 20, 1, 17)), e.origin);
+    }
+
+    #[test]
+    fn value_restriction() {
+        // the function shares `last`, so its type cannot be generalized
+        let source = "
+            let remember = { var last = fn x => x; fn f => { let old = last; last = f; old } };
+            remember(fn x => x + 1)(true)";
+        let e = error(source);
+        assert_eq!(mismatch(Type::Int, Type::Bool), e.kind);
+        test("var id = fn x => x; id(1)", Type::Int);
+        let e = error("var id = fn x => x; id(1); id(true)");
+        assert_eq!(mismatch(Type::Int, Type::Bool), e.kind);
+    }
+
+    #[test]
+    fn mismatch_origin() {
+        let e = error("let f = fn x => x + 1 in f(true)");
+        assert_eq!(mismatch(Type::Int, Type::Bool), e.kind);
+        assert_eq!(Span::new(27, 31, 1, 28), e.span);
+        // the use of `x` as an operand made it an `int`
+        assert_eq!(Some(Span::new(16, 17, 1, 17)), e.origin);
+        let diagnostic = Diagnostic::from(&e);
+        assert_eq!("expected `int`, found `bool`", diagnostic.labels[0].message);
+        assert_eq!("expected because of this", diagnostic.labels[1].message);
+    }
+
+    #[test]
+    fn function_mismatch() {
+        let e = error("let apply = fn f => f(1) in apply(fn b => if b | 1 else 2)");
+        assert_eq!(
+            mismatch(
+                Type::Function(vec![Type::Int], Box::new(Type::Var(0))),
+                Type::Function(vec![Type::Bool], Box::new(Type::Int))
+            ),
+            e.kind
+        );
+        assert_eq!(
+            "mismatched types: expected `fn(int) -> 'a`, found `fn(bool) -> int`",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn occurs_check() {
+        let e = error("fn x => x(x)");
+        assert_eq!(
+            "cannot construct the infinite type `'a = fn('a) -> 'b`",
+            e.to_string()
+        );
+        assert_eq!(Span::new(10, 11, 1, 11), e.span);
+        let e = error("let rec f = fn x => f in 1");
+        let expected = TypeErrorKind::InfiniteType {
+            var: Type::Var(0),
+            t: Type::Function(vec![Type::Var(1)], Box::new(Type::Var(0))),
+        };
+        assert_eq!(expected, e.kind);
+        assert_eq!(Span::new(12, 21, 1, 13), e.span);
+    }
+
+    #[test]
+    fn node_types() {
+        let tokens = Tokenizer::new("let id = fn x => x; id(1) == 1")
+            .run()
+            .unwrap();
+        let program = program(&tokens).unwrap();
+        let types = check(&program).unwrap();
+        let [S::LET(_, id, _), S::EXPR(comparison)] = &program[..] else {
+            unreachable!()
+        };
+        let E::BINARY(call, _, _, _) = comparison else {
+            unreachable!()
+        };
+        let E::CALL(callee, _, _) = &**call else {
+            unreachable!()
+        };
+        let show = |e: &E| types.get(e).map(|t| t.to_string());
+        assert_eq!(Some("fn('a) -> 'a".to_string()), show(id));
+        // each use of a let-bound name has its own instance
+        assert_eq!(Some("fn(int) -> int".to_string()), show(callee));
+        assert_eq!(Some("int".to_string()), show(call));
+        assert_eq!(Some("bool".to_string()), show(comparison));
+    }
+}
@@ -0,0 +1,730 @@
+use std::fmt;
+
+use crate::{
+    codegen::CodegenError,
+    pratt::{E, S},
+    span::Span,
+    tokenizer::OP,
+};
+
+/// The result type of blocks, `if`s and of `main`
+const I32: u8 = 0x7f;
+/// A block without a result
+const EMPTY: u8 = 0x40;
+
+// Section ids
+const TYPE: u8 = 1;
+const FUNCTION: u8 = 3;
+const EXPORT: u8 = 7;
+const CODE: u8 = 10;
+
+// The locals holding the operands and the result of an arithmetic
+// operation while it is checked for overflow
+const LEFT: u32 = 0;
+const RIGHT: u32 = 1;
+const RESULT: u32 = 2;
+/// The number of scratch locals, the locals of the program follow them
+const SCRATCH: u32 = 3;
+
+/// The WebAssembly instructions the compiler emits
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Instr {
+    Unreachable,
+    /// A block, `if` or loop, with whether it has an `i32` result
+    Block(bool),
+    Loop,
+    If(bool),
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Drop,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    I32Const(i32),
+    I32Eqz,
+    I32Eq,
+    I32LtS,
+    I32GtS,
+    I32LeS,
+    I32GeS,
+    I32Ne,
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I32DivS,
+    I32And,
+    I32Xor,
+    I32ShrU,
+}
+
+impl Instr {
+    fn encode(self, out: &mut Vec<u8>) {
+        let result = |i32| if i32 { I32 } else { EMPTY };
+        match self {
+            Instr::Unreachable => out.push(0x00),
+            Instr::Block(i32) => out.extend([0x02, result(i32)]),
+            Instr::Loop => out.extend([0x03, EMPTY]),
+            Instr::If(i32) => out.extend([0x04, result(i32)]),
+            Instr::Else => out.push(0x05),
+            Instr::End => out.push(0x0b),
+            Instr::Br(depth) => {
+                out.push(0x0c);
+                unsigned(out, depth);
+            }
+            Instr::BrIf(depth) => {
+                out.push(0x0d);
+                unsigned(out, depth);
+            }
+            Instr::Drop => out.push(0x1a),
+            Instr::LocalGet(i) => {
+                out.push(0x20);
+                unsigned(out, i);
+            }
+            Instr::LocalSet(i) => {
+                out.push(0x21);
+                unsigned(out, i);
+            }
+            Instr::LocalTee(i) => {
+                out.push(0x22);
+                unsigned(out, i);
+            }
+            Instr::I32Const(v) => {
+                out.push(0x41);
+                signed(out, v);
+            }
+            Instr::I32Eqz => out.push(0x45),
+            Instr::I32Eq => out.push(0x46),
+            Instr::I32LtS => out.push(0x48),
+            Instr::I32GtS => out.push(0x4a),
+            Instr::I32LeS => out.push(0x4c),
+            Instr::I32GeS => out.push(0x4e),
+            Instr::I32Ne => out.push(0x47),
+            Instr::I32Add => out.push(0x6a),
+            Instr::I32Sub => out.push(0x6b),
+            Instr::I32Mul => out.push(0x6c),
+            Instr::I32DivS => out.push(0x6d),
+            Instr::I32And => out.push(0x71),
+            Instr::I32Xor => out.push(0x73),
+            Instr::I32ShrU => out.push(0x76),
+        }
+    }
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let result = |i32: &bool| if *i32 { " (result i32)" } else { "" };
+        match self {
+            Instr::Unreachable => write!(f, "unreachable"),
+            Instr::Block(i32) => write!(f, "block{}", result(i32)),
+            Instr::Loop => write!(f, "loop"),
+            Instr::If(i32) => write!(f, "if{}", result(i32)),
+            Instr::Else => write!(f, "else"),
+            Instr::End => write!(f, "end"),
+            Instr::Br(depth) => write!(f, "br {}", depth),
+            Instr::BrIf(depth) => write!(f, "br_if {}", depth),
+            Instr::Drop => write!(f, "drop"),
+            Instr::LocalGet(i) => write!(f, "local.get {}", i),
+            Instr::LocalSet(i) => write!(f, "local.set {}", i),
+            Instr::LocalTee(i) => write!(f, "local.tee {}", i),
+            Instr::I32Const(v) => write!(f, "i32.const {}", v),
+            Instr::I32Eqz => write!(f, "i32.eqz"),
+            Instr::I32Eq => write!(f, "i32.eq"),
+            Instr::I32LtS => write!(f, "i32.lt_s"),
+            Instr::I32GtS => write!(f, "i32.gt_s"),
+            Instr::I32LeS => write!(f, "i32.le_s"),
+            Instr::I32GeS => write!(f, "i32.ge_s"),
+            Instr::I32Ne => write!(f, "i32.ne"),
+            Instr::I32Add => write!(f, "i32.add"),
+            Instr::I32Sub => write!(f, "i32.sub"),
+            Instr::I32Mul => write!(f, "i32.mul"),
+            Instr::I32DivS => write!(f, "i32.div_s"),
+            Instr::I32And => write!(f, "i32.and"),
+            Instr::I32Xor => write!(f, "i32.xor"),
+            Instr::I32ShrU => write!(f, "i32.shr_u"),
+        }
+    }
+}
+
+/// Unsigned LEB128
+fn unsigned(out: &mut Vec<u8>, mut v: u32) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Signed LEB128
+fn signed(out: &mut Vec<u8>, mut v: i32) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        // done when the rest is the sign extension of the byte
+        if (v == 0 && byte & 0x40 == 0) || (v == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// A module with one function, `main`, exported under that name. It takes
+/// no parameters and returns the value of the program as an `i32`, with
+/// booleans as 0 or 1 and unit as 0.
+#[derive(Debug)]
+pub struct Module {
+    /// The number of `i32` locals of the program, after the scratch ones
+    locals: u32,
+    body: Vec<Instr>,
+}
+
+impl Module {
+    /// The module in the binary format
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = b"\0asm".to_vec();
+        out.extend(1u32.to_le_bytes());
+
+        // one type, `[] -> [i32]`
+        section(&mut out, TYPE, &[1, 0x60, 0, 1, I32]);
+        // one function, of type 0
+        section(&mut out, FUNCTION, &[1, 0]);
+        // function 0 exported as `main`
+        let mut export = vec![1, 4];
+        export.extend(b"main");
+        export.extend([0x00, 0]);
+        section(&mut out, EXPORT, &export);
+
+        let mut body = vec![];
+        // one group of `i32` locals
+        body.push(1);
+        unsigned(&mut body, SCRATCH + self.locals);
+        body.push(I32);
+        for instr in &self.body {
+            instr.encode(&mut body);
+        }
+        Instr::End.encode(&mut body);
+        let mut code = vec![1];
+        unsigned(&mut code, body.len() as u32);
+        code.extend(body);
+        section(&mut out, CODE, &code);
+        out
+    }
+}
+
+fn section(out: &mut Vec<u8>, id: u8, content: &[u8]) {
+    out.push(id);
+    unsigned(out, content.len() as u32);
+    out.extend(content);
+}
+
+/// The module in the text format, with the body indented by its blocks
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "(module")?;
+        writeln!(f, "  (func (export \"main\") (result i32)")?;
+        let locals = (SCRATCH + self.locals) as usize;
+        writeln!(f, "    (local{})", " i32".repeat(locals))?;
+        let mut depth = 2;
+        for instr in &self.body {
+            if let Instr::Else | Instr::End = instr {
+                depth -= 1;
+            }
+            writeln!(f, "{}{}", "  ".repeat(depth), instr)?;
+            if let Instr::Block(_) | Instr::Loop | Instr::If(_) | Instr::Else = instr {
+                depth += 1;
+            }
+        }
+        writeln!(f, "  )")?;
+        write!(f, ")")
+    }
+}
+
+/// A block being compiled, the target of a `br` from inside it
+#[derive(PartialEq, Eq)]
+enum Label {
+    /// An `if` or `else` arm
+    Arm,
+    /// The block around a loop, which `break` leaves
+    Exit,
+    /// The loop itself, which `continue` goes back to
+    Loop,
+}
+
+struct Compiler {
+    body: Vec<Instr>,
+    labels: Vec<Label>,
+    /// Names of the `i32` locals in scope with their index
+    scope: Vec<(String, u32)>,
+    /// The `i32` locals in use, by names and by temporaries
+    locals: u32,
+    /// The most `i32` locals used at once
+    max: u32,
+}
+
+/// Compiles a program that passed the type checker to a WebAssembly
+/// module. Where the interpreter fails with a runtime error the module
+/// traps. `print` and functions are not supported.
+pub fn compile(program: &[S]) -> Result<Module, CodegenError> {
+    let mut c = Compiler {
+        body: vec![],
+        labels: vec![],
+        scope: vec![],
+        locals: 0,
+        max: 0,
+    };
+    c.statements(program)?;
+    Ok(Module {
+        locals: c.max,
+        body: c.body,
+    })
+}
+
+impl Compiler {
+    fn emit(&mut self, instrs: &[Instr]) {
+        self.body.extend(instrs);
+    }
+
+    /// Opens a block, closed with `end`
+    fn open(&mut self, instr: Instr, label: Label) {
+        self.emit(&[instr]);
+        self.labels.push(label);
+    }
+
+    fn end(&mut self) {
+        self.emit(&[Instr::End]);
+        self.labels.pop();
+    }
+
+    /// The depth of the innermost enclosing `label` for a `br`
+    fn depth(&self, label: Label) -> u32 {
+        let index = self.labels.iter().rposition(|l| *l == label).unwrap();
+        (self.labels.len() - 1 - index) as u32
+    }
+
+    /// Takes an `i32` local, freed with `self.locals -= 1`
+    fn local(&mut self) -> u32 {
+        let local = SCRATCH + self.locals;
+        self.locals += 1;
+        self.max = self.max.max(self.locals);
+        local
+    }
+
+    fn lookup(&self, name: &str) -> u32 {
+        self.scope.iter().rev().find(|(n, _)| n == name).unwrap().1
+    }
+
+    /// Statements in order, leaving the value of the last one
+    fn statements(&mut self, statements: &[S]) -> Result<(), CodegenError> {
+        let (scope, locals) = (self.scope.len(), self.locals);
+        let mut value = false;
+        for statement in statements {
+            if value {
+                self.emit(&[Instr::Drop]);
+            }
+            value = matches!(statement, S::EXPR(_));
+            self.statement(statement)?;
+        }
+        if !value {
+            self.emit(&[Instr::I32Const(0)]);
+        }
+        self.scope.truncate(scope);
+        self.locals = locals;
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &S) -> Result<(), CodegenError> {
+        match statement {
+            S::EXPR(e) => self.expression(e)?,
+            S::LET(name, value, _) | S::VAR(name, value, _) => {
+                self.expression(value)?;
+                let local = self.local();
+                self.emit(&[Instr::LocalSet(local)]);
+                self.scope.push((name.clone(), local));
+            }
+            S::ASSIGN(name, value, _) => {
+                self.expression(value)?;
+                let local = self.lookup(name);
+                self.emit(&[Instr::LocalSet(local)]);
+            }
+            S::WHILE(cond, body, _) => {
+                self.open(Instr::Block(false), Label::Exit);
+                self.open(Instr::Loop, Label::Loop);
+                self.expression(cond)?;
+                self.emit(&[Instr::I32Eqz, Instr::BrIf(1)]);
+                self.statements(body)?;
+                self.emit(&[Instr::Drop, Instr::Br(0)]);
+                self.end();
+                self.end();
+            }
+            // the type checker only accepts them inside loops
+            S::BREAK(_) => self.emit(&[Instr::Br(self.depth(Label::Exit))]),
+            S::CONTINUE(_) => self.emit(&[Instr::Br(self.depth(Label::Loop))]),
+            S::PRINT(_, span) => return unsupported("`print` statements", *span),
+            S::LETREC(_, _, span) => return unsupported("recursive bindings", *span),
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, e: &E) -> Result<(), CodegenError> {
+        match e {
+            E::LITERAL(v, _) => match i32::try_from(*v) {
+                Ok(v) => self.emit(&[Instr::I32Const(v)]),
+                Err(_) => self.emit(&[Instr::Unreachable]),
+            },
+            E::BOOL(b, _) => self.emit(&[Instr::I32Const(*b as i32)]),
+            E::PAREN(e, _) => self.expression(e)?,
+            // `-2147483648` is the negation of a literal that is out of range on its own
+            E::UNARY(OP::MINUS, v, _) if matches!(**v, E::LITERAL(2147483648, _)) => {
+                self.emit(&[Instr::I32Const(i32::MIN)])
+            }
+            E::UNARY(OP::MINUS, v, _) => {
+                self.emit(&[Instr::I32Const(0)]);
+                self.expression(v)?;
+                self.checked(&OP::MINUS);
+            }
+            E::UNARY(_, v, _) => self.expression(v)?,
+            E::BINARY(left, op, right, _) => {
+                self.expression(left)?;
+                self.expression(right)?;
+                match op {
+                    OP::PLUS | OP::MINUS | OP::MULT => self.checked(op),
+                    // traps on division by zero and on i32::MIN / -1
+                    OP::DIV => self.emit(&[Instr::I32DivS]),
+                    OP::POW => self.pow(),
+                    OP::EQUALITY => self.emit(&[Instr::I32Eq]),
+                    OP::GREATER => self.emit(&[Instr::I32GtS]),
+                    OP::LESS => self.emit(&[Instr::I32LtS]),
+                    OP::GEQ => self.emit(&[Instr::I32GeS]),
+                    OP::LEQ => self.emit(&[Instr::I32LeS]),
+                }
+            }
+            E::IF(cond, then, elze, _) => {
+                self.expression(cond)?;
+                self.open(Instr::If(true), Label::Arm);
+                self.expression(then)?;
+                self.emit(&[Instr::Else]);
+                self.expression(elze)?;
+                self.end();
+            }
+            E::LET(name, value, body, _) => {
+                self.expression(value)?;
+                let local = self.local();
+                self.emit(&[Instr::LocalSet(local)]);
+                self.scope.push((name.clone(), local));
+                self.expression(body)?;
+                self.scope.pop();
+                self.locals -= 1;
+            }
+            E::VAR(name, _) => self.emit(&[Instr::LocalGet(self.lookup(name))]),
+            E::BLOCK(statements, _) => self.statements(statements)?,
+            E::LETREC(_, _, _, span) => return unsupported("recursive bindings", *span),
+            E::FN(_, _, span) => return unsupported("functions", *span),
+            E::CALL(_, _, span) => return unsupported("function calls", *span),
+        }
+        Ok(())
+    }
+
+    /// `+`, `-` or `*` of the two `i32`s on top with the `i32` opcode,
+    /// trapping if the result wraps around
+    fn checked(&mut self, op: &OP) {
+        let instr = match op {
+            OP::PLUS => Instr::I32Add,
+            OP::MINUS => Instr::I32Sub,
+            _ => Instr::I32Mul,
+        };
+        self.emit(&[
+            Instr::LocalSet(RIGHT),
+            Instr::LocalTee(LEFT),
+            Instr::LocalGet(RIGHT),
+            instr,
+            Instr::LocalSet(RESULT),
+        ]);
+        match op {
+            // the sign of the result differs from the signs of both operands
+            OP::PLUS => self.emit(&[
+                Instr::LocalGet(LEFT),
+                Instr::LocalGet(RESULT),
+                Instr::I32Xor,
+                Instr::LocalGet(RIGHT),
+                Instr::LocalGet(RESULT),
+                Instr::I32Xor,
+                Instr::I32And,
+                Instr::I32Const(0),
+                Instr::I32LtS,
+            ]),
+            // the operands have different signs and the result has the
+            // sign of the right one
+            OP::MINUS => self.emit(&[
+                Instr::LocalGet(LEFT),
+                Instr::LocalGet(RIGHT),
+                Instr::I32Xor,
+                Instr::LocalGet(LEFT),
+                Instr::LocalGet(RESULT),
+                Instr::I32Xor,
+                Instr::I32And,
+                Instr::I32Const(0),
+                Instr::I32LtS,
+            ]),
+            // dividing the result by a non-zero left operand does not give
+            // back the right one. The division itself traps for
+            // `-1 * i32::MIN`, the one case where it overflows.
+            _ => self.emit(&[
+                Instr::LocalGet(LEFT),
+                Instr::If(true),
+                Instr::LocalGet(RESULT),
+                Instr::LocalGet(LEFT),
+                Instr::I32DivS,
+                Instr::LocalGet(RIGHT),
+                Instr::I32Ne,
+                Instr::Else,
+                Instr::I32Const(0),
+                Instr::End,
+            ]),
+        }
+        self.emit(&[
+            Instr::If(false),
+            Instr::Unreachable,
+            Instr::End,
+            Instr::LocalGet(RESULT),
+        ]);
+    }
+
+    /// `a * b` of two `i32` locals, checked
+    fn multiply(&mut self, a: u32, b: u32) {
+        self.emit(&[Instr::LocalGet(a), Instr::LocalGet(b)]);
+        self.checked(&OP::MULT);
+    }
+
+    /// The base below the exponent on top to its power, by squaring like
+    /// `i32::checked_pow` so it only traps if the result overflows
+    fn pow(&mut self) {
+        let (base, exp, acc) = (self.local(), self.local(), self.local());
+        self.emit(&[
+            Instr::LocalSet(exp),
+            Instr::LocalSet(base),
+            // a negative exponent
+            Instr::LocalGet(exp),
+            Instr::I32Const(0),
+            Instr::I32LtS,
+            Instr::If(false),
+            Instr::Unreachable,
+            Instr::End,
+            Instr::I32Const(1),
+            Instr::LocalSet(acc),
+            Instr::LocalGet(exp),
+            Instr::If(false),
+            Instr::Loop,
+            Instr::LocalGet(exp),
+            Instr::I32Const(1),
+            Instr::I32GtS,
+            Instr::If(false),
+            Instr::LocalGet(exp),
+            Instr::I32Const(1),
+            Instr::I32And,
+            Instr::If(false),
+        ]);
+        self.multiply(acc, base);
+        self.emit(&[
+            Instr::LocalSet(acc),
+            Instr::End,
+            Instr::LocalGet(exp),
+            Instr::I32Const(1),
+            Instr::I32ShrU,
+            Instr::LocalSet(exp),
+        ]);
+        self.multiply(base, base);
+        // back to the loop
+        self.emit(&[Instr::LocalSet(base), Instr::Br(1), Instr::End, Instr::End]);
+        self.multiply(acc, base);
+        self.emit(&[Instr::LocalSet(acc), Instr::End, Instr::LocalGet(acc)]);
+        self.locals -= 3;
+    }
+}
+
+fn unsupported<T>(construct: &'static str, span: Span) -> Result<T, CodegenError> {
+    Err(CodegenError {
+        construct,
+        backend: "WebAssembly",
+        span,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pratt::program, tokenizer::Tokenizer};
+
+    use super::*;
+
+    fn compile_source(input: &str) -> Result<Module, CodegenError> {
+        let tokens = Tokenizer::new(input).run().unwrap();
+        compile(&program(&tokens).unwrap())
+    }
+
+    /// The ids and contents of the sections of a module
+    fn sections(module: &[u8]) -> Vec<(u8, &[u8])> {
+        assert_eq!(b"\0asm\x01\0\0\0", &module[..8]);
+        let mut sections = vec![];
+        let mut rest = &module[8..];
+        while let [id, tail @ ..] = rest {
+            // sizes in these tests fit in one byte
+            let size = tail[0] as usize;
+            assert!(size < 0x80);
+            sections.push((*id, &tail[1..1 + size]));
+            rest = &tail[1 + size..];
+        }
+        sections
+    }
+
+    #[test]
+    fn module() {
+        let bytes = compile_source("42").unwrap().encode();
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            // type: [] -> [i32]
+            0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f,
+            // function: type 0
+            0x03, 0x02, 0x01, 0x00,
+            // export: "main", function 0
+            0x07, 0x08, 0x01, 0x04, b'm', b'a', b'i', b'n', 0x00, 0x00,
+            // code: one body of 6 bytes, three i32 locals, i32.const 42, end
+            0x0a, 0x08, 0x01, 0x06, 0x01, 0x03, 0x7f, 0x41, 0x2a, 0x0b,
+        ];
+        assert_eq!(expected, bytes);
+    }
+
+    #[test]
+    fn leb128() {
+        let encode = |v: u32| {
+            let mut out = vec![];
+            unsigned(&mut out, v);
+            out
+        };
+        assert_eq!(vec![0x00], encode(0));
+        assert_eq!(vec![0x7f], encode(127));
+        assert_eq!(vec![0x80, 0x01], encode(128));
+        assert_eq!(vec![0xe5, 0x8e, 0x26], encode(624485));
+        let encode = |v: i32| {
+            let mut out = vec![];
+            signed(&mut out, v);
+            out
+        };
+        assert_eq!(vec![0x3f], encode(63));
+        assert_eq!(vec![0xc0, 0x00], encode(64));
+        assert_eq!(vec![0x7f], encode(-1));
+        assert_eq!(vec![0x40], encode(-64));
+        assert_eq!(vec![0xbf, 0x7f], encode(-65));
+        assert_eq!(vec![0x80, 0x80, 0x80, 0x80, 0x78], encode(i32::MIN));
+    }
+
+    #[test]
+    fn operators() {
+        let module = compile_source("if 1 < 2 | 10 / 5 else 3").unwrap();
+        let bytes = module.encode();
+        let sections = sections(&bytes);
+        let ids: Vec<u8> = sections.iter().map(|(id, _)| *id).collect();
+        assert_eq!(vec![TYPE, FUNCTION, EXPORT, CODE], ids);
+        let code = sections[3].1;
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            0x01, 0x14, 0x01, 0x03, 0x7f,
+            0x41, 0x01, 0x41, 0x02, 0x48, // i32.lt_s
+            0x04, 0x7f, // if (result i32)
+            0x41, 0x0a, 0x41, 0x05, 0x6d, // i32.div_s
+            0x05, 0x41, 0x03, // else
+            0x0b, 0x0b,
+        ];
+        assert_eq!(expected, code);
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let text = compile_source("1 + 2").unwrap().to_string();
+        let expected = "\
+(module
+  (func (export \"main\") (result i32)
+    (local i32 i32 i32)
+    i32.const 1
+    i32.const 2
+    local.set 1
+    local.tee 0
+    local.get 1
+    i32.add
+    local.set 2
+    local.get 0
+    local.get 2
+    i32.xor
+    local.get 1
+    local.get 2
+    i32.xor
+    i32.and
+    i32.const 0
+    i32.lt_s
+    if
+      unreachable
+    end
+    local.get 2
+  )
+)";
+        assert_eq!(expected, text);
+
+        let text = compile_source("3 - 2 * 1").unwrap().to_string();
+        assert!(text.contains("    i32.sub\n"), "{}", text);
+        assert!(
+            text.contains("    i32.mul\n    local.set 2\n    local.get 0\n    if (result i32)\n      local.get 2\n      local.get 0\n      i32.div_s\n      local.get 1\n      i32.ne\n"),
+            "{}",
+            text
+        );
+        assert!(!text.contains("i64"), "{}", text);
+    }
+
+    #[test]
+    fn loops() {
+        let module = compile_source(
+            "var i = 0; while true { i = i + 1; if i > 3 | { break; } else { continue; } }; i",
+        )
+        .unwrap();
+        assert_eq!(1, module.locals);
+        let text = module.to_string();
+        // `break` leaves the block around the loop from inside the `if`,
+        // `continue` goes back to the loop from the `else`
+        assert!(
+            text.contains("        if (result i32)\n          br 2\n"),
+            "{}",
+            text
+        );
+        assert!(text.contains("        else\n          br 1\n"), "{}", text);
+        assert!(text.ends_with("    local.get 3\n  )\n)"), "{}", text);
+    }
+
+    #[test]
+    fn text_format() {
+        let text = compile_source("let x = 3; -x").unwrap().to_string();
+        assert!(
+            text.starts_with(
+                "(module\n  (func (export \"main\") (result i32)\n    (local i32 i32 i32 i32)\n    i32.const 3\n    local.set 3\n"
+            ),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        let e = compile_source("print 1").unwrap_err();
+        assert_eq!(
+            "`print` statements are not supported by the WebAssembly backend",
+            e.to_string()
+        );
+        assert_eq!(
+            "functions",
+            compile_source("fn x => x").unwrap_err().construct
+        );
+    }
+}